@@ -100,7 +100,36 @@ pub fn format_decimal_18(input: impl AsRef<str>) -> String {
     }
 }
 
-/// Converts a u128 number to a decimal string with 18 decimal places
-pub fn format_u128_to_decimal_18(amount: u128, divisor: u128) -> String {
-    format!("{}.000000000000000000", amount / divisor)
+/// Divides the unsigned integer string `input` by 10^`decimals` exactly, keeping every decimal place. Works on numbers
+/// of any length, like the Dec amounts the Cosmos SDK encodes as integers scaled by 10^18. None if `input` is not an
+/// unsigned integer
+pub fn shift_decimal(input: &str, decimals: u32) -> Option<String> {
+    if input.is_empty() || !input.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let decimals = decimals as usize;
+    let digits = input.trim_start_matches('0');
+    let padded = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    if fraction.is_empty() {
+        Some(whole.to_string())
+    } else {
+        Some(format!("{}.{}", whole, fraction))
+    }
+}
+
+#[test]
+fn test_shift_decimal() {
+    assert_eq!(shift_decimal("1500", 3).unwrap(), "1.500");
+    assert_eq!(shift_decimal("15", 3).unwrap(), "0.015");
+    assert_eq!(shift_decimal("0", 2).unwrap(), "0.00");
+    assert_eq!(shift_decimal("0042", 0).unwrap(), "42");
+    // Longer than a u128
+    assert_eq!(
+        shift_decimal("1234567890123456789012345678901234567890123", 36).unwrap(),
+        "1234567.890123456789012345678901234567890123"
+    );
+    assert!(shift_decimal("-1", 2).is_none());
+    assert!(shift_decimal("1.5", 2).is_none());
+    assert!(shift_decimal("", 2).is_none());
 }
@@ -208,3 +208,102 @@ pub async fn get_template(
         oracle_flags,
     })
 }
+
+/// The liquidity and token quantities returned by the CrocQuery position queries
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct PositionTokens {
+    pub liq: u128,
+    pub base_qty: u128,
+    pub quote_qty: u128,
+}
+
+impl PositionTokens {
+    pub fn from_abi(input: &[u8]) -> Self {
+        Self {
+            liq: parse_u128(input, 0),
+            base_qty: parse_u128(input, 32),
+            quote_qty: parse_u128(input, 64),
+        }
+    }
+}
+
+// @notice Queries the rewards accumulated for a concentrated liquidity position.
+// @param owner The owner of the liquidity position.
+// @param base The base token address
+// @param quote The quote token address
+// @param poolIdx The pool index
+// @param lowerTick The lower tick of the position
+// @param upperTick The upper tick of the position
+// @return liqRewards The net accumulated liquidity rewards for the position
+// @return baseRewards The net accumulated base token rewards for the position
+// @return quoteRewards The net accumulated quote token rewards for the position
+// function queryConcRewards (address owner, address base, address quote, uint256 poolIdx,
+//                            int24 lowerTick, int24 upperTick)
+//     public view returns (uint128 liqRewards, uint128 baseRewards, uint128 quoteRewards) {
+pub const QUERY_CONC_REWARDS_SIG: &str =
+    "queryConcRewards(address,address,address,uint256,int24,int24)";
+#[allow(clippy::too_many_arguments)]
+pub async fn get_conc_rewards(
+    web30: &Web3,
+    croc_query: Address,
+    owner: Address,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    lower_tick: i32,
+    upper_tick: i32,
+) -> Result<PositionTokens, AltheaError> {
+    let rewards_res = web30
+        .simulate_transaction(
+            TransactionRequest::quick_tx(
+                Address::from_str(DEFAULT_QUERIER).unwrap(),
+                croc_query,
+                encode_call(
+                    QUERY_CONC_REWARDS_SIG,
+                    &[
+                        owner.into(),
+                        base.into(),
+                        quote.into(),
+                        pool_idx.into(),
+                        lower_tick.into(),
+                        upper_tick.into(),
+                    ],
+                )?,
+            ),
+            None,
+        )
+        .await?;
+    Ok(PositionTokens::from_abi(&rewards_res))
+}
+
+// @notice Queries the current liquidity and token quantities of an ambient position, including compounded rewards.
+// @param owner The owner of the liquidity position.
+// @param base The base token address
+// @param quote The quote token address
+// @param poolIdx The pool index
+// function queryAmbientTokens (address owner, address base, address quote, uint256 poolIdx)
+//     public view returns (uint128 liq, uint128 baseQty, uint128 quoteQty) {
+pub const QUERY_AMBIENT_TOKENS_SIG: &str = "queryAmbientTokens(address,address,address,uint256)";
+pub async fn get_ambient_tokens(
+    web30: &Web3,
+    croc_query: Address,
+    owner: Address,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> Result<PositionTokens, AltheaError> {
+    let tokens_res = web30
+        .simulate_transaction(
+            TransactionRequest::quick_tx(
+                Address::from_str(DEFAULT_QUERIER).unwrap(),
+                croc_query,
+                encode_call(
+                    QUERY_AMBIENT_TOKENS_SIG,
+                    &[owner.into(), base.into(), quote.into(), pool_idx.into()],
+                )?,
+            ),
+            None,
+        )
+        .await?;
+    Ok(PositionTokens::from_abi(&tokens_res))
+}
@@ -1,8 +1,8 @@
-/// The following are signatures for events emitted by the Ambient contract.
-/// Several of these have been added to only the AltheaFoundation fork of Ambient,
-/// all of them have been marked with a NOTE at the beginning of their comments.
-/// When parsing events, any `indexed` values are stored in topics[1], [2], or [3] and all other
-/// values are stored in the data field of the log according to ABI encoding rules.
+// The following are signatures for events emitted by the Ambient contract.
+// Several of these have been added to only the AltheaFoundation fork of Ambient,
+// all of them have been marked with a NOTE at the beginning of their comments.
+// When parsing events, any `indexed` values are stored in topics[1], [2], or [3] and all other
+// values are stored in the data field of the log according to ABI encoding rules.
 
 /// NOTE: This event was added to the AltheaFoundation fork of Ambient
 /// @notice Emitted whenever a swap is performed, exchanging buy tokens for sell tokens.
@@ -9,8 +9,11 @@ use events::{
 };
 use futures::future::join_all;
 use futures::join;
+use futures::stream::{self, StreamExt};
+use itertools::Itertools;
 use knockout::{BurnKnockoutEvent, MintKnockoutEvent, WithdrawKnockoutEvent};
 use log::{debug, info};
+use num_traits::ToPrimitive;
 use pools::InitPoolEvent;
 use positions::{
     BurnAmbientEvent, BurnRangedEvent, HarvestEvent, MintAmbientEvent, MintRangedEvent,
@@ -23,19 +26,23 @@ use crate::althea::{
         pools::{save_init_pool, save_swap},
         positions::{
             ambient::{save_burn_ambient, save_mint_ambient},
+            index::apply_position_events,
             knockout::{save_burn_knockout, save_mint_knockout, save_withdraw_knockout},
             ranged::{save_burn_ranged, save_harvest, save_mint_ranged},
+            PositionEvent,
         },
         tracking::{mark_pool_dirty, set_dirty_pool, update_pool},
+        transactions::{save_pool_tx, Tx},
     },
     error,
 };
 
 use super::{
     database::{
+        blocks::{first_sampled_block, save_block_time},
         curve::{get_curve, get_liquidity, get_price, save_curve, save_liquidity, save_price},
         pools::{
-            get_all_revision_after_block, get_all_swap_after_block, get_init_pool,
+            get_all_revision_after_block, get_all_swap_after_block, get_init_pool, get_init_pools,
             get_pool_template, save_pool_template,
         },
         positions::{
@@ -49,6 +56,7 @@ use super::{
                 get_all_mint_ranged_after_block,
             },
         },
+        tokens::{get_token_metadata, save_token_metadata, TokenMetadata},
         tracking::{get_all_dirty_pools, updates::PoolUpdateEvent, DirtyPoolTracker},
     },
     error::AltheaError,
@@ -59,6 +67,8 @@ pub mod events;
 pub mod knockout;
 pub mod pools;
 pub mod positions;
+pub mod quote;
+pub mod router;
 pub mod swap;
 
 // Searches for all the pool events needed for tracking including swapping, minting, and burning among others.
@@ -277,6 +287,9 @@ pub async fn search_for_pool_events(
         return Ok(());
     }
 
+    // The events which change users' positions, applied to the active position index once all events are saved
+    let mut position_events: Vec<PositionEvent> = vec![];
+
     for event in init_events {
         debug!("Writing {event:?} to database");
         set_dirty_pool(
@@ -293,48 +306,66 @@ pub async fn search_for_pool_events(
         debug!("Writing {event:?} to database");
 
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_swap(db, event);
     }
     for event in mint_ranged_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_mint_ranged(db, event);
     }
     for event in mint_ambient_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_mint_ambient(db, event);
     }
     for event in burn_ranged_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_burn_ranged(db, event);
     }
     for event in burn_ambient_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_burn_ambient(db, event);
     }
     for event in harvest_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_harvest(db, event);
     }
     for event in mint_knockout_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_mint_knockout(db, event);
     }
     for event in burn_knockout_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_burn_knockout(db, event);
     }
     for event in withdraw_knockout_events {
         debug!("Writing {event:?} to database");
         mark_pool_dirty(db, event.base, event.quote, event.pool_idx);
+        position_events.push(event.clone().into());
+        save_pool_tx(db, &Tx::from(event.clone()));
         save_withdraw_knockout(db, event);
     }
+    apply_position_events(db, position_events);
     Ok(())
 }
 
@@ -362,16 +393,25 @@ pub fn track_pool(db: &Arc<rocksdb::DB>, pool: DirtyPoolTracker) -> Result<(), A
     }
 
     // Now handle anything else
-    let mint_ambient = get_all_mint_ambient_after_block(db, None, pool.last_block);
-    let burn_ambient = get_all_burn_ambient_after_block(db, None, pool.last_block);
-    let mint_ranged = get_all_mint_ranged_after_block(db, None, pool.last_block);
-    let burn_ranged = get_all_burn_ranged_after_block(db, None, pool.last_block);
-    let harvest = get_all_harvest_after_block(db, None, pool.last_block);
-    let mint_knockout = get_all_mint_knockout_after_block(db, None, pool.last_block);
-    let burn_knockout = get_all_burn_knockout_after_block(db, None, pool.last_block);
-    let withdraw_knockout = get_all_withdraw_knockout_after_block(db, None, pool.last_block);
-    let swap = get_all_swap_after_block(db, None, pool.last_block);
-    let revision = get_all_revision_after_block(db, None, pool.last_block);
+    for update in get_pool_updates_after_block(db, pool.last_block) {
+        update_pool(db, update);
+    }
+
+    Ok(())
+}
+
+/// Every stored event after `block` as a pool update, in the order they happened
+fn get_pool_updates_after_block(db: &Arc<rocksdb::DB>, block: Uint256) -> Vec<PoolUpdateEvent> {
+    let mint_ambient = get_all_mint_ambient_after_block(db, None, block);
+    let burn_ambient = get_all_burn_ambient_after_block(db, None, block);
+    let mint_ranged = get_all_mint_ranged_after_block(db, None, block);
+    let burn_ranged = get_all_burn_ranged_after_block(db, None, block);
+    let harvest = get_all_harvest_after_block(db, None, block);
+    let mint_knockout = get_all_mint_knockout_after_block(db, None, block);
+    let burn_knockout = get_all_burn_knockout_after_block(db, None, block);
+    let withdraw_knockout = get_all_withdraw_knockout_after_block(db, None, block);
+    let swap = get_all_swap_after_block(db, None, block);
+    let revision = get_all_revision_after_block(db, None, block);
 
     // Sort the events by block number and index and apply them in order
     let mut updates: Vec<PoolUpdateEvent> = mint_ambient
@@ -388,12 +428,7 @@ pub fn track_pool(db: &Arc<rocksdb::DB>, pool: DirtyPoolTracker) -> Result<(), A
         .chain(revision.into_iter().map(PoolUpdateEvent::from))
         .collect();
     updates.sort_by_key(|v| (v.block, v.index));
-
-    for update in updates {
-        update_pool(db, update);
-    }
-
-    Ok(())
+    updates
 }
 
 /// Generates a list of possible pools we may want to track based on the tokens and templates
@@ -474,6 +509,108 @@ pub async fn query_pool(
     Ok(())
 }
 
+/// Samples the timestamps of `blocks` so that the events within a searched range can be placed in time
+pub async fn record_block_times(
+    db: &Arc<rocksdb::DB>,
+    web30: &Web3,
+    blocks: &[Uint256],
+) -> Result<(), AltheaError> {
+    for block in blocks {
+        let header = web30.eth_get_block_by_number(*block).await?;
+        save_block_time(db, *block, header.timestamp.to_u64().unwrap_or_default());
+    }
+    Ok(())
+}
+
+/// How many block headers are requested at once while backfilling block times
+const BLOCK_TIME_BACKFILL_CONCURRENCY: usize = 16;
+
+/// Samples the time of every event block before the first sampled block. Databases indexed before block times were
+/// recorded only have samples from then on, which leaves their earlier events without history or candles. Returns the
+/// number of blocks sampled, pools need to be retracked to build their history from them
+pub async fn backfill_block_times(
+    db: &Arc<rocksdb::DB>,
+    web30: &Web3,
+) -> Result<usize, AltheaError> {
+    let first_sampled = first_sampled_block(db).unwrap_or(u64::MAX);
+    // Every pool starts with its InitPool, so when they all have times there is nothing older to backfill
+    let earliest_init = get_init_pools(db)
+        .iter()
+        .filter_map(|p| p.block_height.to_u64())
+        .min();
+    if earliest_init.is_none_or(|b| b >= first_sampled) {
+        return Ok(0);
+    }
+
+    let blocks = get_init_pools(db)
+        .into_iter()
+        .map(PoolUpdateEvent::from)
+        .chain(get_pool_updates_after_block(db, Uint256::default()))
+        .map(|u| u.block)
+        .filter(|b| b.to_u64().is_some_and(|b| b < first_sampled))
+        .unique()
+        .collect::<Vec<_>>();
+    info!(
+        "Backfilling the times of {} blocks before block {}",
+        blocks.len(),
+        first_sampled
+    );
+    let headers = stream::iter(blocks)
+        .map(|block| async move {
+            let header = web30.eth_get_block_by_number(block).await?;
+            Ok::<_, AltheaError>((block, header.timestamp))
+        })
+        .buffer_unordered(BLOCK_TIME_BACKFILL_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    let mut sampled = 0;
+    for header in headers {
+        let (block, timestamp) = header?;
+        save_block_time(db, block, timestamp.to_u64().unwrap_or_default());
+        sampled += 1;
+    }
+    Ok(sampled)
+}
+
+/// Fetches the ERC20 metadata of any token in `pools` which has not been seen before. A token whose metadata can not be
+/// fetched is logged and tried again on the next call, without holding up the other tokens
+pub async fn query_token_metadata(
+    db: &Arc<rocksdb::DB>,
+    web30: &Web3,
+    pools: &[(Address, Address, Uint256)],
+) {
+    let tokens = pools
+        .iter()
+        .flat_map(|(base, quote, _)| [*base, *quote])
+        .unique()
+        .filter(|token| get_token_metadata(db, *token).is_none())
+        .collect::<Vec<_>>();
+    for token in tokens {
+        match fetch_token_metadata(web30, token).await {
+            Ok(metadata) => save_token_metadata(db, metadata),
+            Err(e) => error!("Error querying metadata of token {}: {}", token, e),
+        }
+    }
+}
+
+async fn fetch_token_metadata(web30: &Web3, token: Address) -> Result<TokenMetadata, AltheaError> {
+    let caller = Address::default();
+    let (name, symbol, decimals) = join!(
+        web30.get_erc20_name(token, caller),
+        web30.get_erc20_symbol(token, caller),
+        web30.get_erc20_decimals(token, caller)
+    );
+    let decimals = decimals?.to_u8().ok_or_else(|| {
+        AltheaError::InvalidResponseError(format!("Invalid decimals for token {}", token))
+    })?;
+    Ok(TokenMetadata {
+        address: token,
+        name: name?,
+        symbol: symbol?,
+        decimals,
+    })
+}
+
 /// Initializes the pool template data in the database so that we can populate pool specs from InitPool events
 pub async fn initialize_templates(
     db: &Arc<rocksdb::DB>,
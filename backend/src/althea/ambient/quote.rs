@@ -0,0 +1,221 @@
+// This file simulates swaps against a pool's liquidity curve to quote their output without touching the chain
+
+use clarity::{Address, Uint256};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::althea::database::{
+    curve::{get_liquidity, get_root_price},
+    storage::Storage,
+    tracking::{get_tracked_pool, LiquidityBump},
+};
+
+/// The result of simulating a swap, all quantities are in the tokens' smallest units
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SwapQuote {
+    pub input: f64,
+    pub output: f64,
+    // The input consumed by fees
+    pub fees: f64,
+    // Output per unit of input at the pool's price before the swap, and over the whole swap
+    pub spot_price: f64,
+    pub execution_price: f64,
+    // The fraction by which the execution price is worse than the spot price, e.g. 0.01 for 1%
+    pub price_impact: f64,
+    // The pool's price (base per quote) once the swap is done
+    pub final_price: f64,
+    // False if the curve ran out of liquidity before the whole input could be swapped
+    pub filled: bool,
+}
+
+/// Quotes a swap of `qty` against a stored pool, `is_buy` is true when paying base for quote (raising the price).
+/// Returns None if the pool's price or liquidity are unknown.
+pub fn quote_pool_swap(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    is_buy: bool,
+    qty: f64,
+) -> Option<SwapQuote> {
+    let pool = get_tracked_pool(db, base, quote, pool_idx)?;
+    let root_price = get_root_price(db, base, quote, pool_idx)?;
+    let liquidity = get_liquidity(db, base, quote, pool_idx)?;
+    Some(quote_swap(
+        root_price,
+        liquidity.to_f64()?,
+        &pool.bumps,
+        pool.fee_rate,
+        is_buy,
+        qty,
+    ))
+}
+
+/// Walks the liquidity curve from `root_price` (the square root of the base per quote price) with `liquidity` active,
+/// crossing `bumps` (sorted by tick) as the price moves. `fee_rate` is a fraction taken from the input each range of
+/// liquidity consumes, so a partially filled swap only pays fees on the input it used.
+pub fn quote_swap(
+    root_price: f64,
+    liquidity: f64,
+    bumps: &[LiquidityBump],
+    fee_rate: f64,
+    is_buy: bool,
+    qty: f64,
+) -> SwapQuote {
+    let mut remaining = qty;
+    let mut fees = 0.0;
+    let mut output = 0.0;
+    let mut root = root_price;
+    let mut liq = liquidity;
+
+    // Bumps in the order the price will reach them
+    let mut crossings: Vec<(f64, f64)> = bumps
+        .iter()
+        .map(|b| (tick_root_price(b.tick), b.liquidity_delta))
+        .filter(|(r, _)| if is_buy { *r > root } else { *r <= root })
+        .collect();
+    if !is_buy {
+        crossings.reverse();
+    }
+    let mut crossings = crossings.into_iter();
+
+    while remaining > 0.0 {
+        let next = crossings.next();
+        if liq <= 0.0 {
+            // Nothing is swapped in a gap between positions, the price moves straight to the next bump
+            match next {
+                Some((target, delta)) => {
+                    root = target;
+                    liq = crossed_liquidity(liq, delta, is_buy);
+                    continue;
+                }
+                None => break,
+            }
+        }
+        // The input needed to move the price to the next bump (or an unbounded amount when there are none left), before
+        // and after the fee taken from it
+        let needed = match next {
+            Some((target, _)) if is_buy => liq * (target - root),
+            Some((target, _)) => liq * (1.0 / target - 1.0 / root),
+            None => f64::INFINITY,
+        };
+        let needed_with_fee = needed / (1.0 - fee_rate);
+        if remaining <= needed_with_fee {
+            let swapped = remaining * (1.0 - fee_rate);
+            let new_root = if is_buy {
+                root + swapped / liq
+            } else {
+                1.0 / (1.0 / root + swapped / liq)
+            };
+            output += swap_output(liq, root, new_root, is_buy);
+            fees += remaining - swapped;
+            root = new_root;
+            remaining = 0.0;
+            break;
+        }
+        let (target, delta) = next.unwrap();
+        output += swap_output(liq, root, target, is_buy);
+        fees += needed_with_fee - needed;
+        remaining -= needed_with_fee;
+        root = target;
+        liq = crossed_liquidity(liq, delta, is_buy);
+    }
+
+    let input = qty - remaining;
+    let spot_price = if is_buy {
+        1.0 / (root_price * root_price)
+    } else {
+        root_price * root_price
+    };
+    let execution_price = if input > 0.0 { output / input } else { 0.0 };
+    let price_impact = if spot_price > 0.0 && input > 0.0 {
+        1.0 - execution_price / spot_price
+    } else {
+        0.0
+    };
+    SwapQuote {
+        input,
+        output,
+        fees,
+        spot_price,
+        execution_price,
+        price_impact,
+        final_price: root * root,
+        filled: remaining <= 0.0,
+    }
+}
+
+// Bumps add liquidity when crossed upwards and remove it when crossed downwards
+fn crossed_liquidity(liq: f64, delta: f64, is_buy: bool) -> f64 {
+    if is_buy {
+        liq + delta
+    } else {
+        liq - delta
+    }
+}
+
+// The output of moving the price from `root` to `new_root` with `liq` active, quote for buys and base for sells
+fn swap_output(liq: f64, root: f64, new_root: f64, is_buy: bool) -> f64 {
+    if is_buy {
+        liq * (1.0 / root - 1.0 / new_root)
+    } else {
+        liq * (root - new_root)
+    }
+}
+
+fn tick_root_price(tick: i32) -> f64 {
+    1.0001f64.powf(tick as f64).sqrt()
+}
+
+#[test]
+fn test_quote_swap() {
+    // Without bumps a small swap executes at close to the spot price
+    let quote = quote_swap(1.0, 1e18, &[], 0.0, true, 1e12);
+    assert!(quote.filled);
+    assert!((quote.output - 1e12).abs() / 1e12 < 1e-5);
+    assert!(quote.price_impact.abs() < 1e-5);
+
+    // Fees are removed from the input
+    let quote = quote_swap(1.0, 1e18, &[], 0.01, false, 1e12);
+    assert!((quote.fees - 1e10).abs() < 1.0);
+    assert!((quote.output - 0.99e12).abs() / 1e12 < 1e-5);
+
+    // A position ending just above the price drops out once crossed, so a large buy sees more impact
+    let ask = LiquidityBump {
+        tick: 100,
+        liquidity_delta: -0.5e6,
+        ..Default::default()
+    };
+    let with_bump = quote_swap(1.0, 1e6, &[ask], 0.0, true, 1e4);
+    let without_bump = quote_swap(1.0, 1e6, &[], 0.0, true, 1e4);
+    assert!(with_bump.filled);
+    assert!(with_bump.output < without_bump.output);
+    assert!(with_bump.price_impact > without_bump.price_impact);
+    assert!(with_bump.final_price > without_bump.final_price);
+
+    // Running out of liquidity leaves the swap partially filled
+    let empty = [LiquidityBump {
+        tick: -100,
+        liquidity_delta: 1e6,
+        ..Default::default()
+    }];
+    let quote = quote_swap(1.0, 1e6, &empty, 0.0, false, 1e5);
+    assert!(!quote.filled);
+    assert!(quote.input < 1e5);
+    // Fees are only taken from the input which was swapped
+    let with_fee = quote_swap(1.0, 1e6, &empty, 0.01, false, 1e5);
+    assert!(!with_fee.filled);
+    assert!((with_fee.fees - with_fee.input * 0.01).abs() < 1e-6);
+    assert!((with_fee.output - quote.output).abs() < 1e-6);
+
+    // A gap without liquidity is skipped, the swap continues in the next position below it
+    let below = LiquidityBump {
+        tick: -200,
+        liquidity_delta: -1e6,
+        ..Default::default()
+    };
+    let quote = quote_swap(1.0, 1e6, &[below, empty[0].clone()], 0.0, false, 1e5);
+    assert!(quote.filled);
+    assert!(quote.input == 1e5);
+    assert!(quote.final_price < tick_root_price(-200).powi(2));
+}
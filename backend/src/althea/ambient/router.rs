@@ -0,0 +1,340 @@
+// This file finds the best path for a trade through the indexed pools, chaining swap quotes across up to three hops
+
+use clarity::{Address, Uint256};
+use serde::{Deserialize, Serialize};
+
+use crate::althea::database::{
+    pools::get_init_pools, storage::Storage, tokens::get_token_metadata,
+};
+
+use super::quote::{quote_pool_swap, SwapQuote};
+
+/// The most pools a single route may pass through
+pub const MAX_HOPS: usize = 3;
+
+/// A pool which can be traded through, identified by its (base, quote, pool index) triple
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolEdge {
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+}
+
+impl PoolEdge {
+    // The token received when paying `token` into this pool
+    fn other(&self, token: Address) -> Option<Address> {
+        if token == self.base {
+            Some(self.quote)
+        } else if token == self.quote {
+            Some(self.base)
+        } else {
+            None
+        }
+    }
+}
+
+/// A single swap along a route, paying `from` into `pool` to receive `to`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RouteHop {
+    pub from: Address,
+    pub to: Address,
+    pub pool: PoolEdge,
+    pub quote: SwapQuote,
+}
+
+/// The result of chaining swap quotes along a route, the output of each hop is the input to the next
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct RouteQuote {
+    pub hops: Vec<RouteHop>,
+    pub input: f64,
+    pub output: f64,
+    // Output per unit of input at the pools' prices before the swap, and over the whole route
+    pub spot_price: f64,
+    pub execution_price: f64,
+    // The fraction by which the execution price is worse than the spot price across all hops
+    pub price_impact: f64,
+    // False if any hop ran out of liquidity
+    pub filled: bool,
+}
+
+/// Finds every path of at most `max_hops` pools from `from` to `to` which does not revisit a token
+pub fn find_paths(
+    pools: &[PoolEdge],
+    from: Address,
+    to: Address,
+    max_hops: usize,
+) -> Vec<Vec<(Address, PoolEdge)>> {
+    let mut paths = vec![];
+    let mut current = vec![];
+    let mut visited = vec![from];
+    extend_paths(
+        pools,
+        from,
+        to,
+        max_hops,
+        &mut visited,
+        &mut current,
+        &mut paths,
+    );
+    // Shorter paths first so that ties go to the simplest route
+    paths.sort_by_key(|p| p.len());
+    paths
+}
+
+// Depth first search from `token`, each path entry is the token paid into a pool and the pool itself
+fn extend_paths(
+    pools: &[PoolEdge],
+    token: Address,
+    to: Address,
+    max_hops: usize,
+    visited: &mut Vec<Address>,
+    current: &mut Vec<(Address, PoolEdge)>,
+    paths: &mut Vec<Vec<(Address, PoolEdge)>>,
+) {
+    if current.len() >= max_hops {
+        return;
+    }
+    for pool in pools {
+        let next = match pool.other(token) {
+            Some(next) => next,
+            None => continue,
+        };
+        if visited.contains(&next) {
+            continue;
+        }
+        current.push((token, pool.clone()));
+        if next == to {
+            paths.push(current.clone());
+        } else {
+            visited.push(next);
+            extend_paths(pools, next, to, max_hops, visited, current, paths);
+            visited.pop();
+        }
+        current.pop();
+    }
+}
+
+/// Quotes `amount` of the first token along `path`, returns None if any pool along the way has no known price
+pub fn quote_path(
+    db: &impl Storage,
+    path: &[(Address, PoolEdge)],
+    amount: f64,
+) -> Option<RouteQuote> {
+    let mut hops = vec![];
+    let mut qty = amount;
+    let mut spot_price = 1.0;
+    let mut filled = true;
+    for (from, pool) in path {
+        // Paying the base token raises the pool price
+        let is_buy = *from == pool.base;
+        let quote = quote_pool_swap(db, pool.base, pool.quote, pool.pool_idx, is_buy, qty)?;
+        qty = quote.output;
+        spot_price *= quote.spot_price;
+        filled &= quote.filled;
+        hops.push(RouteHop {
+            from: *from,
+            to: pool.other(*from)?,
+            pool: pool.clone(),
+            quote,
+        });
+    }
+    let input = hops.first().map_or(0.0, |h| h.quote.input);
+    let execution_price = if input > 0.0 { qty / input } else { 0.0 };
+    let price_impact = if spot_price > 0.0 && input > 0.0 {
+        1.0 - execution_price / spot_price
+    } else {
+        0.0
+    };
+    Some(RouteQuote {
+        hops,
+        input,
+        output: qty,
+        spot_price,
+        execution_price,
+        price_impact,
+        filled,
+    })
+}
+
+/// Quotes `amount` of `from` along every route of up to MAX_HOPS `pools` to `to` and returns the one with the most output.
+/// Routes which can be completely filled are always preferred over those which cannot.
+pub fn best_route(
+    db: &impl Storage,
+    pools: &[PoolEdge],
+    from: Address,
+    to: Address,
+    amount: f64,
+) -> Option<RouteQuote> {
+    let mut best: Option<RouteQuote> = None;
+    for path in find_paths(pools, from, to, MAX_HOPS) {
+        let route = match quote_path(db, &path, amount) {
+            Some(route) => route,
+            None => continue,
+        };
+        // Paths are sorted by length, so only a strictly better quote replaces a shorter route
+        let better = match &best {
+            None => true,
+            Some(b) => (route.filled, route.output) > (b.filled, b.output),
+        };
+        if better {
+            best = Some(route);
+        }
+    }
+    best
+}
+
+/// Collects the known pools of the given templates for routing through
+pub fn get_pool_edges(db: &impl Storage, templates: &[u64]) -> Vec<PoolEdge> {
+    get_init_pools(db)
+        .into_iter()
+        .filter(|p| templates.iter().any(|t| Uint256::from(*t) == p.pool_idx))
+        .map(|p| PoolEdge {
+            base: p.base,
+            quote: p.quote,
+            pool_idx: p.pool_idx,
+        })
+        .collect()
+}
+
+/// The USD value of one of `token`'s smallest units, from the spot prices along the best route from one whole `token`
+/// to `usd_token`. Returns None if there is no such route or the tokens' decimals are unknown
+pub fn get_usd_price(
+    db: &impl Storage,
+    pools: &[PoolEdge],
+    token: Address,
+    usd_token: Address,
+) -> Option<f64> {
+    let usd_unit = 10f64.powi(get_token_metadata(db, usd_token)?.decimals as i32);
+    if token == usd_token {
+        return Some(1.0 / usd_unit);
+    }
+    let unit = 10f64.powi(get_token_metadata(db, token)?.decimals as i32);
+    let route = best_route(db, pools, token, usd_token, unit)?;
+    Some(route.spot_price / usd_unit)
+}
+
+#[test]
+fn test_find_paths() {
+    let token = |i: u8| Address::from_slice(&[i; 20]).unwrap();
+    let edge = |a: u8, b: u8, idx: u64| PoolEdge {
+        base: token(a.min(b)),
+        quote: token(a.max(b)),
+        pool_idx: idx.into(),
+    };
+    // 1-2 directly in two pools, 1-3-2, 1-4-5-2 and 1-4-5-6-2, which is too long
+    let pools = vec![
+        edge(1, 2, 36000),
+        edge(1, 2, 36001),
+        edge(1, 3, 36000),
+        edge(3, 2, 36000),
+        edge(1, 4, 36000),
+        edge(4, 5, 36000),
+        edge(5, 2, 36000),
+        edge(5, 6, 36000),
+        edge(6, 2, 36000),
+        edge(7, 8, 36000),
+    ];
+
+    let paths = find_paths(&pools, token(1), token(2), MAX_HOPS);
+    let lengths: Vec<usize> = paths.iter().map(|p| p.len()).collect();
+    assert_eq!(lengths, vec![1, 1, 2, 3]);
+    // Each hop pays in the token received from the previous one
+    let three = &paths[3];
+    assert_eq!(three[0].0, token(1));
+    assert_eq!(three[1].0, token(4));
+    assert_eq!(three[2].0, token(5));
+    assert_eq!(three[2].1, edge(5, 2, 36000));
+
+    // Paths are directional
+    let reverse = find_paths(&pools, token(2), token(1), MAX_HOPS);
+    assert_eq!(reverse.len(), 4);
+    assert_eq!(reverse[3][0].0, token(2));
+
+    // Tokens never revisited and unconnected tokens have no route
+    assert_eq!(find_paths(&pools, token(1), token(1), MAX_HOPS).len(), 0);
+    assert_eq!(find_paths(&pools, token(1), token(7), MAX_HOPS).len(), 0);
+    assert_eq!(find_paths(&pools, token(1), token(2), 1).len(), 2);
+}
+
+#[test]
+fn test_best_route() {
+    use crate::althea::database::{
+        curve::{save_liquidity, save_price},
+        storage::MemoryStorage,
+        tracking::{set_tracked_pool, LiquidityBump, TrackedPool},
+    };
+
+    let token = |i: u8| Address::from_slice(&[i; 20]).unwrap();
+    let db = MemoryStorage::new();
+    // Every pool is priced at 1 and charges no fees, so the output only falls with price impact
+    let add_pool = |a: u8, b: u8, liquidity: u128, bumps: Vec<LiquidityBump>| {
+        let (base, quote) = (token(a.min(b)), token(a.max(b)));
+        let pool_idx = Uint256::from(36000u64);
+        save_price(&db, 1u128 << 64, base, quote, pool_idx);
+        save_liquidity(&db, liquidity, base, quote, pool_idx);
+        set_tracked_pool(
+            &db,
+            TrackedPool {
+                base,
+                quote,
+                pool_idx,
+                bumps,
+                ..Default::default()
+            },
+        );
+        PoolEdge {
+            base,
+            quote,
+            pool_idx,
+        }
+    };
+    // A deep direct pool whose liquidity all ends at tick 1000, and a shallower route through token 3
+    let ends = LiquidityBump {
+        tick: 1000,
+        liquidity_delta: -1e9,
+        ..Default::default()
+    };
+    let pools = vec![
+        add_pool(1, 2, 1_000_000_000, vec![ends]),
+        add_pool(1, 3, 150_000_000, vec![]),
+        add_pool(3, 2, 150_000_000, vec![]),
+    ];
+
+    // Small trades take the direct pool, which has less impact than two hops
+    let route = best_route(&db, &pools, token(1), token(2), 1e4).unwrap();
+    assert_eq!(route.hops.len(), 1);
+    assert!(route.filled);
+
+    // The direct pool runs dry before 1e8 is swapped. It would still pay out more than the route through token 3, but
+    // only the route through token 3 fills
+    let direct = quote_path(&db, &[(token(1), pools[0].clone())], 1e8).unwrap();
+    assert!(!direct.filled);
+    let route = best_route(&db, &pools, token(1), token(2), 1e8).unwrap();
+    assert!(route.filled);
+    assert!(route.output < direct.output);
+    assert_eq!(route.hops.len(), 2);
+    assert_eq!((route.hops[0].from, route.hops[0].to), (token(1), token(3)));
+    assert_eq!((route.hops[1].from, route.hops[1].to), (token(3), token(2)));
+
+    // Each hop is paid the output of the one before it
+    assert_eq!(route.input, 1e8);
+    assert_eq!(route.hops[1].quote.input, route.hops[0].quote.output);
+    assert_eq!(route.output, route.hops[1].quote.output);
+    // 1e8 moves the first pool's root price to 1 + 1e8 / 1.5e8, paying out 1.5e8 * (1 - 1 / that) = 6e7, and
+    // 6e7 moves the second pool's root price to 1 / (1 + 6e7 / 1.5e8), paying out about 4.29e7
+    assert!((route.hops[0].quote.output - 6e7).abs() < 1.0);
+    assert!((route.output - 1.5e8 * (1.0 - 1.0 / 1.4)).abs() < 1.0);
+
+    // The spot prices multiply to 1, so the impact is what the execution price loses against 1
+    assert!((route.spot_price - 1.0).abs() < 1e-12);
+    assert!((route.execution_price - route.output / 1e8).abs() < 1e-12);
+    assert!((route.price_impact - (1.0 - route.output / 1e8)).abs() < 1e-12);
+
+    // Pools without a known price are skipped, leaving no route
+    let unknown = PoolEdge {
+        base: token(1),
+        quote: token(4),
+        pool_idx: 36000u64.into(),
+    };
+    assert!(best_route(&db, &[unknown], token(1), token(4), 1e4).is_none());
+}
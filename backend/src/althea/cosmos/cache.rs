@@ -0,0 +1,298 @@
+// This file holds the responses fetched from the Cosmos RPC in memory. Each cache has a time to live after which its entries
+// are refetched, and a capacity beyond which the least recently used entries are evicted, so addresses which are queried once
+// do not stay cached forever.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::althea::{CACHE_DURATION, DELEGATIONS_CACHE_DURATION};
+
+use super::{
+    delegations::{DelegatorResponse, RedelegationInfo},
+    denoms::DenomDisplay,
+    governance::ProposalInfo,
+    staking::StakingInfo,
+    validators::ValidatorInfo,
+};
+
+/// The most delegators whose delegations are cached at once
+pub const MAX_CACHED_DELEGATORS: usize = 10_000;
+/// How long after last asking for their delegations a delegator's delegations keep being refreshed in the background
+pub const ACTIVE_DELEGATOR_WINDOW: Duration = Duration::from_secs(300);
+/// The most delegators whose delegations are refreshed in the background
+pub const MAX_ACTIVE_DELEGATORS: usize = 1_000;
+/// How long the bank denom metadata is cached, it only changes when tokens are registered
+pub const DENOM_METADATA_CACHE_DURATION: Duration = Duration::from_secs(3600);
+
+struct Entry<V> {
+    value: V,
+    inserted: Instant,
+    // The ticks of the cache's clock when the entry was inserted and when it was last read or inserted
+    inserted_tick: u64,
+    last_used: u64,
+}
+
+struct Entries<V> {
+    map: HashMap<String, Entry<V>>,
+    // The keys by insertion and by last use, so the entry to evict is found without going through every entry. Every
+    // tick of the clock is used once, so each tick names a single key
+    by_insert: BTreeMap<u64, String>,
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl<V> Entries<V> {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn touch(&mut self, key: &str) {
+        let clock = self.tick();
+        if let Some(entry) = self.map.get_mut(key) {
+            self.by_use.remove(&entry.last_used);
+            entry.last_used = clock;
+            self.by_use.insert(clock, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) -> Option<Entry<V>> {
+        let entry = self.map.remove(key)?;
+        self.by_insert.remove(&entry.inserted_tick);
+        self.by_use.remove(&entry.last_used);
+        Some(entry)
+    }
+
+    // Removes the entry inserted longest ago if it has expired, since every entry lives for the same time, or else the
+    // least recently used one
+    fn evict(&mut self, ttl: Duration) {
+        let oldest = self
+            .by_insert
+            .values()
+            .next()
+            .filter(|k| self.map[*k].inserted.elapsed() >= ttl);
+        let victim = oldest.or_else(|| self.by_use.values().next()).cloned();
+        if let Some(victim) = victim {
+            self.remove(&victim);
+        }
+    }
+}
+
+/// A map whose entries expire `ttl` after they are inserted and which holds at most `capacity` entries
+pub struct TtlCache<V> {
+    name: &'static str,
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<Entries<V>>,
+    counters: Mutex<CacheStats>,
+}
+
+/// How a cache has been used since the server started
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub name: &'static str,
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Entries removed to make room for new ones
+    pub evictions: u64,
+    /// Entries which were read after their time to live had passed
+    pub expirations: u64,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(name: &'static str, ttl: Duration, capacity: usize) -> Self {
+        TtlCache {
+            name,
+            ttl,
+            capacity,
+            entries: Mutex::new(Entries {
+                map: HashMap::new(),
+                by_insert: BTreeMap::new(),
+                by_use: BTreeMap::new(),
+                clock: 0,
+            }),
+            counters: Mutex::new(CacheStats::default()),
+        }
+    }
+
+    /// Gets the value at `key` if it has not expired, counting a hit or a miss
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        let found = match entries.map.get(key) {
+            Some(entry) if entry.inserted.elapsed() < self.ttl => Some(entry.value.clone()),
+            Some(_) => {
+                self.counters.lock().unwrap().expirations += 1;
+                None
+            }
+            None => None,
+        };
+        if found.is_some() {
+            entries.touch(key);
+        }
+        let mut counters = self.counters.lock().unwrap();
+        if found.is_some() {
+            counters.hits += 1;
+        } else {
+            counters.misses += 1;
+        }
+        found
+    }
+
+    /// True if `key` holds a value which has not expired, without counting as a use
+    pub fn is_fresh(&self, key: &str) -> bool {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .map
+            .get(key)
+            .is_some_and(|e| e.inserted.elapsed() < self.ttl)
+    }
+
+    /// Inserts `value` at `key`, evicting an expired entry or else the least recently used one if the cache is full
+    pub fn insert(&self, key: &str, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.remove(key).is_none() && entries.map.len() >= self.capacity {
+            entries.evict(self.ttl);
+            self.counters.lock().unwrap().evictions += 1;
+        }
+        let clock = entries.tick();
+        entries.by_insert.insert(clock, key.to_string());
+        entries.by_use.insert(clock, key.to_string());
+        entries.map.insert(
+            key.to_string(),
+            Entry {
+                value,
+                inserted: Instant::now(),
+                inserted_tick: clock,
+                last_used: clock,
+            },
+        );
+    }
+
+    /// Replaces the value of an entry which is still cached without counting as a use, so background refreshes do not keep
+    /// otherwise unused entries from being evicted. Does nothing if the entry has been evicted
+    pub fn refresh(&self, key: &str, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        let clock = entries.tick();
+        if let Some(entry) = entries.map.get_mut(key) {
+            let previous = entry.inserted_tick;
+            entry.value = value;
+            entry.inserted = Instant::now();
+            entry.inserted_tick = clock;
+            entries.by_insert.remove(&previous);
+            entries.by_insert.insert(clock, key.to_string());
+        }
+    }
+
+    /// Every cached key, including those which have expired but not been evicted yet
+    pub fn keys(&self) -> Vec<String> {
+        self.entries.lock().unwrap().map.keys().cloned().collect()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap().map.len();
+        CacheStats {
+            name: self.name,
+            entries,
+            capacity: self.capacity,
+            ..self.counters.lock().unwrap().clone()
+        }
+    }
+}
+
+/// The caches of every Cosmos response, shared by the endpoints and the refresh tasks
+pub struct CosmosCaches {
+    pub validators: TtlCache<Vec<ValidatorInfo>>,
+    pub proposals: TtlCache<Vec<ProposalInfo>>,
+    pub staking_info: TtlCache<StakingInfo>,
+    /// Keyed by the delegator's address
+    pub delegations: TtlCache<DelegatorResponse>,
+    /// Keyed by the delegator's address, only filled by the endpoints which show redelegations
+    pub redelegations: TtlCache<Vec<RedelegationInfo>>,
+    /// The delegators who asked for their delegations within ACTIVE_DELEGATOR_WINDOW, whose delegations are refreshed
+    pub active_delegators: TtlCache<()>,
+    /// How each denom with bank metadata is displayed, keyed by base denom
+    pub denom_metadata: TtlCache<HashMap<String, DenomDisplay>>,
+}
+
+impl CosmosCaches {
+    pub fn new() -> Self {
+        let ttl = Duration::from_secs(CACHE_DURATION);
+        CosmosCaches {
+            validators: TtlCache::new("validators", ttl, 1),
+            proposals: TtlCache::new("proposals", ttl, 1),
+            staking_info: TtlCache::new("staking_info", ttl, 1),
+            delegations: TtlCache::new(
+                "delegations",
+                Duration::from_secs(DELEGATIONS_CACHE_DURATION),
+                MAX_CACHED_DELEGATORS,
+            ),
+            redelegations: TtlCache::new(
+                "redelegations",
+                Duration::from_secs(DELEGATIONS_CACHE_DURATION),
+                MAX_CACHED_DELEGATORS,
+            ),
+            active_delegators: TtlCache::new(
+                "active_delegators",
+                ACTIVE_DELEGATOR_WINDOW,
+                MAX_ACTIVE_DELEGATORS,
+            ),
+            denom_metadata: TtlCache::new("denom_metadata", DENOM_METADATA_CACHE_DURATION, 1),
+        }
+    }
+
+    pub fn stats(&self) -> Vec<CacheStats> {
+        vec![
+            self.validators.stats(),
+            self.proposals.stats(),
+            self.staking_info.stats(),
+            self.delegations.stats(),
+            self.redelegations.stats(),
+            self.active_delegators.stats(),
+            self.denom_metadata.stats(),
+        ]
+    }
+}
+
+impl Default for CosmosCaches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_ttl_cache() {
+    let cache = TtlCache::new("test", Duration::from_secs(60), 2);
+    assert_eq!(cache.get("a"), None);
+    cache.insert("a", 1);
+    cache.insert("b", 2);
+    assert_eq!(cache.get("a"), Some(1));
+    // b is the least recently used, so it makes room for c
+    cache.insert("c", 3);
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("a"), Some(1));
+    assert_eq!(cache.get("c"), Some(3));
+    // Refreshing an evicted entry does not bring it back
+    cache.refresh("b", 4);
+    assert_eq!(cache.get("b"), None);
+    // Replacing a cached entry evicts nothing
+    cache.insert("a", 5);
+    assert_eq!(cache.get("a"), Some(5));
+    assert_eq!(cache.get("c"), Some(3));
+
+    let stats = cache.stats();
+    assert_eq!(stats.entries, 2);
+    assert_eq!((stats.hits, stats.misses, stats.evictions), (5, 3, 1));
+
+    // Expired entries miss and are evicted before live ones
+    let cache = TtlCache::new("test", Duration::ZERO, 2);
+    cache.insert("a", 1);
+    assert!(!cache.is_fresh("a"));
+    assert_eq!(cache.get("a"), None);
+    assert_eq!(cache.stats().expirations, 1);
+    assert_eq!(cache.keys(), ["a"]);
+}
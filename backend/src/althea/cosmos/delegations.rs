@@ -1,25 +1,31 @@
-use bincode;
 use deep_space::{Address as CosmosAddress, Contact};
-use log::error;
-use rocksdb::DB;
+use futures::stream::{self, StreamExt};
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
 
-use crate::althea::abi_util::format_u128_to_decimal_18;
-use crate::althea::DELEGATIONS_CACHE_DURATION;
+use crate::althea::abi_util::shift_decimal;
+use cosmos_sdk_proto_althea::cosmos::base::query::v1beta1::{PageRequest, PageResponse};
+use cosmos_sdk_proto_althea::cosmos::base::v1beta1::DecCoin;
 use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{
-    query_client::QueryClient as StakingQueryClient, QueryDelegatorUnbondingDelegationsRequest,
+    query_client::QueryClient as StakingQueryClient, QueryDelegatorDelegationsRequest,
+    QueryDelegatorUnbondingDelegationsRequest, QueryRedelegationsRequest, RedelegationResponse,
 };
+use prost_types::Timestamp;
 use tokio;
+use tonic::transport::Channel;
 
-const DELEGATIONS_KEY_PREFIX: &str = "delegations_";
+use super::cache::CosmosCaches;
+use super::denoms::{fetch_denom_metadata_or_empty, DecCoinAmount, DEC_PRECISION};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DelegatorResponse {
     pub delegations: Vec<DelegationResponse>,
+    /// Every unbonding entry on its own, kept for the /delegations response
     pub unbonding_delegations: Option<Vec<UnbondingDelegation>>,
+    pub unbondings: Vec<ValidatorUnbondings>,
     pub rewards: RewardsResponse,
 }
 
@@ -46,13 +52,13 @@ pub struct Balance {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RewardsResponse {
     pub rewards: Vec<ValidatorReward>,
-    pub total: Vec<Balance>,
+    pub total: Vec<DecCoinAmount>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ValidatorReward {
     pub validator_address: String,
-    pub reward: Vec<Balance>,
+    pub reward: Vec<DecCoinAmount>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -65,198 +71,519 @@ pub struct UnbondingDelegation {
     pub balance: String,
 }
 
-fn get_cached_delegations(
-    db: &rocksdb::DB,
-    delegator: &CosmosAddress,
-) -> Option<DelegatorResponse> {
-    let key = format!("{}{}", DELEGATIONS_KEY_PREFIX, delegator);
-    match db.get(key.as_bytes()).unwrap() {
-        Some(data) => {
-            let delegations: DelegatorResponse = bincode::deserialize(&data).unwrap();
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            // Check if any delegations exist and if cache is still valid
-            if !delegations.delegations.is_empty()
-                && now - delegations.delegations[0].delegation.last_updated
-                    < DELEGATIONS_CACHE_DURATION
-            {
-                Some(delegations)
-            } else {
-                None
-            }
+/// The pending unbondings from one validator, each entry is a separate undelegation with its own completion time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ValidatorUnbondings {
+    pub validator_address: String,
+    pub entries: Vec<UnbondingEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnbondingEntry {
+    pub creation_height: i64,
+    /// When the tokens are returned to the delegator, in RFC 3339
+    pub completion_time: String,
+    /// The amount undelegated, in the bond denom's base units
+    pub initial_balance: String,
+    /// The amount which will be returned, less than initial_balance if the validator was slashed since
+    pub balance: String,
+}
+
+/// The pending redelegations from one validator to another. Until an entry completes the redelegated tokens can not be
+/// redelegated again and are still slashed for the source validator's misbehaviour
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedelegationInfo {
+    pub validator_src_address: String,
+    pub validator_dst_address: String,
+    pub entries: Vec<RedelegationEntryInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RedelegationEntryInfo {
+    pub creation_height: i64,
+    /// When the redelegation lock is lifted, in RFC 3339
+    pub completion_time: String,
+    /// The amount redelegated, in the bond denom's base units
+    pub initial_balance: String,
+    /// The amount still locked, less than initial_balance if the source validator was slashed since
+    pub balance: String,
+    /// The shares of the destination validator received
+    pub shares_dst: String,
+}
+
+/// Everything a delegator has staked, in one response
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StakingOverview {
+    pub delegations: Vec<DelegationResponse>,
+    pub unbondings: Vec<ValidatorUnbondings>,
+    pub redelegations: Vec<RedelegationInfo>,
+    /// The rewards which can be withdrawn now
+    pub rewards: RewardsResponse,
+}
+
+impl StakingOverview {
+    pub fn new(response: DelegatorResponse, redelegations: Vec<RedelegationInfo>) -> Self {
+        StakingOverview {
+            delegations: response.delegations,
+            unbondings: response.unbondings,
+            redelegations,
+            rewards: response.rewards,
         }
-        None => None,
     }
 }
 
-fn cache_delegations(db: &rocksdb::DB, delegator: &CosmosAddress, response: &DelegatorResponse) {
-    let key = format!("{}{}", DELEGATIONS_KEY_PREFIX, delegator);
-    let encoded = bincode::serialize(response).unwrap();
-    db.put(key.as_bytes(), encoded).unwrap();
+/// How many delegations, unbonding delegations or redelegations are requested per page
+const DELEGATIONS_PAGE_LIMIT: u64 = 100;
+
+/// The request for the page starting at `key`, the first page has an empty key
+fn page_request(key: Vec<u8>) -> Option<PageRequest> {
+    Some(PageRequest {
+        key,
+        offset: 0,
+        limit: DELEGATIONS_PAGE_LIMIT,
+        count_total: false,
+        reverse: false,
+    })
 }
 
-async fn fetch_unbonding_delegations(
-    url: String,
+/// The key of the page after `pagination`, None once there are no more pages
+fn next_page(pagination: Option<PageResponse>) -> Option<Vec<u8>> {
+    pagination
+        .map(|p| p.next_key)
+        .filter(|next_key| !next_key.is_empty())
+}
+
+async fn fetch_delegator_delegations(
+    client: &mut StakingQueryClient<Channel>,
     delegator_address: CosmosAddress,
-) -> Result<Vec<UnbondingDelegation>, Box<dyn std::error::Error>> {
-    let mut client = StakingQueryClient::connect(url).await?;
+) -> Result<Vec<DelegationResponse>, Box<dyn std::error::Error>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let mut delegations = Vec::new();
+    let mut key = Vec::new();
+    loop {
+        let response = client
+            .delegator_delegations(QueryDelegatorDelegationsRequest {
+                delegator_addr: delegator_address.to_string(),
+                pagination: page_request(key),
+            })
+            .await?
+            .into_inner();
 
-    let request = tonic::Request::new(QueryDelegatorUnbondingDelegationsRequest {
-        delegator_addr: delegator_address.to_string(),
-        pagination: None,
-    });
+        for delegation in response.delegation_responses {
+            let (Some(del_response), Some(balance)) = (delegation.delegation, delegation.balance)
+            else {
+                continue;
+            };
+            delegations.push(DelegationResponse {
+                delegation: DelegationInfo {
+                    delegator_address: delegator_address.to_string(),
+                    validator_address: del_response.validator_address,
+                    shares: format!("{}.000000000000000000", del_response.shares),
+                    last_updated: now,
+                },
+                balance: Balance {
+                    denom: balance.denom,
+                    amount: balance.amount,
+                },
+            });
+        }
+
+        match next_page(response.pagination) {
+            Some(next_key) => key = next_key,
+            None => return Ok(delegations),
+        }
+    }
+}
+
+fn format_completion_time(completion_time: Option<Timestamp>) -> String {
+    completion_time.map(|t| t.to_string()).unwrap_or_default()
+}
+
+async fn fetch_unbonding_delegations(
+    client: &mut StakingQueryClient<Channel>,
+    delegator_address: CosmosAddress,
+) -> Result<Vec<ValidatorUnbondings>, Box<dyn std::error::Error>> {
+    let mut unbondings = Vec::new();
+    let mut key = Vec::new();
+    loop {
+        let response = client
+            .delegator_unbonding_delegations(QueryDelegatorUnbondingDelegationsRequest {
+                delegator_addr: delegator_address.to_string(),
+                pagination: page_request(key),
+            })
+            .await?
+            .into_inner();
 
-    let response = client.delegator_unbonding_delegations(request).await?;
-    let unbonding_responses = response.into_inner().unbonding_responses;
+        for unbonding in response.unbonding_responses {
+            unbondings.push(ValidatorUnbondings {
+                validator_address: unbonding.validator_address,
+                entries: unbonding
+                    .entries
+                    .into_iter()
+                    .map(|entry| UnbondingEntry {
+                        creation_height: entry.creation_height,
+                        completion_time: format_completion_time(entry.completion_time),
+                        initial_balance: entry.initial_balance,
+                        balance: entry.balance,
+                    })
+                    .collect(),
+            });
+        }
 
-    let mut unbonding_delegations = Vec::new();
+        match next_page(response.pagination) {
+            Some(next_key) => key = next_key,
+            None => return Ok(unbondings),
+        }
+    }
+}
 
-    for unbonding in unbonding_responses {
-        // Clone the validator address since we'll use it multiple times in the loop
-        let validator_address = unbonding.validator_address.clone();
-        for entry in unbonding.entries {
-            unbonding_delegations.push(UnbondingDelegation {
+/// Lists every unbonding entry on its own in the format /delegations has always returned
+fn flatten_unbondings(
+    delegator_address: &str,
+    unbondings: &[ValidatorUnbondings],
+) -> Vec<UnbondingDelegation> {
+    unbondings
+        .iter()
+        .flat_map(|unbonding| {
+            unbonding.entries.iter().map(|entry| UnbondingDelegation {
                 delegator_address: delegator_address.to_string(),
-                validator_address: validator_address.clone(),
+                validator_address: unbonding.validator_address.clone(),
                 creation_height: entry.creation_height,
-                completion_time: entry.completion_time.unwrap().to_string(),
+                completion_time: entry.completion_time.clone(),
                 initial_balance: format!("{}.000000000000000000", entry.initial_balance),
                 balance: format!("{}.000000000000000000", entry.balance),
-            });
+            })
+        })
+        .collect()
+}
+
+/// Converts a redelegation from the staking module, None if it is missing the redelegation itself
+fn redelegation_from_response(response: RedelegationResponse) -> Option<RedelegationInfo> {
+    let redelegation = response.redelegation?;
+    // The entries with balances are in the same order as the redelegation's entries
+    let entries = response
+        .entries
+        .into_iter()
+        .filter_map(|e| {
+            let entry = e.redelegation_entry?;
+            Some(RedelegationEntryInfo {
+                creation_height: entry.creation_height,
+                completion_time: format_completion_time(entry.completion_time),
+                initial_balance: entry.initial_balance,
+                balance: e.balance,
+                shares_dst: shift_decimal(&entry.shares_dst, DEC_PRECISION)?,
+            })
+        })
+        .collect();
+    Some(RedelegationInfo {
+        validator_src_address: redelegation.validator_src_address,
+        validator_dst_address: redelegation.validator_dst_address,
+        entries,
+    })
+}
+
+async fn query_redelegations(
+    client: &mut StakingQueryClient<Channel>,
+    delegator_address: CosmosAddress,
+) -> Result<Vec<RedelegationInfo>, Box<dyn std::error::Error>> {
+    let mut redelegations = Vec::new();
+    let mut key = Vec::new();
+    loop {
+        // Leaving both validators empty queries every redelegation of the delegator
+        let response = client
+            .redelegations(QueryRedelegationsRequest {
+                delegator_addr: delegator_address.to_string(),
+                src_validator_addr: String::new(),
+                dst_validator_addr: String::new(),
+                pagination: page_request(key),
+            })
+            .await?
+            .into_inner();
+
+        redelegations.extend(
+            response
+                .redelegation_responses
+                .into_iter()
+                .filter_map(redelegation_from_response),
+        );
+
+        match next_page(response.pagination) {
+            Some(next_key) => key = next_key,
+            None => return Ok(redelegations),
         }
     }
-
-    Ok(unbonding_delegations)
 }
 
 pub async fn fetch_delegations(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &Contact,
     delegator_address: CosmosAddress,
 ) -> Result<DelegatorResponse, Box<dyn std::error::Error>> {
+    let key = delegator_address.to_string();
+    // Keeps the delegator's cached delegations refreshed in the background while they keep asking for them
+    caches.active_delegators.insert(&key, ());
     // Check cache first
-    if let Some(cached) = get_cached_delegations(db, &delegator_address) {
+    if let Some(cached) = caches.delegations.get(&key) {
         return Ok(cached);
     }
 
-    let validators = contact
-        .query_delegator_validators(delegator_address)
-        .await?;
-
-    let mut delegation_responses = Vec::new();
-    for validator_addr in &validators {
-        let validator_address = CosmosAddress::from_bech32(validator_addr.to_string())?;
+    let response = query_delegations(caches, contact, delegator_address).await?;
+    caches.delegations.insert(&key, response.clone());
+    Ok(response)
+}
 
-        if let Some(delegation) = contact
-            .get_delegation(validator_address, delegator_address)
-            .await?
-        {
-            if let Some(del_response) = delegation.delegation {
-                delegation_responses.push(DelegationResponse {
-                    delegation: DelegationInfo {
-                        delegator_address: delegator_address.to_string(),
-                        validator_address: validator_addr.clone(),
-                        shares: format!("{}.000000000000000000", del_response.shares),
-                        last_updated: SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
-                    },
-                    balance: Balance {
-                        denom: "aalthea".to_string(),
-                        amount: delegation.balance.map(|b| b.amount).unwrap_or_default(),
-                    },
-                });
-            }
-        }
+/// Gets the pending redelegations of `delegator_address`. Only /redelegations and /staking_overview show them, so they are
+/// cached apart from the delegations and are not refreshed in the background
+pub async fn fetch_redelegations(
+    caches: &CosmosCaches,
+    contact: &Contact,
+    delegator_address: CosmosAddress,
+) -> Result<Vec<RedelegationInfo>, Box<dyn std::error::Error>> {
+    let key = delegator_address.to_string();
+    if let Some(cached) = caches.redelegations.get(&key) {
+        return Ok(cached);
     }
 
+    let mut client = StakingQueryClient::connect(contact.get_url()).await?;
+    let redelegations = query_redelegations(&mut client, delegator_address).await?;
+    caches.redelegations.insert(&key, redelegations.clone());
+    Ok(redelegations)
+}
+
+/// Like fetch_redelegations, but for responses which are still worth returning without the redelegations, so errors
+/// leave them empty
+pub async fn fetch_redelegations_or_empty(
+    caches: &CosmosCaches,
+    contact: &Contact,
+    delegator_address: CosmosAddress,
+) -> Vec<RedelegationInfo> {
+    fetch_redelegations(caches, contact, delegator_address)
+        .await
+        .unwrap_or_else(|e| {
+            error!(
+                "Failed to fetch redelegations for {}: {}",
+                delegator_address, e
+            );
+            Vec::new()
+        })
+}
+
+/// Queries the delegations, rewards and unbonding delegations of `delegator_address` from the chain, bypassing the cache
+async fn query_delegations(
+    caches: &CosmosCaches,
+    contact: &Contact,
+    delegator_address: CosmosAddress,
+) -> Result<DelegatorResponse, Box<dyn std::error::Error>> {
+    let mut client = StakingQueryClient::connect(contact.get_url()).await?;
+    let delegation_responses = fetch_delegator_delegations(&mut client, delegator_address).await?;
+    let validators: Vec<String> = delegation_responses
+        .iter()
+        .map(|d| d.delegation.validator_address.clone())
+        .collect();
+
     // Fetch rewards using query_all_delegation_rewards
     let rewards_response = contact
         .query_all_delegation_rewards(delegator_address)
         .await?;
 
+    // Every coin is kept, rewards can be paid in IBC and ERC20 converted tokens as well as aalthea
+    let denoms = fetch_denom_metadata_or_empty(caches, contact).await;
+    let to_amounts = |coins: &[DecCoin]| -> Vec<DecCoinAmount> {
+        coins
+            .iter()
+            .filter_map(|c| DecCoinAmount::new(c.denom.clone(), &c.amount, &denoms))
+            .collect()
+    };
     let rewards = validators
         .iter()
         .map(|validator_addr| ValidatorReward {
             validator_address: validator_addr.clone(),
-            reward: vec![Balance {
-                denom: "aalthea".to_string(),
-                amount: rewards_response
-                    .rewards
-                    .iter()
-                    .find(|r| r.validator_address == *validator_addr)
-                    .and_then(|r| r.reward.first())
-                    .map(|r| {
-                        let amount = r.amount.parse::<u128>().unwrap_or_default();
-                        format_u128_to_decimal_18(amount, 100_000_000_000_000)
-                    })
-                    .unwrap_or_else(|| "0.000000000000000000".to_string()),
-            }],
+            reward: rewards_response
+                .rewards
+                .iter()
+                .find(|r| r.validator_address == *validator_addr)
+                .map(|r| to_amounts(&r.reward))
+                .unwrap_or_default(),
         })
         .collect();
-
-    let total = vec![Balance {
-        denom: "aalthea".to_string(),
-        amount: rewards_response
-            .total
-            .first()
-            .map(|t| {
-                let amount = t.amount.parse::<u128>().unwrap_or_default();
-                format_u128_to_decimal_18(amount, 100_000_000_000_000)
-            })
-            .unwrap_or_else(|| "0.000000000000000000".to_string()),
-    }];
+    let total = to_amounts(&rewards_response.total);
 
     // Fetch unbonding delegations
-    let unbonding_delegations =
-        fetch_unbonding_delegations(contact.get_url(), delegator_address).await?;
+    let unbondings = fetch_unbonding_delegations(&mut client, delegator_address).await?;
+    let unbonding_delegations = flatten_unbondings(&delegator_address.to_string(), &unbondings);
     let unbonding_delegations = if unbonding_delegations.is_empty() {
         None
     } else {
         Some(unbonding_delegations)
     };
 
-    // Create and cache response
-    let response = DelegatorResponse {
+    Ok(DelegatorResponse {
         delegations: delegation_responses,
         unbonding_delegations,
+        unbondings,
         rewards: RewardsResponse { rewards, total },
-    };
+    })
+}
 
-    cache_delegations(db, &delegator_address, &response);
-    Ok(response)
+/// The default number of delegators whose delegations are refreshed at once
+pub const DEFAULT_DELEGATION_REFRESH_CONCURRENCY: usize = 8;
+/// The longest the refresh task waits between rounds while the Cosmos RPC keeps failing
+const MAX_DELEGATION_REFRESH_BACKOFF: Duration = Duration::from_secs(120);
+
+/// The wait before the next refresh round. A round in which every refresh failed doubles the wait, up to
+/// MAX_DELEGATION_REFRESH_BACKOFF, so an unreachable RPC is not queried for every active delegator each interval. Any
+/// success returns to the configured `interval`
+fn next_refresh_wait(
+    interval: Duration,
+    current: Duration,
+    succeeded: usize,
+    failed: usize,
+) -> Duration {
+    if failed > 0 && succeeded == 0 {
+        (current * 2).clamp(interval, MAX_DELEGATION_REFRESH_BACKOFF.max(interval))
+    } else {
+        interval
+    }
 }
 
-pub fn start_delegation_cache_refresh_task(db: Arc<DB>, contact: Contact) {
+/// Refreshes the expired delegations of delegators who asked for them recently, `concurrency` at a time every `interval`,
+/// backing off while every refresh fails. Delegators who stop asking drop out of the active set after
+/// ACTIVE_DELEGATOR_WINDOW, or sooner if it is full, and are not refreshed again
+pub fn start_delegation_cache_refresh_task(
+    caches: Arc<CosmosCaches>,
+    contact: Contact,
+    interval: Duration,
+    concurrency: usize,
+) {
     tokio::spawn(async move {
+        let mut wait = interval;
         loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(DELEGATIONS_CACHE_DURATION)).await;
-
-            let iter = db.iterator(rocksdb::IteratorMode::Start);
-            for item in iter {
-                if item.is_ok() {
-                    let (key_bytes, _) = item.unwrap();
-                    let key_str = String::from_utf8_lossy(&key_bytes);
-                    if key_str.starts_with(DELEGATIONS_KEY_PREFIX) {
-                        let delegator_addr = key_str.trim_start_matches(DELEGATIONS_KEY_PREFIX);
-                        if let Ok(cosmos_addr) =
-                            CosmosAddress::from_bech32(delegator_addr.to_string())
-                        {
-                            match fetch_delegations(&db, &contact, cosmos_addr).await {
-                                Ok(_) => {}
-                                Err(e) => error!(
+            tokio::time::sleep(wait).await;
+
+            let stale: Vec<String> = caches
+                .active_delegators
+                .keys()
+                .into_iter()
+                .filter(|d| caches.active_delegators.is_fresh(d) && !caches.delegations.is_fresh(d))
+                .collect();
+            let results: Vec<bool> = stream::iter(stale)
+                .map(|delegator_addr| {
+                    let caches = &caches;
+                    let contact = &contact;
+                    async move {
+                        let Ok(cosmos_addr) = CosmosAddress::from_bech32(delegator_addr.clone())
+                        else {
+                            return true;
+                        };
+                        match query_delegations(caches, contact, cosmos_addr).await {
+                            Ok(response) => {
+                                caches.delegations.refresh(&delegator_addr, response);
+                                true
+                            }
+                            Err(e) => {
+                                error!(
                                     "Failed to refresh delegations cache for {}: {}",
                                     delegator_addr, e
-                                ),
+                                );
+                                false
                             }
                         }
                     }
-                }
+                })
+                .buffer_unordered(concurrency.max(1))
+                .collect()
+                .await;
+
+            let succeeded = results.iter().filter(|ok| **ok).count();
+            wait = next_refresh_wait(interval, wait, succeeded, results.len() - succeeded);
+            if wait > interval {
+                warn!(
+                    "Every delegation refresh failed, waiting {}s before the next round",
+                    wait.as_secs()
+                );
             }
         }
     });
 }
+
+#[test]
+fn test_unbondings_and_redelegations() {
+    use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{
+        Redelegation, RedelegationEntry, RedelegationEntryResponse,
+    };
+
+    let completion_time = Some(Timestamp {
+        seconds: 1_700_000_000,
+        nanos: 0,
+    });
+    let unbondings = vec![ValidatorUnbondings {
+        validator_address: "altheavaloper1a".to_string(),
+        entries: vec![
+            UnbondingEntry {
+                creation_height: 10,
+                completion_time: format_completion_time(completion_time),
+                initial_balance: "100".to_string(),
+                balance: "100".to_string(),
+            },
+            UnbondingEntry {
+                creation_height: 20,
+                completion_time: format_completion_time(None),
+                initial_balance: "50".to_string(),
+                balance: "45".to_string(),
+            },
+        ],
+    }];
+    let flat = flatten_unbondings("althea1d", &unbondings);
+    assert_eq!(flat.len(), 2);
+    assert_eq!(flat[0].completion_time, "2023-11-14T22:13:20Z");
+    assert_eq!(flat[1].validator_address, "altheavaloper1a");
+    assert_eq!(flat[1].balance, "45.000000000000000000");
+
+    let entry = RedelegationEntry {
+        creation_height: 30,
+        completion_time,
+        initial_balance: "200".to_string(),
+        shares_dst: "200000000000000000000".to_string(),
+    };
+    let response = RedelegationResponse {
+        redelegation: Some(Redelegation {
+            delegator_address: "althea1d".to_string(),
+            validator_src_address: "altheavaloper1a".to_string(),
+            validator_dst_address: "altheavaloper1b".to_string(),
+            entries: vec![entry.clone()],
+        }),
+        entries: vec![RedelegationEntryResponse {
+            redelegation_entry: Some(entry),
+            balance: "190".to_string(),
+        }],
+    };
+    let redelegation = redelegation_from_response(response).unwrap();
+    assert_eq!(redelegation.validator_dst_address, "altheavaloper1b");
+    assert_eq!(redelegation.entries[0].balance, "190");
+    assert_eq!(redelegation.entries[0].shares_dst, "200.000000000000000000");
+    assert_eq!(
+        redelegation.entries[0].completion_time,
+        "2023-11-14T22:13:20Z"
+    );
+    assert!(redelegation_from_response(RedelegationResponse::default()).is_none());
+}
+
+#[test]
+fn test_next_refresh_wait() {
+    let interval = Duration::from_secs(4);
+    // Failed rounds double the wait up to the cap
+    let mut wait = interval;
+    for expected in [8, 16, 32, 64, 120, 120] {
+        wait = next_refresh_wait(interval, wait, 0, 3);
+        assert_eq!(wait, Duration::from_secs(expected));
+    }
+    // A partly successful round or one with nothing to refresh returns to the interval
+    assert_eq!(next_refresh_wait(interval, wait, 1, 3), interval);
+    assert_eq!(next_refresh_wait(interval, wait, 0, 0), interval);
+    // An interval longer than the cap is never shortened
+    let long = Duration::from_secs(300);
+    assert_eq!(next_refresh_wait(long, long, 0, 1), long);
+}
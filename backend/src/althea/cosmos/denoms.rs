@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::Metadata;
+use deep_space::Contact;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::althea::abi_util::shift_decimal;
+
+use super::cache::CosmosCaches;
+
+pub const DENOM_METADATA_CACHE_KEY: &str = "denom_metadata";
+
+/// The decimal places of a Cosmos SDK Dec, which DecCoin amounts are encoded as integers scaled by
+pub const DEC_PRECISION: u32 = 18;
+/// The staking denom, the only denom rewards were reported in before every reward coin was returned
+pub const STAKING_DENOM: &str = "aalthea";
+/// The decimal places dropped from the integer encoding of a staking reward in the `amount` older clients read
+const LEGACY_AMOUNT_SHIFT: u32 = 14;
+
+/// How a denom is displayed, taken from its bank metadata
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DenomDisplay {
+    /// The denom amounts are shown in, e.g. althea for aalthea
+    pub display: String,
+    pub symbol: String,
+    /// The exponent of the display denom, the number of base units in one display unit is 10^decimals
+    pub decimals: u32,
+}
+
+impl DenomDisplay {
+    /// Reads the display denom's exponent from `metadata`, None if the display denom is not one of its units
+    pub fn from_metadata(metadata: &Metadata) -> Option<DenomDisplay> {
+        let unit = metadata
+            .denom_units
+            .iter()
+            .find(|u| u.denom == metadata.display || u.aliases.contains(&metadata.display))?;
+        Some(DenomDisplay {
+            display: metadata.display.clone(),
+            symbol: metadata.symbol.clone(),
+            decimals: unit.exponent,
+        })
+    }
+}
+
+/// Gets how every denom with bank metadata is displayed, keyed by base denom. Metadata rarely changes, so it is cached
+/// for much longer than other responses
+pub async fn fetch_denom_metadata(
+    caches: &CosmosCaches,
+    contact: &Contact,
+) -> Result<HashMap<String, DenomDisplay>, Box<dyn std::error::Error>> {
+    if let Some(denoms) = caches.denom_metadata.get(DENOM_METADATA_CACHE_KEY) {
+        return Ok(denoms);
+    }
+
+    let denoms: HashMap<String, DenomDisplay> = contact
+        .get_all_denoms_metadata()
+        .await?
+        .iter()
+        .filter_map(|m| Some((m.base.clone(), DenomDisplay::from_metadata(m)?)))
+        .collect();
+    info!("Fetched metadata of {} denoms", denoms.len());
+    caches
+        .denom_metadata
+        .insert(DENOM_METADATA_CACHE_KEY, denoms.clone());
+    Ok(denoms)
+}
+
+/// Like fetch_denom_metadata, but amounts are still worth returning without it, so errors only leave out the display
+/// amounts
+pub async fn fetch_denom_metadata_or_empty(
+    caches: &CosmosCaches,
+    contact: &Contact,
+) -> HashMap<String, DenomDisplay> {
+    fetch_denom_metadata(caches, contact)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Failed to fetch denom metadata: {}", e);
+            HashMap::new()
+        })
+}
+
+/// An amount of a DecCoin, like a staking reward, which may be a fraction of the denom's base unit
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DecCoinAmount {
+    pub denom: String,
+    /// For the staking denom, the amount as older clients read it: the integer encoding of the DecCoin divided by 10^14
+    /// and rounded down, followed by 18 zero decimal places. For every other denom the same as base_amount. New clients
+    /// should use base_amount or display_amount
+    pub amount: String,
+    /// The amount in base units with all 18 decimal places
+    pub base_amount: String,
+    /// The display denom and its exponent, when the chain has metadata for the denom
+    pub display_denom: Option<String>,
+    pub decimals: Option<u32>,
+    /// The amount in display units with all of its decimal places
+    pub display_amount: Option<String>,
+}
+
+impl DecCoinAmount {
+    /// Converts a DecCoin as encoded in gRPC responses, where `amount` is the integer of the Dec scaled by 10^18
+    pub fn new(
+        denom: String,
+        amount: &str,
+        denoms: &HashMap<String, DenomDisplay>,
+    ) -> Option<DecCoinAmount> {
+        let display = denoms.get(&denom);
+        let base_amount = shift_decimal(amount, DEC_PRECISION)?;
+        let legacy_amount = if denom == STAKING_DENOM {
+            let legacy = shift_decimal(amount, LEGACY_AMOUNT_SHIFT)?;
+            let whole = legacy.split('.').next().unwrap_or("0");
+            format!("{}.{}", whole, "0".repeat(DEC_PRECISION as usize))
+        } else {
+            base_amount.clone()
+        };
+        Some(DecCoinAmount {
+            amount: legacy_amount,
+            base_amount,
+            display_denom: display.map(|d| d.display.clone()),
+            decimals: display.map(|d| d.decimals),
+            display_amount: match display {
+                Some(d) => Some(shift_decimal(amount, DEC_PRECISION + d.decimals)?),
+                None => None,
+            },
+            denom,
+        })
+    }
+}
+
+#[test]
+fn test_dec_coin_amount() {
+    use cosmos_sdk_proto_althea::cosmos::bank::v1beta1::DenomUnit;
+
+    let metadata = Metadata {
+        base: "aalthea".to_string(),
+        display: "althea".to_string(),
+        symbol: "ALTHEA".to_string(),
+        denom_units: vec![
+            DenomUnit {
+                denom: "aalthea".to_string(),
+                exponent: 0,
+                aliases: vec![],
+            },
+            DenomUnit {
+                denom: "althea".to_string(),
+                exponent: 18,
+                aliases: vec![],
+            },
+        ],
+        ..Default::default()
+    };
+    let denoms = HashMap::from([(
+        "aalthea".to_string(),
+        DenomDisplay::from_metadata(&metadata).unwrap(),
+    )]);
+
+    // 1.5 ALTHEA and a fraction of an aalthea
+    let reward = DecCoinAmount::new(
+        "aalthea".to_string(),
+        "1500000000000000000250000000000000000",
+        &denoms,
+    )
+    .unwrap();
+    assert_eq!(reward.base_amount, "1500000000000000000.250000000000000000");
+    // The legacy amount drops the last 14 digits of the encoding
+    assert_eq!(reward.amount, "15000000000000000002500.000000000000000000");
+    assert_eq!(reward.decimals, Some(18));
+    assert_eq!(
+        reward.display_amount.unwrap(),
+        "1.500000000000000000250000000000000000"
+    );
+
+    // Denoms without metadata keep their base amount
+    let reward = DecCoinAmount::new("ibc/ABC".to_string(), "2000000000000000000", &denoms).unwrap();
+    assert_eq!(reward.base_amount, "2.000000000000000000");
+    // Only staking rewards keep the legacy amount
+    assert_eq!(reward.amount, reward.base_amount);
+    assert_eq!(reward.display_amount, None);
+    let reward = DecCoinAmount::new("aalthea".to_string(), "99", &denoms).unwrap();
+    assert_eq!(reward.amount, "0.000000000000000000");
+    assert!(DecCoinAmount::new("ibc/ABC".to_string(), "1.5", &denoms).is_none());
+}
@@ -15,11 +15,12 @@ use deep_space::utils::decode_any;
 use deep_space::Contact;
 use log::{error, info};
 use prost_types::Any;
-use rocksdb::DB;
 use serde::{Deserialize, Serialize};
 
 use crate::althea::CACHE_DURATION;
 
+use super::cache::CosmosCaches;
+
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tokio::time::sleep;
@@ -175,11 +176,11 @@ impl ProposalInfo {
 }
 
 pub async fn fetch_proposals(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
 ) -> Result<Vec<ProposalInfo>, Box<dyn std::error::Error>> {
     info!("Fetching proposals");
-    if let Some(proposals) = get_cached_proposals(db) {
+    if let Some(proposals) = caches.proposals.get(PROPOSALS_CACHE_KEY) {
         return Ok(proposals);
     }
 
@@ -218,7 +219,9 @@ pub async fn fetch_proposals(
     }
 
     if !proposals.is_empty() {
-        cache_proposals(db, &proposals);
+        caches
+            .proposals
+            .insert(PROPOSALS_CACHE_KEY, proposals.clone());
     }
 
     info!(
@@ -228,59 +231,7 @@ pub async fn fetch_proposals(
     Ok(proposals)
 }
 
-fn get_cached_proposals(db: &rocksdb::DB) -> Option<Vec<ProposalInfo>> {
-    const PROPOSALS_CACHE_KEY: &[u8] = b"proposals";
-
-    match db.get(PROPOSALS_CACHE_KEY) {
-        Ok(Some(data)) => match serde_json::from_slice(&data) {
-            Ok(proposals) => {
-                let proposals: Vec<ProposalInfo> = proposals;
-                if proposals.is_empty() {
-                    let _ = db.delete(PROPOSALS_CACHE_KEY);
-                    return None;
-                }
-
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-
-                if now - proposals[0].last_updated < CACHE_DURATION {
-                    Some(proposals)
-                } else {
-                    let _ = db.delete(PROPOSALS_CACHE_KEY);
-                    None
-                }
-            }
-            Err(e) => {
-                error!("Failed to deserialize cached proposals: {}", e);
-                let _ = db.delete(PROPOSALS_CACHE_KEY);
-                None
-            }
-        },
-        Ok(None) => None,
-        Err(e) => {
-            error!("Failed to read from cache: {}", e);
-            None
-        }
-    }
-}
-
-fn cache_proposals(db: &rocksdb::DB, proposals: &[ProposalInfo]) {
-    const PROPOSALS_CACHE_KEY: &[u8] = b"proposals";
-
-    match serde_json::to_vec(proposals) {
-        Ok(encoded) => {
-            if let Err(e) = db.put(PROPOSALS_CACHE_KEY, encoded) {
-                error!("Failed to cache proposals: {}", e);
-            }
-        }
-        Err(e) => {
-            error!("Failed to serialize proposals: {}", e);
-            let _ = db.delete(PROPOSALS_CACHE_KEY);
-        }
-    }
-}
+pub const PROPOSALS_CACHE_KEY: &str = "proposals";
 
 fn decode_proposal_content(input: Any) -> Result<ProposalContent, Box<dyn std::error::Error>> {
     let type_url = input.type_url.clone();
@@ -511,11 +462,11 @@ impl From<Proposal> for ProposalInfo {
 }
 
 pub async fn fetch_proposals_filtered(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
     active_only: Option<bool>,
 ) -> Result<Vec<ProposalInfo>, Box<dyn std::error::Error>> {
-    let proposals = fetch_proposals(db, contact).await?;
+    let proposals = fetch_proposals(caches, contact).await?;
 
     Ok(match active_only {
         Some(true) => proposals.into_iter().filter(|p| p.is_active()).collect(),
@@ -524,13 +475,13 @@ pub async fn fetch_proposals_filtered(
     })
 }
 
-pub fn start_proposal_cache_refresh_task(db: Arc<DB>, contact: Contact) {
+pub fn start_proposal_cache_refresh_task(caches: Arc<CosmosCaches>, contact: Contact) {
     tokio::spawn(async move {
         loop {
             // Check if cache needs refresh
-            if get_cached_proposals(&db).is_none() {
+            if !caches.proposals.is_fresh(PROPOSALS_CACHE_KEY) {
                 info!("Proposal cache expired, refreshing...");
-                match fetch_proposals(&db, &contact).await {
+                match fetch_proposals(&caches, &contact).await {
                     Ok(_) => info!("Successfully refreshed proposal cache"),
                     Err(e) => error!("Failed to refresh proposal cache: {}", e),
                 }
@@ -1,4 +1,6 @@
+pub mod cache;
 pub mod delegations;
+pub mod denoms;
 pub mod governance;
 pub mod staking;
 pub mod validators;
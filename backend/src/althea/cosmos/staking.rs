@@ -3,73 +3,27 @@ use crate::althea::{abi_util::format_decimal_18, CACHE_DURATION};
 use deep_space::Contact;
 use log::{error, info};
 use rust_decimal::Decimal;
-use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde::Serialize;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Clone)]
-pub struct StakingInfo {
-    pub apr: String,
-    pub last_updated: u64,
-}
+use super::cache::CosmosCaches;
 
-// Separate serialization structs for cache vs API
-#[derive(Serialize, Deserialize)]
-struct StakingInfoCache {
+#[derive(Debug, Clone, Serialize)]
+pub struct StakingInfo {
     pub apr: String,
+    // Only used to know when the info was fetched, not part of the API response
+    #[serde(skip_serializing)]
     pub last_updated: u64,
 }
 
-#[derive(Serialize)]
-struct StakingInfoAPI {
-    pub apr: String,
-}
-
-// Implement custom serialization
-impl Serialize for StakingInfo {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
-        // For JSON serialization (API responses)
-        if serializer.is_human_readable() {
-            StakingInfoAPI {
-                apr: self.apr.clone(),
-            }
-            .serialize(serializer)
-        } else {
-            // For bincode serialization (cache)
-            StakingInfoCache {
-                apr: self.apr.clone(),
-                last_updated: self.last_updated,
-            }
-            .serialize(serializer)
-        }
-    }
-}
-
-// Implement custom deserialization
-impl<'de> Deserialize<'de> for StakingInfo {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: Deserializer<'de>,
-    {
-        // Always deserialize as cache format
-        let cache = StakingInfoCache::deserialize(deserializer)?;
-        Ok(StakingInfo {
-            apr: cache.apr,
-            last_updated: cache.last_updated,
-        })
-    }
-}
-
 pub async fn fetch_staking_info(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
 ) -> Result<StakingInfo, Box<dyn std::error::Error>> {
     info!("Fetching staking info");
-    let cached = get_cached_staking_info(db);
+    let cached = caches.staking_info.get(STAKING_INFO_CACHE_KEY);
     if let Some(info) = cached {
         return Ok(info);
     }
@@ -100,9 +54,9 @@ pub async fn fetch_staking_info(
             .as_secs(),
     };
 
-    if let Err(e) = cache_staking_info(db, &staking_info) {
-        error!("Failed to cache staking info: {}", e);
-    }
+    caches
+        .staking_info
+        .insert(STAKING_INFO_CACHE_KEY, staking_info.clone());
 
     Ok(staking_info)
 }
@@ -143,69 +97,14 @@ fn calculate_apr(annual_provisions: &str, bonded_tokens: &str) -> String {
     format!("{:.6}", apr)
 }
 
-fn get_cached_staking_info(db: &rocksdb::DB) -> Option<StakingInfo> {
-    let key = b"staking_info";
-
-    // Try to get the data from cache
-    let data = match db.get(key) {
-        Ok(Some(data)) if !data.is_empty() => data,
-        Ok(_) => {
-            // Empty or no data, clean up and return None
-            let _ = db.delete(key);
-            return None;
-        }
-        Err(e) => {
-            error!("Failed to read from cache: {}", e);
-            return None;
-        }
-    };
-
-    // Try to deserialize
-    match bincode::deserialize::<StakingInfo>(&data) {
-        Ok(info) => {
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            if now - info.last_updated < CACHE_DURATION {
-                Some(info)
-            } else {
-                // Cache expired, clean up
-                let _ = db.delete(key);
-                None
-            }
-        }
-        Err(e) => {
-            error!("Failed to deserialize cache: {}", e);
-            // Invalid cache data, clean up
-            let _ = db.delete(key);
-            None
-        }
-    }
-}
-
-fn cache_staking_info(
-    db: &rocksdb::DB,
-    info: &StakingInfo,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let key = b"staking_info";
-
-    // Serialize first to validate the data
-    let encoded = bincode::serialize(info)?;
-
-    // Only clear existing cache and write if serialization succeeded
-    db.put(key, encoded)?;
-
-    Ok(())
-}
+pub const STAKING_INFO_CACHE_KEY: &str = "staking_info";
 
-pub fn start_staking_info_cache_refresh_task(db: Arc<rocksdb::DB>, contact: Contact) {
+pub fn start_staking_info_cache_refresh_task(caches: Arc<CosmosCaches>, contact: Contact) {
     tokio::spawn(async move {
         loop {
-            if get_cached_staking_info(&db).is_none() {
+            if !caches.staking_info.is_fresh(STAKING_INFO_CACHE_KEY) {
                 info!("Staking info cache expired, refreshing...");
-                match fetch_staking_info(&db, &contact).await {
+                match fetch_staking_info(&caches, &contact).await {
                     Ok(_) => info!("Successfully refreshed staking info cache"),
                     Err(e) => error!("Failed to refresh staking info cache: {}", e),
                 }
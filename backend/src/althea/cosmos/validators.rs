@@ -5,11 +5,12 @@ use cosmos_sdk_proto_althea::cosmos::base::query::v1beta1::PageRequest;
 use cosmos_sdk_proto_althea::cosmos::staking::v1beta1::{QueryValidatorsRequest, Validator};
 use deep_space::Contact;
 use log::{error, info};
-use rocksdb::DB;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use super::cache::CosmosCaches;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ValidatorInfo {
     pub operator_address: String,
@@ -36,11 +37,11 @@ pub struct ValidatorDescription {
 }
 
 pub async fn fetch_validators(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
 ) -> Result<Vec<ValidatorInfo>, Box<dyn std::error::Error>> {
     info!("Fetching validators");
-    let cached = get_cached_validators(db);
+    let cached = caches.validators.get(VALIDATORS_CACHE_KEY);
     if let Some(validators) = cached {
         return Ok(validators);
     }
@@ -67,7 +68,9 @@ pub async fn fetch_validators(
         b_tokens.cmp(&a_tokens)
     });
 
-    cache_validators(db, &all_validators);
+    caches
+        .validators
+        .insert(VALIDATORS_CACHE_KEY, all_validators.clone());
     info!(
         "Successfully fetched and stored {} validators",
         all_validators.len()
@@ -75,32 +78,7 @@ pub async fn fetch_validators(
     Ok(all_validators)
 }
 
-fn get_cached_validators(db: &rocksdb::DB) -> Option<Vec<ValidatorInfo>> {
-    let key = b"validators";
-    match db.get(key).unwrap() {
-        Some(data) => {
-            let validators: Vec<ValidatorInfo> = bincode::deserialize(&data).unwrap();
-            let now = SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs();
-
-            // Cache for 5 minutes
-            if now - validators[0].last_updated < CACHE_DURATION {
-                Some(validators)
-            } else {
-                None
-            }
-        }
-        None => None,
-    }
-}
-
-fn cache_validators(db: &rocksdb::DB, validators: &[ValidatorInfo]) {
-    const VALIDATORS_CACHE_KEY: &[u8] = b"validators";
-    let encoded = bincode::serialize(validators).unwrap();
-    db.put(VALIDATORS_CACHE_KEY, encoded).unwrap();
-}
+pub const VALIDATORS_CACHE_KEY: &str = "validators";
 
 impl From<Validator> for ValidatorInfo {
     fn from(v: Validator) -> Self {
@@ -145,11 +123,11 @@ impl ValidatorInfo {
 }
 
 pub async fn fetch_validators_filtered(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
     active_only: Option<bool>,
 ) -> Result<Vec<ValidatorInfo>, Box<dyn std::error::Error>> {
-    let validators = fetch_validators(db, contact).await?;
+    let validators = fetch_validators(caches, contact).await?;
 
     Ok(match active_only {
         Some(true) => validators.into_iter().filter(|v| v.is_active()).collect(),
@@ -158,13 +136,13 @@ pub async fn fetch_validators_filtered(
     })
 }
 
-pub fn start_validator_cache_refresh_task(db: Arc<DB>, contact: Contact) {
+pub fn start_validator_cache_refresh_task(caches: Arc<CosmosCaches>, contact: Contact) {
     tokio::spawn(async move {
         loop {
             // Check if cache needs refresh
-            if get_cached_validators(&db).is_none() {
+            if !caches.validators.is_fresh(VALIDATORS_CACHE_KEY) {
                 info!("Validator cache expired, refreshing...");
-                match fetch_validators(&db, &contact).await {
+                match fetch_validators(&caches, &contact).await {
                     Ok(_) => info!("Successfully refreshed validator cache"),
                     Err(e) => error!("Failed to refresh validator cache: {}", e),
                 }
@@ -177,11 +155,11 @@ pub fn start_validator_cache_refresh_task(db: Arc<DB>, contact: Contact) {
 }
 
 pub async fn fetch_validator_by_address(
-    db: &rocksdb::DB,
+    caches: &CosmosCaches,
     contact: &deep_space::Contact,
     operator_address: &str,
 ) -> Result<Option<ValidatorInfo>, Box<dyn std::error::Error>> {
-    let validators = fetch_validators(db, contact).await?;
+    let validators = fetch_validators(caches, contact).await?;
     Ok(validators
         .into_iter()
         .find(|v| v.operator_address == operator_address))
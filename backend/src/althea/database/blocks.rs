@@ -0,0 +1,102 @@
+use clarity::Uint256;
+use log::debug;
+use num_traits::ToPrimitive;
+
+use crate::althea::database::storage::{Direction, Storage};
+
+/// Block timestamps sampled by the indexer, used to place events (which only know their block) in time
+pub const BLOCK_TIME_PREFIX: &str = "block-time_";
+// Blocks are zero padded so that the keys sort numerically, which the interpolation below relies on
+fn block_time_key(block: Uint256) -> String {
+    format!(
+        "{}{:020}",
+        BLOCK_TIME_PREFIX,
+        block.to_u64().unwrap_or(u64::MAX)
+    )
+}
+
+fn parse_block_time_entry(k: &[u8], v: &[u8]) -> Option<(u64, u64)> {
+    let block = std::str::from_utf8(&k[BLOCK_TIME_PREFIX.len()..])
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+    let time = u64::from_be_bytes(v.try_into().ok()?);
+    Some((block, time))
+}
+
+/// Stores the unix timestamp of `block`
+pub fn save_block_time(db: &impl Storage, block: Uint256, timestamp: u64) {
+    let k = block_time_key(block);
+    debug!("Saving block time {} to key {}", timestamp, k);
+    db.put(BLOCK_TIME_PREFIX, k.as_bytes(), &timestamp.to_be_bytes())
+        .unwrap();
+}
+
+/// The earliest block with a sampled time, blocks before it can not be placed in time
+pub fn first_sampled_block(db: &impl Storage) -> Option<u64> {
+    let (k, v) = db
+        .prefix_scan(BLOCK_TIME_PREFIX, BLOCK_TIME_PREFIX.as_bytes())
+        .next()?
+        .ok()?;
+    parse_block_time_entry(&k, &v).map(|(block, _)| block)
+}
+
+/// Gets the unix timestamp of `block`, interpolating between the nearest known samples when the block itself
+/// was not sampled. Blocks after the last sample are given the last sample's time, and blocks before the
+/// first sample have no known time.
+pub fn get_block_time(db: &impl Storage, block: Uint256) -> Option<u64> {
+    let target = block.to_u64()?;
+    let k = block_time_key(block);
+    let prefix = BLOCK_TIME_PREFIX.as_bytes();
+
+    let mut before = db.scan_from(BLOCK_TIME_PREFIX, k.as_bytes(), Direction::Reverse);
+    let prev = match before.next() {
+        Some(Ok((k, v))) if k.starts_with(prefix) => parse_block_time_entry(&k, &v),
+        _ => None,
+    }?;
+    if prev.0 == target {
+        return Some(prev.1);
+    }
+
+    let mut after = db.scan_from(BLOCK_TIME_PREFIX, k.as_bytes(), Direction::Forward);
+    let next = match after.next() {
+        Some(Ok((k, v))) if k.starts_with(prefix) => parse_block_time_entry(&k, &v),
+        _ => None,
+    };
+    Some(match next {
+        Some(next) => interpolate_block_time(prev, next, target),
+        None => prev.1,
+    })
+}
+
+// Linearly interpolates the time of `block` between the (block, time) samples `prev` and `next`
+fn interpolate_block_time(prev: (u64, u64), next: (u64, u64), block: u64) -> u64 {
+    if next.0 <= prev.0 || next.1 <= prev.1 {
+        return prev.1;
+    }
+    let elapsed_blocks = (block - prev.0) as u128;
+    let span_blocks = (next.0 - prev.0) as u128;
+    let span_time = (next.1 - prev.1) as u128;
+    prev.1 + (span_time * elapsed_blocks / span_blocks) as u64
+}
+
+#[test]
+fn test_first_sampled_block() {
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    assert_eq!(first_sampled_block(&db), None);
+    save_block_time(&db, 500u32.into(), 5000);
+    save_block_time(&db, 90u32.into(), 900);
+    assert_eq!(first_sampled_block(&db), Some(90));
+    assert_eq!(get_block_time(&db, 50u32.into()), None);
+}
+
+#[test]
+fn test_interpolate_block_time() {
+    assert_eq!(interpolate_block_time((100, 1000), (200, 1500), 150), 1250);
+    assert_eq!(interpolate_block_time((100, 1000), (200, 1500), 100), 1000);
+    assert_eq!(interpolate_block_time((100, 1000), (101, 1006), 101), 1006);
+    // Out of order samples fall back to the earlier time
+    assert_eq!(interpolate_block_time((100, 1000), (200, 900), 150), 1000);
+}
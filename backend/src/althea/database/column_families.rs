@@ -0,0 +1,199 @@
+// This file lists the column family each kind of record is stored in, along with the compaction and memory settings of each.
+// Families are named after the key prefix of their records, and the keys keep that prefix, so a family can be scanned with
+// the same prefixes that were used before the records were split out of the default family.
+
+use std::path::Path;
+
+use rocksdb::{
+    BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor, DBCompressionType, Options,
+    WriteBufferManager, DB,
+};
+
+use super::{
+    blocks::BLOCK_TIME_PREFIX,
+    curve::{LATEST_CURVE_KEY, LATEST_LIQUIDITY_KEY, LATEST_PRICE_KEY},
+    pools::{INIT_POOL_PREFIX, POOL_TEMPLATE_PREFIX, REVISION_PREFIX, SWAP_PREFIX},
+    positions::{
+        ambient::{BURN_AMBIENT_PREFIX, MINT_AMBIENT_PREFIX},
+        index::ACTIVE_POSITIONS_PREFIX,
+        knockout::{BURN_KNOCKOUT_PREFIX, MINT_KNOCKOUT_PREFIX, WITHDRAW_KNOCKOUT_PREFIX},
+        ranged::{BURN_RANGED_PREFIX, HARVEST_PREFIX, MINT_RANGED_PREFIX},
+    },
+    tokens::TOKEN_METADATA_PREFIX,
+    tracking::{
+        candles::CANDLE_PREFIX, history::POOL_SNAPSHOT_PREFIX, knockouts::KNOCKOUT_CROSS_PREFIX,
+        DIRTY_POOL_PREFIX, TRACKED_POOL_PREFIX,
+    },
+    transactions::POOL_TX_PREFIX,
+};
+
+/// How a family's records are written and read, which decides its settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FamilyProfile {
+    // Chain events, written once and read back with prefix scans, the bulk of the database
+    Events,
+    // Records derived from the events which are rewritten as new events arrive and read by range or prefix
+    Derived,
+    // Small records which are overwritten in place and read by key
+    State,
+}
+
+/// A column family and the profile of the records stored in it
+#[derive(Debug, Clone, Copy)]
+pub struct Family {
+    pub name: &'static str,
+    pub profile: FamilyProfile,
+}
+
+const fn family(name: &'static str, profile: FamilyProfile) -> Family {
+    Family { name, profile }
+}
+
+/// Every record type's family. The latest searched block and syncing flag remain in the default family
+pub const FAMILIES: [Family; 25] = [
+    family(INIT_POOL_PREFIX, FamilyProfile::State),
+    family(POOL_TEMPLATE_PREFIX, FamilyProfile::State),
+    family(SWAP_PREFIX, FamilyProfile::Events),
+    family(REVISION_PREFIX, FamilyProfile::Events),
+    family(MINT_RANGED_PREFIX, FamilyProfile::Events),
+    family(BURN_RANGED_PREFIX, FamilyProfile::Events),
+    family(HARVEST_PREFIX, FamilyProfile::Events),
+    family(MINT_AMBIENT_PREFIX, FamilyProfile::Events),
+    family(BURN_AMBIENT_PREFIX, FamilyProfile::Events),
+    family(MINT_KNOCKOUT_PREFIX, FamilyProfile::Events),
+    family(BURN_KNOCKOUT_PREFIX, FamilyProfile::Events),
+    family(WITHDRAW_KNOCKOUT_PREFIX, FamilyProfile::Events),
+    family(POOL_TX_PREFIX, FamilyProfile::Events),
+    family(BLOCK_TIME_PREFIX, FamilyProfile::Derived),
+    family(CANDLE_PREFIX, FamilyProfile::Derived),
+    family(POOL_SNAPSHOT_PREFIX, FamilyProfile::Derived),
+    family(ACTIVE_POSITIONS_PREFIX, FamilyProfile::Derived),
+    family(KNOCKOUT_CROSS_PREFIX, FamilyProfile::Derived),
+    family(TRACKED_POOL_PREFIX, FamilyProfile::State),
+    family(DIRTY_POOL_PREFIX, FamilyProfile::State),
+    family(LATEST_CURVE_KEY, FamilyProfile::State),
+    family(LATEST_PRICE_KEY, FamilyProfile::State),
+    family(LATEST_LIQUIDITY_KEY, FamilyProfile::State),
+    family(TOKEN_METADATA_PREFIX, FamilyProfile::State),
+    family(rocksdb::DEFAULT_COLUMN_FAMILY_NAME, FamilyProfile::State),
+];
+
+const MB: usize = 1024 * 1024;
+
+/// The default memory budget of the block cache and write buffers of every family together, in megabytes
+pub const DEFAULT_DB_MEMORY_MB: usize = 1024;
+
+/// The block cache and write buffer limit shared by every family of a database, so that its memory use is bounded by a
+/// single budget however many families there are
+#[derive(Clone)]
+pub struct DbMemory {
+    cache: Cache,
+    write_buffers: WriteBufferManager,
+}
+
+impl DbMemory {
+    /// Splits `budget_mb` megabytes between the block cache and the write buffers. The write buffers are charged to the
+    /// cache, so together they stay within the budget, and are flushed early once they use half of it
+    pub fn new(budget_mb: usize) -> Self {
+        let cache = Cache::new_lru_cache(budget_mb * MB);
+        let write_buffers = WriteBufferManager::new_write_buffer_manager_with_cache(
+            budget_mb * MB / 2,
+            false,
+            cache.clone(),
+        );
+        DbMemory {
+            cache,
+            write_buffers,
+        }
+    }
+}
+
+impl FamilyProfile {
+    /// The options of a family with this profile, whose blocks are cached in the shared `memory`
+    pub fn options(&self, memory: &DbMemory) -> Options {
+        let (write_buffer, compressed) = match self {
+            FamilyProfile::Events => (64 * MB, true),
+            FamilyProfile::Derived => (32 * MB, true),
+            FamilyProfile::State => (16 * MB, false),
+        };
+        let mut block_options = BlockBasedOptions::default();
+        block_options.set_block_cache(&memory.cache);
+        block_options.set_bloom_filter(10.0, false);
+
+        let mut options = Options::default();
+        options.set_block_based_table_factory(&block_options);
+        options.set_write_buffer_size(write_buffer);
+        options.set_write_buffer_manager(&memory.write_buffers);
+        if compressed {
+            // Events are never rewritten, so the lowest level holds nearly all of them and is worth compressing harder
+            options.set_compression_type(DBCompressionType::Lz4);
+            options.set_bottommost_compression_type(DBCompressionType::Zstd);
+            options.set_level_compaction_dynamic_level_bytes(true);
+        } else {
+            options.set_compression_type(DBCompressionType::None);
+        }
+        options
+    }
+}
+
+/// The descriptors to open the database with, one per family, all sharing `memory`
+pub fn family_descriptors(memory: &DbMemory) -> Vec<ColumnFamilyDescriptor> {
+    FAMILIES
+        .iter()
+        .map(|f| ColumnFamilyDescriptor::new(f.name, f.profile.options(memory)))
+        .collect()
+}
+
+/// The descriptors to open the database at `path` with, which are family_descriptors() plus any other family the database
+/// has, since RocksDB refuses to open a database without all of its families. Families this version does not know are
+/// opened with the default options and left untouched
+pub fn existing_family_descriptors(path: &Path, memory: &DbMemory) -> Vec<ColumnFamilyDescriptor> {
+    let existing = DB::list_cf(&Options::default(), path).unwrap_or_default();
+    let mut descriptors = family_descriptors(memory);
+    descriptors.extend(
+        existing
+            .iter()
+            .filter(|name| !FAMILIES.iter().any(|f| f.name == name.as_str()))
+            .map(|name| ColumnFamilyDescriptor::new(name, Options::default())),
+    );
+    descriptors
+}
+
+/// Gets the handle of the family named `name`, which is always one of FAMILIES' names
+pub fn cf<'a>(db: &'a rocksdb::DB, name: &str) -> &'a ColumnFamily {
+    db.cf_handle(name)
+        .unwrap_or_else(|| panic!("Column family {} was not opened", name))
+}
+
+/// The family a key from the default family belongs in, by the longest family name it starts with. Used to move
+/// databases created before the records were split into families
+pub fn family_for_key(key: &[u8]) -> Option<&'static str> {
+    FAMILIES
+        .iter()
+        .filter(|f| f.name != rocksdb::DEFAULT_COLUMN_FAMILY_NAME)
+        .filter(|f| key.starts_with(f.name.as_bytes()))
+        .max_by_key(|f| f.name.len())
+        .map(|f| f.name)
+}
+
+#[test]
+fn test_family_for_key() {
+    assert_eq!(family_for_key(b"swap_0xabc_1"), Some(SWAP_PREFIX));
+    assert_eq!(
+        family_for_key(b"block-time_00000000000000000001"),
+        Some(BLOCK_TIME_PREFIX)
+    );
+    assert_eq!(
+        family_for_key(b"price0xabc_0xdef_36000"),
+        Some(LATEST_PRICE_KEY)
+    );
+    // Cosmos responses are no longer stored
+    assert_eq!(family_for_key(b"validators"), None);
+    // The latest searched block and syncing flag stay behind
+    assert_eq!(family_for_key(b"block"), None);
+    assert_eq!(family_for_key(b"syncing"), None);
+    // Every family name is unique
+    for (i, f) in FAMILIES.iter().enumerate() {
+        assert!(FAMILIES[i + 1..].iter().all(|g| g.name != f.name));
+    }
+}
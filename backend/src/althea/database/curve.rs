@@ -3,6 +3,8 @@ use clarity::Uint256;
 use log::debug;
 
 use crate::althea::ambient::croc_query::CurveState;
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
 
 /// CrocQuery queryCurve()
 pub const LATEST_CURVE_KEY: &str = "curve";
@@ -10,23 +12,23 @@ pub fn curve_key(base: Address, quote: Address, pool_idx: Uint256) -> String {
     format!("{}{}_{}_{}", LATEST_CURVE_KEY, base, quote, pool_idx)
 }
 pub fn get_curve(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<CurveState> {
     let k = curve_key(base, quote, pool_idx);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(LATEST_CURVE_KEY, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         debug!("No curve at key {}", k);
         return None;
     }
-    let decoded: CurveState = bincode::deserialize(&v.unwrap()).unwrap();
+    let decoded: CurveState = deserialize_record(&v.unwrap()).unwrap();
     Some(decoded)
 }
 pub fn save_curve(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     curve: CurveState,
     base: Address,
     quote: Address,
@@ -34,8 +36,8 @@ pub fn save_curve(
 ) {
     debug!("Saving curve {:?}", curve);
     let k = curve_key(base, quote, pool_idx);
-    let v = bincode::serialize(&curve).unwrap();
-    db.put(k.as_bytes(), v).unwrap();
+    let v = serialize_record(&curve).unwrap();
+    db.put(LATEST_CURVE_KEY, k.as_bytes(), &v).unwrap();
 }
 
 /// CrocQuery queryPrice()
@@ -44,13 +46,13 @@ pub fn price_key(base: Address, quote: Address, pool_idx: Uint256) -> String {
     format!("{}{}_{}_{}", LATEST_PRICE_KEY, base, quote, pool_idx)
 }
 pub fn get_price(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<u128> {
     let k = price_key(base, quote, pool_idx);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(LATEST_PRICE_KEY, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         debug!("No price at key {}", k);
@@ -60,11 +62,35 @@ pub fn get_price(
     let decoded = u128::from_be_bytes(v.try_into().unwrap());
     Some(decoded)
 }
-pub fn save_price(db: &rocksdb::DB, price: u128, base: Address, quote: Address, pool_idx: Uint256) {
+/// Gets the latest price as a float square root price (of base per quote), converted from the stored Q64.64 value
+pub fn get_root_price(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> Option<f64> {
+    get_price(db, base, quote, pool_idx).map(|p| p as f64 / 2.0f64.powi(64))
+}
+/// Gets the latest price (of base per quote), the square of the root price
+pub fn get_latest_price(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> Option<f64> {
+    get_root_price(db, base, quote, pool_idx).map(|p| p * p)
+}
+pub fn save_price(
+    db: &impl Storage,
+    price: u128,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) {
     debug!("Saving price {:?}", price);
     let k = price_key(base, quote, pool_idx);
     let v = price.to_be_bytes();
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(LATEST_PRICE_KEY, k.as_bytes(), &v).unwrap();
 }
 
 /// CrocQuery queryLiquidity()
@@ -73,13 +99,13 @@ pub fn liquidity_key(base: Address, quote: Address, pool_idx: Uint256) -> String
     format!("{}{}_{}_{}", LATEST_LIQUIDITY_KEY, base, quote, pool_idx)
 }
 pub fn get_liquidity(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<u128> {
     let k = liquidity_key(base, quote, pool_idx);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(LATEST_LIQUIDITY_KEY, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         debug!("No price at key {}", k);
@@ -90,7 +116,7 @@ pub fn get_liquidity(
     Some(decoded)
 }
 pub fn save_liquidity(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     liquidity: u128,
     base: Address,
     quote: Address,
@@ -99,5 +125,5 @@ pub fn save_liquidity(
     debug!("Saving liquidity {:?}", liquidity);
     let k = liquidity_key(base, quote, pool_idx);
     let v = liquidity.to_be_bytes();
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(LATEST_LIQUIDITY_KEY, k.as_bytes(), &v).unwrap();
 }
@@ -1,16 +1,25 @@
 use clarity::Uint256;
 use log::debug;
 
+pub mod blocks;
+pub mod column_families;
 pub mod curve;
 pub mod pools;
 pub mod positions;
+pub mod schema;
+pub mod storage;
+pub mod tokens;
 pub mod tracking;
+pub mod transactions;
 
 use super::InitPoolEvent;
+use storage::{Storage, DEFAULT_FAMILY};
 
 pub const LATEST_SEARCHED_BLOCK_KEY: &str = "block";
-pub fn get_latest_searched_block(db: &rocksdb::DB) -> Option<Uint256> {
-    let v = db.get(LATEST_SEARCHED_BLOCK_KEY.as_bytes()).unwrap();
+pub fn get_latest_searched_block(db: &impl Storage) -> Option<Uint256> {
+    let v = db
+        .get(DEFAULT_FAMILY, LATEST_SEARCHED_BLOCK_KEY.as_bytes())
+        .unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         debug!("No latest searched block");
@@ -18,15 +27,16 @@ pub fn get_latest_searched_block(db: &rocksdb::DB) -> Option<Uint256> {
     }
     Some(Uint256::from_be_bytes(&v.unwrap()))
 }
-pub fn save_latest_searched_block(db: &rocksdb::DB, block: Uint256) {
+pub fn save_latest_searched_block(db: &impl Storage, block: Uint256) {
     debug!("Saving latest searched block {}", block);
     let value = block.to_be_bytes();
-    db.put(LATEST_SEARCHED_BLOCK_KEY.as_bytes(), value).unwrap();
+    db.put(DEFAULT_FAMILY, LATEST_SEARCHED_BLOCK_KEY.as_bytes(), &value)
+        .unwrap();
 }
 
 pub const SYNCING_KEY: &str = "syncing";
-pub fn get_syncing(db: &rocksdb::DB) -> bool {
-    let v = db.get(SYNCING_KEY.as_bytes()).unwrap();
+pub fn get_syncing(db: &impl Storage) -> bool {
+    let v = db.get(DEFAULT_FAMILY, SYNCING_KEY.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         debug!("No syncing key");
@@ -35,8 +45,17 @@ pub fn get_syncing(db: &rocksdb::DB) -> bool {
     v.unwrap()[0] == 1
 }
 
-pub fn save_syncing(db: &rocksdb::DB, syncing: bool) {
+pub fn save_syncing(db: &impl Storage, syncing: bool) {
     debug!("Saving syncing {}", syncing);
     let value = if syncing { vec![1] } else { vec![0] };
-    db.put(SYNCING_KEY.as_bytes(), value).unwrap();
+    db.put(DEFAULT_FAMILY, SYNCING_KEY.as_bytes(), &value)
+        .unwrap();
+}
+
+/// True if there is at least one key in the family named `prefix`
+pub fn has_prefix(db: &impl Storage, prefix: &str) -> bool {
+    db.prefix_scan(prefix, prefix.as_bytes())
+        .flatten()
+        .next()
+        .is_some_and(|(k, _)| k.starts_with(prefix.as_bytes()))
 }
@@ -6,6 +6,8 @@ use serde::Serialize;
 
 use crate::althea::ambient::pools::PoolRevisionEvent;
 use crate::althea::ambient::swap::SwapEvent;
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
 
 use super::InitPoolEvent;
 
@@ -16,17 +18,17 @@ fn init_pool_key(base: Address, quote: Address, pool_idx: Uint256) -> String {
 
 // Gets all known InitPool events from the database
 // Note: these are the pools as of the InitPool event, not the current state
-pub fn get_init_pools(db: &rocksdb::DB) -> Vec<InitPoolEvent> {
+pub fn get_init_pools(db: &impl Storage) -> Vec<InitPoolEvent> {
     let prefix = INIT_POOL_PREFIX.as_bytes();
     let mut pools = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(INIT_POOL_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let pool: InitPoolEvent = bincode::deserialize(&v).unwrap();
+                let pool: InitPoolEvent = deserialize_record(&v).unwrap();
                 pools.push(pool);
             }
             Err(_) => break,
@@ -38,21 +40,27 @@ pub fn get_init_pools(db: &rocksdb::DB) -> Vec<InitPoolEvent> {
 // Gets a single InitPool event from the database by its (base, quote, pool index) triple, returns none if it does not exist
 // Note: this is the pool as of the InitPool event, not the current state
 pub fn get_init_pool(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<InitPoolEvent> {
     let k = init_pool_key(base, quote, pool_idx);
-    let v = db.get(k.as_bytes()).unwrap()?;
+    let v = db.get(INIT_POOL_PREFIX, k.as_bytes()).unwrap()?;
 
-    Some(bincode::deserialize(&v).expect("Invalid InitPool stored in database?"))
+    Some(deserialize_record(&v).expect("Invalid InitPool stored in database?"))
 }
 
-pub fn save_init_pool(db: &rocksdb::DB, pool: InitPoolEvent) {
+pub fn save_init_pool(db: &impl Storage, pool: InitPoolEvent) {
     let k = init_pool_key(pool.base, pool.quote, pool.pool_idx);
-    let v = bincode::serialize(&pool).unwrap();
-    db.put(k.as_bytes(), &v).unwrap();
+    let v = serialize_record(&pool).unwrap();
+    db.put(INIT_POOL_PREFIX, k.as_bytes(), &v).unwrap();
+}
+
+/// Deletes a pool's InitPool event, after which the pool is no longer listed or tracked
+pub fn delete_init_pool(db: &impl Storage, base: Address, quote: Address, pool_idx: Uint256) {
+    let k = init_pool_key(base, quote, pool_idx);
+    db.delete(INIT_POOL_PREFIX, k.as_bytes()).unwrap();
 }
 
 pub const POOL_TEMPLATE_PREFIX: &str = "template_";
@@ -71,28 +79,30 @@ pub struct Pool {
     pub oracle_flags: u8,
 }
 // Gets a known template from the database by its pool index, returns none if it does not exist
-pub fn get_pool_template(db: &rocksdb::DB, pool_idx: Uint256) -> Option<Pool> {
-    let v = db.get(pool_template_key(pool_idx).as_bytes()).unwrap();
+pub fn get_pool_template(db: &impl Storage, pool_idx: Uint256) -> Option<Pool> {
+    let v = db
+        .get(POOL_TEMPLATE_PREFIX, pool_template_key(pool_idx).as_bytes())
+        .unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
-pub fn save_pool_template(db: &rocksdb::DB, pool_idx: Uint256, template: Pool) {
+pub fn save_pool_template(db: &impl Storage, pool_idx: Uint256, template: Pool) {
     let k = pool_template_key(pool_idx);
     debug!("Saving pool template to key {}", k);
-    let v = bincode::serialize(&template).unwrap();
+    let v = serialize_record(&template).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(POOL_TEMPLATE_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const SWAP_PREFIX: &str = "swap_";
-fn swap_user_prefix(user: Address) -> String {
+pub fn swap_user_prefix(user: Address) -> String {
     format!("{}{}", SWAP_PREFIX, user)
 }
-fn swap_user_pool_prefix(
+pub fn swap_user_pool_prefix(
     user: Address,
     base: Address,
     quote: Address,
@@ -131,7 +141,7 @@ fn swap_key(
 
 // Gets a single Swap event from `db` by the other arguments, returns none if it does not exist
 pub fn get_swap(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     block: Uint256,
     base: Address,
@@ -147,26 +157,26 @@ pub fn get_swap(
         block,
         index.unwrap_or_default(),
     );
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(SWAP_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known Swap events from the database
-pub fn get_all_swap(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<SwapEvent> {
+pub fn get_all_swap(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<SwapEvent> {
     let prefix = prefix.unwrap_or_else(|| SWAP_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(SWAP_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let pool: SwapEvent = bincode::deserialize(&v).unwrap();
+                let pool: SwapEvent = deserialize_record(&v).unwrap();
                 events.push(pool);
             }
             Err(_) => break,
@@ -176,7 +186,7 @@ pub fn get_all_swap(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<SwapEvent> {
 }
 
 pub fn get_all_swap_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<SwapEvent> {
@@ -188,7 +198,7 @@ pub fn get_all_swap_after_block(
         .collect()
 }
 
-pub fn save_swap(db: &rocksdb::DB, swap: SwapEvent) {
+pub fn save_swap(db: &impl Storage, swap: SwapEvent) {
     let k = swap_key(
         swap.user,
         swap.base,
@@ -198,9 +208,9 @@ pub fn save_swap(db: &rocksdb::DB, swap: SwapEvent) {
         swap.index,
     );
     debug!("Saving SwapEvent to key {}", k);
-    let v = bincode::serialize(&swap).unwrap();
+    let v = serialize_record(&swap).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(SWAP_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const REVISION_PREFIX: &str = "revision_";
@@ -231,7 +241,7 @@ fn revision_key(
 
 // Gets a single PoolRevision event from `db` by the other arguments, returns none if it does not exist
 pub fn get_revision(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     block: Uint256,
     base: Address,
     quote: Address,
@@ -239,26 +249,26 @@ pub fn get_revision(
     index: Option<Uint256>,
 ) -> Option<PoolRevisionEvent> {
     let k = revision_key(base, quote, pool_idx, block, index.unwrap_or_default());
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(REVISION_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known PoolRevision events from the database
-pub fn get_all_revision(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<PoolRevisionEvent> {
+pub fn get_all_revision(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<PoolRevisionEvent> {
     let prefix = prefix.unwrap_or_else(|| REVISION_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(REVISION_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let pool: PoolRevisionEvent = bincode::deserialize(&v).unwrap();
+                let pool: PoolRevisionEvent = deserialize_record(&v).unwrap();
                 events.push(pool);
             }
             Err(_) => break,
@@ -268,7 +278,7 @@ pub fn get_all_revision(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<PoolRevi
 }
 
 pub fn get_all_revision_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<PoolRevisionEvent> {
@@ -280,7 +290,7 @@ pub fn get_all_revision_after_block(
         .collect()
 }
 
-pub fn save_revision(db: &rocksdb::DB, revision: PoolRevisionEvent) {
+pub fn save_revision(db: &impl Storage, revision: PoolRevisionEvent) {
     let k = revision_key(
         revision.base,
         revision.quote,
@@ -289,7 +299,7 @@ pub fn save_revision(db: &rocksdb::DB, revision: PoolRevisionEvent) {
         revision.index,
     );
     debug!("Saving PoolRevision to key {}", k);
-    let v = bincode::serialize(&revision).unwrap();
+    let v = serialize_record(&revision).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(REVISION_PREFIX, k.as_bytes(), &v).unwrap();
 }
@@ -2,6 +2,9 @@ use clarity::Address;
 use clarity::Uint256;
 use log::debug;
 
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
+
 use super::super::super::ambient::positions::{BurnAmbientEvent, MintAmbientEvent};
 
 pub const MINT_AMBIENT_PREFIX: &str = "mint-ambient_";
@@ -52,7 +55,7 @@ pub fn mint_ambient_key(
 
 // Gets a single MintAmbient event from `db` by the other arguments, returns none if it does not exist
 pub fn get_mint_ambient(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -61,26 +64,26 @@ pub fn get_mint_ambient(
     index: Uint256,
 ) -> Option<MintAmbientEvent> {
     let k = mint_ambient_key(user, base, quote, pool_idx, block, index);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(MINT_AMBIENT_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known MintAmbient events from the database
-pub fn get_all_mint_ambient(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<MintAmbientEvent> {
+pub fn get_all_mint_ambient(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<MintAmbientEvent> {
     let prefix = prefix.unwrap_or_else(|| MINT_AMBIENT_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(MINT_AMBIENT_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: MintAmbientEvent = bincode::deserialize(&v).unwrap();
+                let event: MintAmbientEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -90,7 +93,7 @@ pub fn get_all_mint_ambient(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<Mint
 }
 
 pub fn get_all_mint_ambient_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<MintAmbientEvent> {
@@ -102,7 +105,7 @@ pub fn get_all_mint_ambient_after_block(
         .collect()
 }
 
-pub fn save_mint_ambient(db: &rocksdb::DB, mae: MintAmbientEvent) {
+pub fn save_mint_ambient(db: &impl Storage, mae: MintAmbientEvent) {
     let k = mint_ambient_key(
         mae.user,
         mae.base,
@@ -112,9 +115,9 @@ pub fn save_mint_ambient(db: &rocksdb::DB, mae: MintAmbientEvent) {
         mae.index,
     );
     debug!("Saving MintAmbientEvent to key {}", k);
-    let v = bincode::serialize(&mae).unwrap();
+    let v = serialize_record(&mae).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(MINT_AMBIENT_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const BURN_AMBIENT_PREFIX: &str = "burn-ambient_";
@@ -164,7 +167,7 @@ pub fn burn_ambient_key(
 }
 // Gets a single BurnAmbient event from `db` by the other arguments, returns none if it does not exist
 pub fn get_burn_ambient(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -173,26 +176,26 @@ pub fn get_burn_ambient(
     index: Uint256,
 ) -> Option<BurnAmbientEvent> {
     let k = burn_ambient_key(user, base, quote, pool_idx, block, index);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(BURN_AMBIENT_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known BurnAmbient events from the database
-pub fn get_all_burn_ambient(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<BurnAmbientEvent> {
+pub fn get_all_burn_ambient(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<BurnAmbientEvent> {
     let prefix = prefix.unwrap_or_else(|| BURN_AMBIENT_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(BURN_AMBIENT_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: BurnAmbientEvent = bincode::deserialize(&v).unwrap();
+                let event: BurnAmbientEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -202,7 +205,7 @@ pub fn get_all_burn_ambient(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<Burn
 }
 
 pub fn get_all_burn_ambient_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<BurnAmbientEvent> {
@@ -213,7 +216,7 @@ pub fn get_all_burn_ambient_after_block(
         .cloned()
         .collect()
 }
-pub fn save_burn_ambient(db: &rocksdb::DB, bae: BurnAmbientEvent) {
+pub fn save_burn_ambient(db: &impl Storage, bae: BurnAmbientEvent) {
     let k = burn_ambient_key(
         bae.user,
         bae.base,
@@ -223,7 +226,7 @@ pub fn save_burn_ambient(db: &rocksdb::DB, bae: BurnAmbientEvent) {
         bae.index,
     );
     debug!("Saving BurnAmbientEvent to key {}", k);
-    let v = bincode::serialize(&bae).unwrap();
+    let v = serialize_record(&bae).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(BURN_AMBIENT_PREFIX, k.as_bytes(), &v).unwrap();
 }
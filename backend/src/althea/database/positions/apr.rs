@@ -0,0 +1,248 @@
+// This file estimates the fees earned by ranged and ambient positions from the pool's swap candles, and the APR they imply
+
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::althea::database::{
+    blocks::get_block_time,
+    curve::{get_liquidity, get_root_price},
+    storage::Storage,
+    tracking::{
+        candles::{get_candle_before, get_candles, Candle, CANDLE_PERIODS},
+        get_tracked_pool, tick_from_price, LiquidityBump,
+    },
+};
+
+use super::Position;
+
+/// The most candles read to estimate a position's fees, older positions are followed with wider candles
+const MAX_APR_CANDLES: u64 = 500;
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+
+/// The estimated earnings of a position since its most recent mint, or harvest for ranged positions
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PositionApr {
+    // Fees earned in each token's smallest units
+    pub base_fees: f64,
+    pub quote_fees: f64,
+    // Seconds since rewards began accruing, and how many of them the pool's price was within the position's range
+    pub duration: u64,
+    pub time_in_range: u64,
+    // The position's own liquidity, the liquidity equivalent of its fees, and the two combined
+    pub contributed_liq: f64,
+    pub reward_liq: f64,
+    pub post_liq: f64,
+    // The value of the fees relative to the value of the position, annualized over the duration
+    pub apr: f64,
+}
+
+/// Estimates the unclaimed fees earned by a ranged or ambient position since its rewards began accruing up to `now`.
+/// Each swap's fees (from the pool's fee rate) are split by the position's share of the liquidity active at the swap's price,
+/// when that price is within the position's range. Returns None for knockouts or if the pool's state is unknown
+pub fn get_position_apr(db: &impl Storage, position: &Position, now: u64) -> Option<PositionApr> {
+    let (base, quote, pool_idx, liq, range) = match position {
+        Position::Ranged(p) => (
+            p.base,
+            p.quote,
+            p.pool_idx,
+            p.liq,
+            Some((p.bid_tick, p.ask_tick)),
+        ),
+        Position::Ambient(p) => (p.base, p.quote, p.pool_idx, p.liq, None),
+        Position::Knockout(_) => return None,
+    };
+    let start = get_block_time(db, position.rewards_block())?;
+    let pool = get_tracked_pool(db, base, quote, pool_idx)?;
+    let root_price = get_root_price(db, base, quote, pool_idx)?;
+    let pool_liq = get_liquidity(db, base, quote, pool_idx)?.to_f64()?;
+    let current_tick = tick_from_price(root_price * root_price);
+
+    // The price as rewards began accruing, from the last candle before it
+    let period = apr_candle_period(now.saturating_sub(start));
+    let open_price = get_candle_before(db, base, quote, pool_idx, period, start)
+        .map_or(root_price * root_price, |c| c.close);
+    let candles = get_candles(db, base, quote, pool_idx, period, start, now);
+    let liq = liq as f64;
+    let earned = estimate_fees(&candles, open_price, start, now, range, liq, |tick| {
+        liquidity_at_tick(pool_liq, current_tick, &pool.bumps, tick)
+    });
+
+    let (position_base, position_quote) = position.token_amounts(root_price);
+    let price = root_price * root_price;
+    let duration = now.saturating_sub(start);
+    let value = position_base / price + position_quote;
+    let rewards = earned.base_fees / price + earned.quote_fees;
+    let apr = if value > 0.0 && duration > 0 {
+        rewards / value * SECONDS_PER_YEAR / duration as f64
+    } else {
+        0.0
+    };
+    // An ambient position holds L * sqrt(P) base and L / sqrt(P) quote, so each token implies half of the reward liquidity
+    let reward_liq = (earned.base_fees / root_price + earned.quote_fees * root_price) / 2.0;
+    Some(PositionApr {
+        base_fees: earned.base_fees,
+        quote_fees: earned.quote_fees,
+        duration,
+        time_in_range: earned.time_in_range,
+        contributed_liq: liq,
+        reward_liq,
+        post_liq: liq + reward_liq,
+        apr,
+    })
+}
+
+// The narrowest candle width which covers `duration` seconds in at most MAX_APR_CANDLES candles, so the cost of an
+// estimate is bounded however old the position is. Wider candles only see the price at each candle's close
+fn apr_candle_period(duration: u64) -> u64 {
+    CANDLE_PERIODS
+        .into_iter()
+        .find(|period| duration / period < MAX_APR_CANDLES)
+        .unwrap_or(CANDLE_PERIODS[CANDLE_PERIODS.len() - 1])
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct FeeEstimate {
+    base_fees: f64,
+    quote_fees: f64,
+    time_in_range: u64,
+}
+
+// Walks `candles` (sorted, ending after `start`) from `start` to `now`, beginning at `open_price`. Fees are credited for
+// candles closing within `range` (None for ambient positions), prorated for a candle which began before `start`, and
+// time in range is counted with the price held between candles.
+// `pool_liq_at` gives the pool's active liquidity at a tick, which is at least the position's own `liq` when in range
+fn estimate_fees(
+    candles: &[Candle],
+    open_price: f64,
+    start: u64,
+    now: u64,
+    range: Option<(i32, i32)>,
+    liq: f64,
+    pool_liq_at: impl Fn(i32) -> f64,
+) -> FeeEstimate {
+    let in_range = |tick: i32| match range {
+        Some((bid_tick, ask_tick)) => bid_tick <= tick && tick < ask_tick,
+        None => true,
+    };
+    let mut estimate = FeeEstimate::default();
+    let mut price = open_price;
+    let mut time = start;
+    for candle in candles {
+        // The price held steady from the previous candle until this one
+        let candle_time = candle.time.max(start);
+        if in_range(tick_from_price(price)) {
+            estimate.time_in_range += candle_time.min(now).saturating_sub(time);
+        }
+        let tick = tick_from_price(candle.close);
+        if in_range(tick) {
+            let elapsed = (candle.time + candle.period).saturating_sub(candle_time);
+            let share = liq / pool_liq_at(tick).max(liq) * elapsed as f64 / candle.period as f64;
+            estimate.base_fees += candle.base_fees * share;
+            estimate.quote_fees += candle.quote_fees * share;
+            estimate.time_in_range += (candle_time + candle.period)
+                .min(now)
+                .saturating_sub(candle_time);
+        }
+        price = candle.close;
+        time = (candle_time + candle.period).min(now);
+    }
+    if in_range(tick_from_price(price)) {
+        estimate.time_in_range += now.saturating_sub(time);
+    }
+    estimate
+}
+
+// Moves the pool's active liquidity from `current_tick` to `tick` across the liquidity bumps in between.
+// A bump adds its delta to the liquidity at and above its tick
+fn liquidity_at_tick(liq: f64, current_tick: i32, bumps: &[LiquidityBump], tick: i32) -> f64 {
+    let crossed: f64 = if tick > current_tick {
+        bumps
+            .iter()
+            .filter(|b| b.tick > current_tick && b.tick <= tick)
+            .map(|b| b.liquidity_delta)
+            .sum()
+    } else {
+        -bumps
+            .iter()
+            .filter(|b| b.tick > tick && b.tick <= current_tick)
+            .map(|b| b.liquidity_delta)
+            .sum::<f64>()
+    };
+    (liq + crossed).max(0.0)
+}
+
+#[test]
+fn test_liquidity_at_tick() {
+    let bump = |tick: i32, liquidity_delta: f64| LiquidityBump {
+        tick,
+        liquidity_delta,
+        ..Default::default()
+    };
+    // A position from -100 to 100 worth 50 on top of 100 ambient liquidity
+    let bumps = vec![bump(-100, 50.0), bump(100, -50.0)];
+    assert_eq!(liquidity_at_tick(150.0, 0, &bumps, 0), 150.0);
+    assert_eq!(liquidity_at_tick(150.0, 0, &bumps, 99), 150.0);
+    assert_eq!(liquidity_at_tick(150.0, 0, &bumps, 100), 100.0);
+    assert_eq!(liquidity_at_tick(150.0, 0, &bumps, -100), 150.0);
+    assert_eq!(liquidity_at_tick(150.0, 0, &bumps, -101), 100.0);
+    assert_eq!(liquidity_at_tick(100.0, 200, &bumps, 0), 150.0);
+}
+
+#[test]
+fn test_estimate_fees() {
+    let candle = |time: u64, close: f64, base_fees: f64| Candle {
+        period: 60,
+        time,
+        close,
+        base_fees,
+        quote_fees: base_fees / 2.0,
+        ..Default::default()
+    };
+    let in_price = 1.0;
+    let out_price = 1.0001f64.powi(500);
+    let candles = vec![
+        candle(1000, in_price, 100.0),
+        candle(1120, out_price, 100.0),
+        candle(1300, in_price, 40.0),
+    ];
+
+    // A quarter of the pool's liquidity, in range except for the middle candle and the 120 seconds after it
+    let estimate = estimate_fees(
+        &candles,
+        in_price,
+        900,
+        1600,
+        Some((-100, 100)),
+        25.0,
+        |_| 100.0,
+    );
+    assert_eq!(estimate.base_fees, 35.0);
+    assert_eq!(estimate.quote_fees, 17.5);
+    assert_eq!(estimate.time_in_range, 700 - 60 - 120);
+
+    // Ambient positions earn from every candle for the whole duration
+    let estimate = estimate_fees(&candles, in_price, 900, 1600, None, 25.0, |_| 100.0);
+    assert_eq!(estimate.base_fees, 60.0);
+    assert_eq!(estimate.time_in_range, 700);
+
+    // The position can never earn more than the whole pool's fees
+    let estimate = estimate_fees(&candles, in_price, 900, 1600, None, 500.0, |_| 100.0);
+    assert_eq!(estimate.base_fees, 240.0);
+
+    // Only the part of the first candle after the position's start earns fees
+    let estimate = estimate_fees(&candles, in_price, 1015, 1600, None, 25.0, |_| 100.0);
+    assert_eq!(estimate.base_fees, 18.75 + 25.0 + 10.0);
+
+    // A candle which starts after `now`, e.g. from a clock behind the chain's, adds no time in range
+    let estimate = estimate_fees(&candles, in_price, 900, 1250, None, 25.0, |_| 100.0);
+    assert_eq!(estimate.time_in_range, 350);
+}
+
+#[test]
+fn test_apr_candle_period() {
+    assert_eq!(apr_candle_period(0), 60);
+    assert_eq!(apr_candle_period(60 * 499), 60);
+    assert_eq!(apr_candle_period(60 * 500), 900);
+    assert_eq!(apr_candle_period(86400 * 30), 86400);
+    assert_eq!(apr_candle_period(86400 * 5000), 86400);
+}
@@ -0,0 +1,271 @@
+// This file stores each user's active positions per pool as records of their own, updated by applying each event as it is
+// indexed so that reading a user's positions is a single prefix scan rather than a replay of their whole history
+
+use std::collections::{HashMap, HashSet};
+
+use clarity::{Address, Uint256};
+use log::{debug, info, warn};
+
+use crate::althea::database::{
+    has_prefix,
+    schema::{deserialize_record, serialize_record},
+    storage::Storage,
+};
+
+use super::{
+    ambient::{BURN_AMBIENT_PREFIX, MINT_AMBIENT_PREFIX},
+    compute_user_pool_positions,
+    knockout::{BURN_KNOCKOUT_PREFIX, MINT_KNOCKOUT_PREFIX, WITHDRAW_KNOCKOUT_PREFIX},
+    ranged::{BURN_RANGED_PREFIX, HARVEST_PREFIX, MINT_RANGED_PREFIX},
+    PoolPositions, Position, PositionEvent, UserPool,
+};
+
+/// The families of the events which create or change positions, whose keys all start with the user and pool
+const POSITION_EVENT_PREFIXES: [&str; 8] = [
+    MINT_RANGED_PREFIX,
+    BURN_RANGED_PREFIX,
+    HARVEST_PREFIX,
+    MINT_AMBIENT_PREFIX,
+    BURN_AMBIENT_PREFIX,
+    MINT_KNOCKOUT_PREFIX,
+    BURN_KNOCKOUT_PREFIX,
+    WITHDRAW_KNOCKOUT_PREFIX,
+];
+
+pub const ACTIVE_POSITIONS_PREFIX: &str = "active-positions_";
+// The trailing separator keeps the user prefix from matching the pool part of the key
+fn active_positions_user_prefix(user: Address) -> String {
+    format!("{}{}_", ACTIVE_POSITIONS_PREFIX, user)
+}
+fn active_positions_key(user: Address, base: Address, quote: Address, pool_idx: Uint256) -> String {
+    format!(
+        "{}{}_{}_{}",
+        active_positions_user_prefix(user),
+        base,
+        quote,
+        pool_idx
+    )
+}
+
+/// Gets all of `user`'s indexed positions across every pool
+pub fn get_indexed_user_positions(db: &impl Storage, user: Address) -> Vec<Position> {
+    let prefix = active_positions_user_prefix(user);
+    let mut positions = vec![];
+    let iter = db.prefix_scan(ACTIVE_POSITIONS_PREFIX, prefix.as_bytes());
+    for entry in iter {
+        match entry {
+            Ok((k, v)) => {
+                if !k.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                let pool_positions: PoolPositions = deserialize_record(&v).unwrap();
+                positions.extend(pool_positions.into_positions());
+            }
+            Err(_) => break,
+        }
+    }
+    positions
+}
+
+/// Gets `user`'s indexed positions in a single pool
+pub fn get_indexed_user_pool_positions(
+    db: &impl Storage,
+    user: Address,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> Vec<Position> {
+    get_pool_positions(db, (user, base, quote, pool_idx)).into_positions()
+}
+
+// Gets the stored positions of a user in a pool, with no positions and no applied events if there is no record
+fn get_pool_positions(db: &impl Storage, (user, base, quote, pool_idx): UserPool) -> PoolPositions {
+    let k = active_positions_key(user, base, quote, pool_idx);
+    match db.get(ACTIVE_POSITIONS_PREFIX, k.as_bytes()).unwrap() {
+        Some(v) => deserialize_record(&v).unwrap(),
+        None => PoolPositions::default(),
+    }
+}
+
+// Stores `positions` as the user's positions in the pool. The record is kept once no positions are left, since its last
+// applied event is what keeps events from being applied twice
+fn save_pool_positions(
+    db: &impl Storage,
+    (user, base, quote, pool_idx): UserPool,
+    positions: &PoolPositions,
+) {
+    let k = active_positions_key(user, base, quote, pool_idx);
+    debug!("Saving active positions {:?} to key {}", positions, k);
+    let v = serialize_record(positions).unwrap();
+
+    db.put(ACTIVE_POSITIONS_PREFIX, k.as_bytes(), &v).unwrap();
+}
+
+/// Applies newly indexed events to the stored positions of the users and pools they belong to, called once a batch of
+/// events has been saved. Events which the stored positions already include, e.g. from re-indexed blocks, are skipped
+pub fn apply_position_events(db: &impl Storage, events: Vec<PositionEvent>) {
+    let mut by_user_pool: HashMap<UserPool, Vec<PositionEvent>> = HashMap::new();
+    for event in events {
+        by_user_pool
+            .entry(event.user_pool())
+            .or_default()
+            .push(event);
+    }
+    for (user_pool, mut events) in by_user_pool {
+        events.sort_by_key(|e| e.order());
+        let mut positions = get_pool_positions(db, user_pool);
+        let mut applied = 0;
+        for event in events {
+            if positions.apply(event) {
+                applied += 1;
+            }
+        }
+        if applied > 0 {
+            save_pool_positions(db, user_pool, &positions);
+        }
+    }
+}
+
+/// The outcome of comparing the active position index with the positions replayed from the stored events
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PositionIndexCheck {
+    // The (user, pool) pairs with any position events
+    pub checked: usize,
+    // Pairs whose indexed positions differ from the replayed ones, including those missing from the index
+    pub mismatched: usize,
+    // Index records for pairs which no longer have any events
+    pub stale: usize,
+}
+
+/// Replays every user's events in every pool and compares the result with the active position index, correcting the
+/// index when `repair` is set
+pub fn check_position_index(db: &impl Storage, repair: bool) -> PositionIndexCheck {
+    let user_pools = position_user_pools(db);
+
+    let mut check = PositionIndexCheck {
+        checked: user_pools.len(),
+        ..Default::default()
+    };
+    for (i, user_pool) in user_pools.iter().enumerate() {
+        let (user, base, quote, pool_idx) = *user_pool;
+        let expected = compute_user_pool_positions(db, user, base, quote, pool_idx);
+        let indexed = get_pool_positions(db, *user_pool);
+        if expected != indexed {
+            warn!(
+                "Active positions of {user} in {base} {quote} {pool_idx} do not match their events"
+            );
+            check.mismatched += 1;
+            if repair {
+                save_pool_positions(db, *user_pool, &expected);
+            }
+        }
+        if (i + 1) % 1000 == 0 {
+            info!(
+                "Checked positions of {} / {} users and pools",
+                i + 1,
+                user_pools.len()
+            );
+        }
+    }
+
+    let live: HashSet<Vec<u8>> = user_pools
+        .iter()
+        .map(|(user, base, quote, pool_idx)| {
+            active_positions_key(*user, *base, *quote, *pool_idx).into_bytes()
+        })
+        .collect();
+    let prefix = ACTIVE_POSITIONS_PREFIX.as_bytes();
+    for (k, _) in db.prefix_scan(ACTIVE_POSITIONS_PREFIX, prefix).flatten() {
+        if !k.starts_with(prefix) {
+            break;
+        }
+        if !live.contains(k.as_ref()) {
+            check.stale += 1;
+            if repair {
+                db.delete(ACTIVE_POSITIONS_PREFIX, &k).unwrap();
+            }
+        }
+    }
+    check
+}
+
+// Finds every (user, base, quote, pool_idx) with position events from the keys of the events, without decoding them
+fn position_user_pools(db: &impl Storage) -> HashSet<UserPool> {
+    let mut user_pools: HashSet<UserPool> = HashSet::new();
+    for family in POSITION_EVENT_PREFIXES {
+        let prefix = family.as_bytes();
+        for (k, _) in db.prefix_scan(family, prefix).flatten() {
+            if !k.starts_with(prefix) {
+                break;
+            }
+            match parse_user_pool(&k[prefix.len()..]) {
+                Some(user_pool) => {
+                    user_pools.insert(user_pool);
+                }
+                None => warn!(
+                    "Unable to read the user and pool of event key {}",
+                    String::from_utf8_lossy(&k)
+                ),
+            }
+        }
+    }
+    user_pools
+}
+
+// Parses the {user}_{base}_{quote}_{pool_idx} which every position event key starts with after its family prefix
+fn parse_user_pool(key: &[u8]) -> Option<UserPool> {
+    let mut parts = std::str::from_utf8(key).ok()?.split('_');
+    let user = parts.next()?.parse().ok()?;
+    let base = parts.next()?.parse().ok()?;
+    let quote = parts.next()?.parse().ok()?;
+    let pool_idx = parts.next()?.parse().ok()?;
+    Some((user, base, quote, pool_idx))
+}
+
+/// Whether positions have been indexed without the active position index, e.g. by an older version
+pub fn position_index_missing(db: &impl Storage) -> bool {
+    !has_prefix(db, ACTIVE_POSITIONS_PREFIX)
+        && (has_prefix(db, MINT_RANGED_PREFIX)
+            || has_prefix(db, MINT_AMBIENT_PREFIX)
+            || has_prefix(db, MINT_KNOCKOUT_PREFIX))
+}
+
+#[test]
+fn test_apply_position_events() {
+    use super::ranged::{save_burn_ranged, save_mint_ranged};
+    use super::test_events::{burn_ranged as burn, mint_ranged as mint};
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    let user_pool = PositionEvent::from(mint(1, 0, 0)).user_pool();
+    let liq = |db: &MemoryStorage| -> Vec<u128> {
+        get_indexed_user_positions(db, user_pool.0)
+            .iter()
+            .map(|p| p.liq())
+            .collect()
+    };
+
+    save_mint_ranged(&db, mint(1, 0, 1000));
+    apply_position_events(&db, vec![mint(1, 0, 1000).into()]);
+    save_mint_ranged(&db, mint(2, 0, 500));
+    save_burn_ranged(&db, burn(3, 0, 300));
+    apply_position_events(&db, vec![burn(3, 0, 300).into(), mint(2, 0, 500).into()]);
+    assert_eq!(liq(&db), vec![1200]);
+    // Re-indexing blocks which were already applied leaves the positions as they are
+    apply_position_events(&db, vec![mint(2, 0, 500).into(), burn(3, 0, 300).into()]);
+    assert_eq!(liq(&db), vec![1200]);
+
+    // The applied events match a replay of every stored event, found from their keys
+    assert_eq!(position_user_pools(&db), HashSet::from([user_pool]));
+    let check = check_position_index(&db, false);
+    assert_eq!((check.checked, check.mismatched, check.stale), (1, 0, 0));
+
+    // Burning everything keeps the record of the applied events, but no positions
+    save_burn_ranged(&db, burn(4, 0, 1200));
+    apply_position_events(&db, vec![burn(4, 0, 1200).into()]);
+    assert!(liq(&db).is_empty());
+    apply_position_events(&db, vec![mint(1, 0, 1000).into()]);
+    assert!(liq(&db).is_empty());
+    let check = check_position_index(&db, false);
+    assert_eq!((check.checked, check.mismatched, check.stale), (1, 0, 0));
+}
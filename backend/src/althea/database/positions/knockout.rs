@@ -2,6 +2,9 @@ use clarity::Address;
 use clarity::Uint256;
 use log::debug;
 
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
+
 use crate::althea::ambient::knockout::BurnKnockoutEvent;
 use crate::althea::ambient::knockout::MintKnockoutEvent;
 use crate::althea::ambient::knockout::WithdrawKnockoutEvent;
@@ -54,7 +57,7 @@ pub fn mint_knockout_key(
 
 // Gets a single MintKnockout event from `db` by the other arguments, returns none if it does not exist
 pub fn get_mint_knockout(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -63,26 +66,26 @@ pub fn get_mint_knockout(
     index: Uint256,
 ) -> Option<MintKnockoutEvent> {
     let k = mint_knockout_key(user, base, quote, pool_idx, block, index);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(MINT_KNOCKOUT_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known MintKnockout events from the database
-pub fn get_all_mint_knockout(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<MintKnockoutEvent> {
+pub fn get_all_mint_knockout(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<MintKnockoutEvent> {
     let prefix = prefix.unwrap_or_else(|| MINT_KNOCKOUT_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(MINT_KNOCKOUT_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: MintKnockoutEvent = bincode::deserialize(&v).unwrap();
+                let event: MintKnockoutEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -92,7 +95,7 @@ pub fn get_all_mint_knockout(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<Min
 }
 
 pub fn get_all_mint_knockout_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<MintKnockoutEvent> {
@@ -104,7 +107,7 @@ pub fn get_all_mint_knockout_after_block(
         .collect()
 }
 
-pub fn save_mint_knockout(db: &rocksdb::DB, mke: MintKnockoutEvent) {
+pub fn save_mint_knockout(db: &impl Storage, mke: MintKnockoutEvent) {
     let k = mint_knockout_key(
         mke.user,
         mke.base,
@@ -114,9 +117,9 @@ pub fn save_mint_knockout(db: &rocksdb::DB, mke: MintKnockoutEvent) {
         mke.index,
     );
     debug!("Saving MintKnockoutEvent to key {}", k);
-    let v = bincode::serialize(&mke).unwrap();
+    let v = serialize_record(&mke).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(MINT_KNOCKOUT_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const BURN_KNOCKOUT_PREFIX: &str = "burn-knockout_";
@@ -167,7 +170,7 @@ pub fn burn_knockout_key(
 
 // Gets a single BurnKnockout event from `db` by the other arguments, returns none if it does not exist
 pub fn get_burn_knockout(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -176,26 +179,26 @@ pub fn get_burn_knockout(
     index: Uint256,
 ) -> Option<BurnKnockoutEvent> {
     let k = burn_knockout_key(user, base, quote, pool_idx, block, index);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(BURN_KNOCKOUT_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known BurnKnockout events from the database
-pub fn get_all_burn_knockout(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<BurnKnockoutEvent> {
+pub fn get_all_burn_knockout(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<BurnKnockoutEvent> {
     let prefix = prefix.unwrap_or_else(|| BURN_KNOCKOUT_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(BURN_KNOCKOUT_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: BurnKnockoutEvent = bincode::deserialize(&v).unwrap();
+                let event: BurnKnockoutEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -205,7 +208,7 @@ pub fn get_all_burn_knockout(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<Bur
 }
 
 pub fn get_all_burn_knockout_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<BurnKnockoutEvent> {
@@ -217,7 +220,7 @@ pub fn get_all_burn_knockout_after_block(
         .collect()
 }
 
-pub fn save_burn_knockout(db: &rocksdb::DB, bke: BurnKnockoutEvent) {
+pub fn save_burn_knockout(db: &impl Storage, bke: BurnKnockoutEvent) {
     let k = burn_knockout_key(
         bke.user,
         bke.base,
@@ -227,9 +230,9 @@ pub fn save_burn_knockout(db: &rocksdb::DB, bke: BurnKnockoutEvent) {
         bke.index,
     );
     debug!("Saving BurnKnockoutEvent to key {}", k);
-    let v = bincode::serialize(&bke).unwrap();
+    let v = serialize_record(&bke).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(BURN_KNOCKOUT_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const WITHDRAW_KNOCKOUT_PREFIX: &str = "withdraw-knockout_";
@@ -280,7 +283,7 @@ pub fn withdraw_knockout_key(
 
 // Gets a single WithdrawKnockout event from `db` by the other arguments, returns none if it does not exist
 pub fn get_withdraw_knockout(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -289,29 +292,29 @@ pub fn get_withdraw_knockout(
     index: Uint256,
 ) -> Option<WithdrawKnockoutEvent> {
     let k = withdraw_knockout_key(user, base, quote, pool_idx, block, index);
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(WITHDRAW_KNOCKOUT_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known WithdrawKnockout events from the database
 pub fn get_all_withdraw_knockout(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
 ) -> Vec<WithdrawKnockoutEvent> {
     let prefix = prefix.unwrap_or_else(|| WITHDRAW_KNOCKOUT_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(WITHDRAW_KNOCKOUT_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: WithdrawKnockoutEvent = bincode::deserialize(&v).unwrap();
+                let event: WithdrawKnockoutEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -321,7 +324,7 @@ pub fn get_all_withdraw_knockout(
 }
 
 pub fn get_all_withdraw_knockout_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<WithdrawKnockoutEvent> {
@@ -332,7 +335,7 @@ pub fn get_all_withdraw_knockout_after_block(
         .cloned()
         .collect()
 }
-pub fn save_withdraw_knockout(db: &rocksdb::DB, bke: WithdrawKnockoutEvent) {
+pub fn save_withdraw_knockout(db: &impl Storage, bke: WithdrawKnockoutEvent) {
     let k = withdraw_knockout_key(
         bke.user,
         bke.base,
@@ -342,7 +345,7 @@ pub fn save_withdraw_knockout(db: &rocksdb::DB, bke: WithdrawKnockoutEvent) {
         bke.index,
     );
     debug!("Saving WithdrawKnockoutEvent to key {}", k);
-    let v = bincode::serialize(&bke).unwrap();
+    let v = serialize_record(&bke).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(WITHDRAW_KNOCKOUT_PREFIX, k.as_bytes(), &v).unwrap();
 }
@@ -1,33 +1,173 @@
 use clarity::Address;
 use clarity::Uint256;
+use log::debug;
 use log::error;
-use log::info;
+use serde::{Deserialize, Serialize};
 
 use ambient::{
-    burn_ambient_user_pool_prefix, burn_ambient_user_prefix, get_all_burn_ambient,
-    get_all_mint_ambient, mint_ambient_user_pool_prefix, mint_ambient_user_prefix,
+    burn_ambient_user_pool_prefix, get_all_burn_ambient, get_all_mint_ambient,
+    mint_ambient_user_pool_prefix,
+};
+use index::{get_indexed_user_pool_positions, get_indexed_user_positions};
+use knockout::{
+    burn_knockout_user_pool_prefix, get_all_burn_knockout, get_all_mint_knockout,
+    get_all_withdraw_knockout, mint_knockout_user_pool_prefix, withdraw_knockout_user_pool_prefix,
 };
 use ranged::{
-    burn_ranged_user_pool_prefix, burn_ranged_user_prefix, get_all_burn_ranged,
-    get_all_mint_ranged, mint_ranged_user_pool_prefix, mint_ranged_user_prefix,
+    burn_ranged_user_pool_prefix, get_all_burn_ranged, get_all_harvest, get_all_mint_ranged,
+    harvest_user_pool_prefix, mint_ranged_user_pool_prefix,
 };
 
+use super::super::ambient::knockout::{
+    BurnKnockoutEvent, MintKnockoutEvent, WithdrawKnockoutEvent,
+};
 use super::super::ambient::positions::{
-    BurnAmbientEvent, BurnRangedEvent, MintAmbientEvent, MintRangedEvent,
+    BurnAmbientEvent, BurnRangedEvent, HarvestEvent, MintAmbientEvent, MintRangedEvent,
 };
+use super::curve::get_root_price;
+use super::storage::Storage;
+use super::tracking::knockouts::knocked_out_since;
+use super::tracking::root_price_from_tick;
+use super::tracking::tick_from_price;
+use super::tracking::updates::knockout_liquidity;
 
 pub mod ambient;
+pub mod apr;
+pub mod index;
 pub mod knockout;
+pub mod pnl;
 pub mod ranged;
 
+/// A user and a pool identified by (user, base, quote, pool_idx)
+pub type UserPool = (Address, Address, Address, Uint256);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Position {
     Ranged(RangedPosition),
     Ambient(AmbientPosition),
+    Knockout(KnockoutPosition),
+}
+
+impl Position {
+    pub fn start_block(&self) -> Uint256 {
+        match self {
+            Position::Ranged(v) => v.start_block,
+            Position::Ambient(v) => v.start_block,
+            Position::Knockout(v) => v.start_block,
+        }
+    }
+
+    pub fn first_block(&self) -> Uint256 {
+        match self {
+            Position::Ranged(v) => v.first_block,
+            Position::Ambient(v) => v.first_block,
+            Position::Knockout(v) => v.first_block,
+        }
+    }
+
+    /// The block from which the position's unclaimed rewards accrue, only ranged positions can harvest without a mint
+    pub fn rewards_block(&self) -> Uint256 {
+        match self {
+            Position::Ranged(v) => v.rewards_block,
+            _ => self.start_block(),
+        }
+    }
+
+    /// The (base, quote) rewards already collected by harvests
+    pub fn harvested(&self) -> (u128, u128) {
+        match self {
+            Position::Ranged(v) => (v.harvested_base, v.harvested_quote),
+            _ => (0, 0),
+        }
+    }
+
+    /// The (user, base, quote, pool_idx) the position belongs to
+    pub fn user_pool(&self) -> UserPool {
+        match self {
+            Position::Ranged(v) => (v.user, v.base, v.quote, v.pool_idx),
+            Position::Ambient(v) => (v.user, v.base, v.quote, v.pool_idx),
+            Position::Knockout(v) => (v.user, v.base, v.quote, v.pool_idx),
+        }
+    }
+
+    /// The position's ticks, ambient positions span the whole curve and are reported as (0, 0)
+    pub fn ticks(&self) -> (i32, i32) {
+        match self {
+            Position::Ranged(v) => (v.bid_tick, v.ask_tick),
+            Position::Ambient(_) => (0, 0),
+            Position::Knockout(v) => (v.bid_tick, v.ask_tick),
+        }
+    }
+
+    /// The net (base, quote) flows into the position, mints less burns
+    pub fn flows(&self) -> (i128, i128) {
+        match self {
+            Position::Ranged(v) => (v.base_flow, v.quote_flow),
+            Position::Ambient(v) => (v.base_flow, v.quote_flow),
+            Position::Knockout(v) => (v.base_flow, v.quote_flow),
+        }
+    }
+
+    pub fn liq(&self) -> u128 {
+        match self {
+            Position::Ranged(v) => v.liq,
+            Position::Ambient(v) => v.liq,
+            Position::Knockout(v) => v.liq,
+        }
+    }
+
+    /// The (base, quote) tokens the position's liquidity is worth at the pool's `root_price`
+    pub fn token_amounts(&self, root_price: f64) -> (f64, f64) {
+        let liq = self.liq() as f64;
+        match self {
+            Position::Ambient(_) => (liq * root_price, liq / root_price),
+            // Withdrawn knockouts no longer hold any liquidity
+            Position::Knockout(v) if v.status == KnockoutStatus::Withdrawn => (0.0, 0.0),
+            _ => {
+                let (bid_tick, ask_tick) = self.ticks();
+                concentrated_amounts(liq, root_price, bid_tick, ask_tick)
+            }
+        }
+    }
+}
+
+/// The (base, quote) tokens held by `liq` concentrated liquidity between `bid_tick` and `ask_tick` at `root_price`.
+/// Below the range the position is entirely quote, above it entirely base
+pub fn concentrated_amounts(liq: f64, root_price: f64, bid_tick: i32, ask_tick: i32) -> (f64, f64) {
+    let lower = root_price_from_tick(bid_tick);
+    let upper = root_price_from_tick(ask_tick);
+    let root = root_price.clamp(lower, upper);
+    (liq * (root - lower), liq * (1.0 / root - 1.0 / upper))
+}
+
+/// Gets a single active position for `user` in a pool, ambient positions are requested with bid_tick == ask_tick
+pub fn get_active_user_position(
+    db: &impl Storage,
+    user: Address,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    bid_tick: i32,
+    ask_tick: i32,
+) -> Option<Position> {
+    get_active_user_pool_positions(db, user, base, quote, pool_idx)
+        .into_iter()
+        .filter(|p| match p {
+            Position::Ambient(_) => bid_tick == ask_tick,
+            _ => p.ticks() == (bid_tick, ask_tick),
+        })
+        // A ranged position and a knockout could share ticks, the ranged one is returned in that case
+        .min_by_key(|p| match p {
+            Position::Knockout(_) => 1,
+            _ => 0,
+        })
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RangedPosition {
+    // The block of the most recent mint, from which rewards accrue
     pub start_block: Uint256,
+    pub first_block: Uint256,
     pub user: Address,
     pub base: Address,
     pub quote: Address,
@@ -37,134 +177,319 @@ pub struct RangedPosition {
     pub liq: u128,
     pub base_flow: i128,
     pub quote_flow: i128,
+    // The totals paid out by harvests, which collect rewards without touching the liquidity
+    pub harvested_base: u128,
+    pub harvested_quote: u128,
+    // The block of the most recent mint or harvest, the position's unclaimed rewards accrue from here
+    pub rewards_block: Uint256,
 }
-pub fn get_active_user_positions(db: &rocksdb::DB, user: Address) -> Vec<Position> {
-    let mut mint_ranged = get_all_mint_ranged(db, Some(mint_ranged_user_prefix(user).as_bytes()));
-    mint_ranged.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut burn_ranged = get_all_burn_ranged(db, Some(burn_ranged_user_prefix(user).as_bytes()));
-    burn_ranged.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut mint_ambient =
-        get_all_mint_ambient(db, Some(mint_ambient_user_prefix(user).as_bytes()));
-    mint_ambient.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut burn_ambient =
-        get_all_burn_ambient(db, Some(burn_ambient_user_prefix(user).as_bytes()));
-    burn_ambient.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-
-    let ranged_positions: Vec<RangedPosition> =
-        combine_and_filter_ranged_positions(mint_ranged, burn_ranged);
-    let ambient_positions = combine_and_filter_ambient_positions(mint_ambient, burn_ambient);
-    let mut positions = ranged_positions
-        .into_iter()
-        .map(Position::Ranged)
-        .collect::<Vec<_>>();
-    positions.extend(ambient_positions.into_iter().map(Position::Ambient));
-    positions.sort_by_key(|a| match a {
-        Position::Ranged(v) => v.start_block,
-        Position::Ambient(v) => v.start_block,
-    });
+/// Gets all of `user`'s active positions from the active position index, oldest first
+pub fn get_active_user_positions(db: &impl Storage, user: Address) -> Vec<Position> {
+    let mut positions = get_indexed_user_positions(db, user);
+    update_knockout_status(db, &mut positions);
+    positions.sort_by_key(|a| a.start_block());
     positions
 }
+/// Gets `user`'s active positions in a single pool from the active position index, oldest first
 pub fn get_active_user_pool_positions(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Vec<Position> {
-    let mut mint_ranged = get_all_mint_ranged(
+    let mut positions = get_indexed_user_pool_positions(db, user, base, quote, pool_idx);
+    update_knockout_status(db, &mut positions);
+    positions.sort_by_key(|a| a.start_block());
+    positions
+}
+/// Replays all of `user`'s events in a pool to find their active positions there. The active position index applies each
+/// new event to the positions it stored instead, this rebuilds them from scratch to check the index against. Readers
+/// should use get_active_user_positions or get_active_user_pool_positions instead.
+/// Knockout positions are only marked Withdrawn here, whether they have been knocked out is found when they are read
+pub fn compute_user_pool_positions(
+    db: &impl Storage,
+    user: Address,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> PoolPositions {
+    let mint_ranged = get_all_mint_ranged(
         db,
         Some(mint_ranged_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
     );
-    mint_ranged.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut burn_ranged = get_all_burn_ranged(
+    let burn_ranged = get_all_burn_ranged(
         db,
         Some(burn_ranged_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
     );
-    burn_ranged.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut mint_ambient = get_all_mint_ambient(
+    let harvests = get_all_harvest(
+        db,
+        Some(harvest_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
+    );
+    let mint_ambient = get_all_mint_ambient(
         db,
         Some(mint_ambient_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
     );
-    mint_ambient.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    let mut burn_ambient = get_all_burn_ambient(
+    let burn_ambient = get_all_burn_ambient(
         db,
         Some(burn_ambient_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
     );
-    burn_ambient.sort_by(|a, b| a.block_height.cmp(&b.block_height));
-    info!("MR: {mint_ranged:?} BR: {burn_ranged:?} MA: {mint_ambient:?} BA: {burn_ambient:?}");
-    let ranged_positions: Vec<RangedPosition> =
-        combine_and_filter_ranged_positions(mint_ranged, burn_ranged);
-    info!("Ranged positions: {ranged_positions:?}");
-    let ambient_positions = combine_and_filter_ambient_positions(mint_ambient, burn_ambient);
-    info!("Ambient positions: {ambient_positions:?}");
-    let mut positions = ranged_positions
+    let mint_knockout = get_all_mint_knockout(
+        db,
+        Some(mint_knockout_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
+    );
+    let burn_knockout = get_all_burn_knockout(
+        db,
+        Some(burn_knockout_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
+    );
+    let withdraw_knockout = get_all_withdraw_knockout(
+        db,
+        Some(withdraw_knockout_user_pool_prefix(user, base, quote, pool_idx).as_bytes()),
+    );
+    debug!("MR: {mint_ranged:?} BR: {burn_ranged:?} H: {harvests:?}");
+    debug!("MA: {mint_ambient:?} BA: {burn_ambient:?}");
+    debug!("MK: {mint_knockout:?} BK: {burn_knockout:?} WK: {withdraw_knockout:?}");
+    let events = mint_ranged
         .into_iter()
-        .map(Position::Ranged)
-        .collect::<Vec<_>>();
-    positions.extend(ambient_positions.into_iter().map(Position::Ambient));
-    positions.sort_by_key(|a| match a {
-        Position::Ranged(v) => v.start_block,
-        Position::Ambient(v) => v.start_block,
-    });
+        .map(PositionEvent::from)
+        .chain(burn_ranged.into_iter().map(PositionEvent::from))
+        .chain(harvests.into_iter().map(PositionEvent::from))
+        .chain(mint_ambient.into_iter().map(PositionEvent::from))
+        .chain(burn_ambient.into_iter().map(PositionEvent::from))
+        .chain(mint_knockout.into_iter().map(PositionEvent::from))
+        .chain(burn_knockout.into_iter().map(PositionEvent::from))
+        .chain(withdraw_knockout.into_iter().map(PositionEvent::from));
+    let positions = PoolPositions::replay(events);
+    debug!("Positions: {positions:?}");
     positions
 }
 
-// Combines together any corresponding mint_ranged entries, and filters them by any corresponding burn_ranged entries
-fn combine_and_filter_ranged_positions(
-    mint_ranged: Vec<MintRangedEvent>,
-    burn_ranged: Vec<BurnRangedEvent>,
-) -> Vec<RangedPosition> {
-    let mut ranged_positions: Vec<RangedPosition> = vec![];
-    for mr in mint_ranged {
-        match ranged_positions.iter_mut().find(|v| {
-            v.start_block <= mr.block_height
-                && v.base == mr.base
-                && v.quote == mr.quote
-                && v.pool_idx == mr.pool_idx
-                && v.bid_tick == mr.bid_tick
-                && v.ask_tick == mr.ask_tick
-        }) {
-            Some(pos) => {
-                pos.base_flow += mr.base_flow;
-                pos.quote_flow += mr.quote_flow;
-                pos.liq += mr.liq;
-                // We overwrite the block because fees should only apply from the most recent effective mint
-                pos.start_block = mr.block_height;
-            }
-            None => ranged_positions.push(RangedPosition {
-                start_block: mr.block_height,
-                user: mr.user,
-                base: mr.base,
-                quote: mr.quote,
-                pool_idx: mr.pool_idx,
-                bid_tick: mr.bid_tick,
-                ask_tick: mr.ask_tick,
-                liq: mr.liq,
-                base_flow: mr.base_flow,
-                quote_flow: mr.quote_flow,
-            }),
-        }
-    }
-    for br in burn_ranged {
-        if let Some(idx) = ranged_positions.iter().position(|v| {
-            v.start_block <= br.block_height
-                && v.base == br.base
-                && v.quote == br.quote
-                && v.pool_idx == br.pool_idx
-                && v.bid_tick == br.bid_tick
-                && v.ask_tick == br.ask_tick
-        }) {
+/// An event which creates or changes one of a user's positions in a pool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionEvent {
+    MintRanged(MintRangedEvent),
+    BurnRanged(BurnRangedEvent),
+    Harvest(HarvestEvent),
+    MintAmbient(MintAmbientEvent),
+    BurnAmbient(BurnAmbientEvent),
+    MintKnockout(MintKnockoutEvent),
+    BurnKnockout(BurnKnockoutEvent),
+    WithdrawKnockout(WithdrawKnockoutEvent),
+}
+
+impl PositionEvent {
+    /// The (block, log index) of the event, the order in which events must be applied
+    pub fn order(&self) -> (Uint256, Uint256) {
+        match self {
+            PositionEvent::MintRanged(v) => (v.block_height, v.index),
+            PositionEvent::BurnRanged(v) => (v.block_height, v.index),
+            PositionEvent::Harvest(v) => (v.block_height, v.index),
+            PositionEvent::MintAmbient(v) => (v.block_height, v.index),
+            PositionEvent::BurnAmbient(v) => (v.block_height, v.index),
+            PositionEvent::MintKnockout(v) => (v.block_height, v.index),
+            PositionEvent::BurnKnockout(v) => (v.block_height, v.index),
+            PositionEvent::WithdrawKnockout(v) => (v.block_height, v.index),
+        }
+    }
+
+    /// The (user, base, quote, pool_idx) whose positions the event changes
+    pub fn user_pool(&self) -> UserPool {
+        match self {
+            PositionEvent::MintRanged(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::BurnRanged(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::Harvest(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::MintAmbient(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::BurnAmbient(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::MintKnockout(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::BurnKnockout(v) => (v.user, v.base, v.quote, v.pool_idx),
+            PositionEvent::WithdrawKnockout(v) => (v.user, v.base, v.quote, v.pool_idx),
+        }
+    }
+}
+
+impl From<MintRangedEvent> for PositionEvent {
+    fn from(e: MintRangedEvent) -> Self {
+        PositionEvent::MintRanged(e)
+    }
+}
+impl From<BurnRangedEvent> for PositionEvent {
+    fn from(e: BurnRangedEvent) -> Self {
+        PositionEvent::BurnRanged(e)
+    }
+}
+impl From<HarvestEvent> for PositionEvent {
+    fn from(e: HarvestEvent) -> Self {
+        PositionEvent::Harvest(e)
+    }
+}
+impl From<MintAmbientEvent> for PositionEvent {
+    fn from(e: MintAmbientEvent) -> Self {
+        PositionEvent::MintAmbient(e)
+    }
+}
+impl From<BurnAmbientEvent> for PositionEvent {
+    fn from(e: BurnAmbientEvent) -> Self {
+        PositionEvent::BurnAmbient(e)
+    }
+}
+impl From<MintKnockoutEvent> for PositionEvent {
+    fn from(e: MintKnockoutEvent) -> Self {
+        PositionEvent::MintKnockout(e)
+    }
+}
+impl From<BurnKnockoutEvent> for PositionEvent {
+    fn from(e: BurnKnockoutEvent) -> Self {
+        PositionEvent::BurnKnockout(e)
+    }
+}
+impl From<WithdrawKnockoutEvent> for PositionEvent {
+    fn from(e: WithdrawKnockoutEvent) -> Self {
+        PositionEvent::WithdrawKnockout(e)
+    }
+}
+
+/// A user's positions in a single pool, as stored in the active position index. Closed ranged and ambient positions are
+/// removed, withdrawn knockouts are kept. `last_event` is the (block, log index) of the last event applied, events at or
+/// before it are already included, which keeps re-indexed or re-searched blocks from being applied twice
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolPositions {
+    pub ranged: Vec<RangedPosition>,
+    pub ambient: Vec<AmbientPosition>,
+    pub knockout: Vec<KnockoutPosition>,
+    pub last_event: Option<(Uint256, Uint256)>,
+}
+
+impl PoolPositions {
+    /// Applies `events` in (block, log index) order to a user who has no positions yet
+    pub fn replay(events: impl IntoIterator<Item = PositionEvent>) -> Self {
+        let mut events: Vec<PositionEvent> = events.into_iter().collect();
+        events.sort_by_key(|e| e.order());
+        let mut positions = PoolPositions::default();
+        for event in events {
+            positions.apply(event);
+        }
+        positions
+    }
+
+    /// Applies a single event to the positions, events must be applied in (block, log index) order. Returns false for
+    /// events which have already been applied
+    pub fn apply(&mut self, event: PositionEvent) -> bool {
+        let order = event.order();
+        if self.last_event.is_some_and(|last| order <= last) {
+            return false;
+        }
+        match event {
+            PositionEvent::MintRanged(mr) => apply_mint_ranged(&mut self.ranged, mr),
+            PositionEvent::BurnRanged(br) => apply_burn_ranged(&mut self.ranged, br),
+            PositionEvent::Harvest(he) => apply_harvest(&mut self.ranged, he),
+            PositionEvent::MintAmbient(ma) => apply_mint_ambient(&mut self.ambient, ma),
+            PositionEvent::BurnAmbient(ba) => apply_burn_ambient(&mut self.ambient, ba),
+            PositionEvent::MintKnockout(mk) => apply_mint_knockout(&mut self.knockout, mk),
+            PositionEvent::BurnKnockout(bk) => apply_burn_knockout(&mut self.knockout, bk),
+            PositionEvent::WithdrawKnockout(wk) => apply_withdraw_knockout(&mut self.knockout, wk),
+        }
+        self.last_event = Some(order);
+        true
+    }
+
+    /// All of the positions, oldest first
+    pub fn into_positions(self) -> Vec<Position> {
+        let mut positions = self
+            .ranged
+            .into_iter()
+            .map(Position::Ranged)
+            .collect::<Vec<_>>();
+        positions.extend(self.ambient.into_iter().map(Position::Ambient));
+        positions.extend(self.knockout.into_iter().map(Position::Knockout));
+        positions.sort_by_key(|a| a.start_block());
+        positions
+    }
+}
+
+// Adds the liquidity of a mint to the ranged position on its range, or opens a new position if there is none
+fn apply_mint_ranged(ranged_positions: &mut Vec<RangedPosition>, mr: MintRangedEvent) {
+    match ranged_positions.iter_mut().find(|v| {
+        v.start_block <= mr.block_height
+            && v.base == mr.base
+            && v.quote == mr.quote
+            && v.pool_idx == mr.pool_idx
+            && v.bid_tick == mr.bid_tick
+            && v.ask_tick == mr.ask_tick
+    }) {
+        Some(pos) => {
+            pos.base_flow += mr.base_flow;
+            pos.quote_flow += mr.quote_flow;
+            pos.liq += mr.liq;
+            // We overwrite the block because fees should only apply from the most recent effective mint
+            pos.start_block = mr.block_height;
+            pos.rewards_block = mr.block_height;
+        }
+        None => ranged_positions.push(RangedPosition {
+            start_block: mr.block_height,
+            first_block: mr.block_height,
+            user: mr.user,
+            base: mr.base,
+            quote: mr.quote,
+            pool_idx: mr.pool_idx,
+            bid_tick: mr.bid_tick,
+            ask_tick: mr.ask_tick,
+            liq: mr.liq,
+            base_flow: mr.base_flow,
+            quote_flow: mr.quote_flow,
+            harvested_base: 0,
+            harvested_quote: 0,
+            rewards_block: mr.block_height,
+        }),
+    }
+}
+
+// Reduces the ranged position on the burn's range, removing it once all of its liquidity has been burned
+fn apply_burn_ranged(ranged_positions: &mut Vec<RangedPosition>, br: BurnRangedEvent) {
+    if let Some(idx) = ranged_positions.iter().position(|v| {
+        v.start_block <= br.block_height
+            && v.base == br.base
+            && v.quote == br.quote
+            && v.pool_idx == br.pool_idx
+            && v.bid_tick == br.bid_tick
+            && v.ask_tick == br.ask_tick
+    }) {
+        let pos = &mut ranged_positions[idx];
+        // Burned flows are negative, since they are paid out to the user
+        pos.base_flow += br.base_flow;
+        pos.quote_flow += br.quote_flow;
+        pos.liq = pos.liq.saturating_sub(br.liq);
+        if pos.liq == 0 {
             ranged_positions.remove(idx);
-        } else {
-            error!("BurnRangedEvent without corresponding MintRangedEvent");
         }
+    } else {
+        error!("BurnRangedEvent without corresponding MintRangedEvent");
+    }
+}
+
+// Adds up the amounts paid out by a harvest on the ranged position it was collected from, and moves the position's
+// reward accounting up to the harvest. Harvests from before the position's first mint belong to an earlier position on
+// the same range which has since been burned, and are ignored
+fn apply_harvest(ranged_positions: &mut [RangedPosition], he: HarvestEvent) {
+    let pos = ranged_positions.iter_mut().find(|v| {
+        v.first_block <= he.block_height
+            && v.base == he.base
+            && v.quote == he.quote
+            && v.pool_idx == he.pool_idx
+            && v.bid_tick == he.bid_tick
+            && v.ask_tick == he.ask_tick
+    });
+    if let Some(pos) = pos {
+        // Like burns, harvested flows are negative since they are paid out to the user
+        pos.harvested_base += he.base_flow.unsigned_abs();
+        pos.harvested_quote += he.quote_flow.unsigned_abs();
+        pos.rewards_block = pos.rewards_block.max(he.block_height);
     }
-    ranged_positions
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AmbientPosition {
     pub start_block: Uint256,
+    pub first_block: Uint256,
     pub user: Address,
     pub base: Address,
     pub quote: Address,
@@ -174,49 +499,566 @@ pub struct AmbientPosition {
     pub quote_flow: i128,
 }
 
-// Combines together any corresponding mint_ambient entries, and filters them by any corresponding burn_ambient entries
-fn combine_and_filter_ambient_positions(
-    mint_ambient: Vec<MintAmbientEvent>,
-    burn_ambient: Vec<BurnAmbientEvent>,
-) -> Vec<AmbientPosition> {
-    let mut ambient_positions: Vec<AmbientPosition> = vec![];
-    for ma in mint_ambient {
-        match ambient_positions.iter_mut().find(|v| {
-            v.start_block <= ma.block_height
-                && v.base == ma.base
-                && v.quote == ma.quote
-                && v.pool_idx == ma.pool_idx
-        }) {
-            Some(pos) => {
-                pos.base_flow += ma.base_flow;
-                pos.quote_flow += ma.quote_flow;
-                pos.liq += ma.liq;
-                // We overwrite the block because fees should only apply from the most recent effective mint
-                pos.start_block = ma.block_height;
-            }
-            None => ambient_positions.push(AmbientPosition {
-                start_block: ma.block_height,
-                user: ma.user,
-                base: ma.base,
-                quote: ma.quote,
-                pool_idx: ma.pool_idx,
-                liq: ma.liq,
-                base_flow: ma.base_flow,
-                quote_flow: ma.quote_flow,
-            }),
-        }
-    }
-    for br in burn_ambient {
-        if let Some(idx) = ambient_positions.iter().position(|v| {
-            v.start_block <= br.block_height
-                && v.base == br.base
-                && v.quote == br.quote
-                && v.pool_idx == br.pool_idx
-        }) {
+// Adds the liquidity of a mint to the user's ambient position in the pool, or opens one if there is none
+fn apply_mint_ambient(ambient_positions: &mut Vec<AmbientPosition>, ma: MintAmbientEvent) {
+    match ambient_positions.iter_mut().find(|v| {
+        v.start_block <= ma.block_height
+            && v.base == ma.base
+            && v.quote == ma.quote
+            && v.pool_idx == ma.pool_idx
+    }) {
+        Some(pos) => {
+            pos.base_flow += ma.base_flow;
+            pos.quote_flow += ma.quote_flow;
+            pos.liq += ma.liq;
+            // We overwrite the block because fees should only apply from the most recent effective mint
+            pos.start_block = ma.block_height;
+        }
+        None => ambient_positions.push(AmbientPosition {
+            start_block: ma.block_height,
+            first_block: ma.block_height,
+            user: ma.user,
+            base: ma.base,
+            quote: ma.quote,
+            pool_idx: ma.pool_idx,
+            liq: ma.liq,
+            base_flow: ma.base_flow,
+            quote_flow: ma.quote_flow,
+        }),
+    }
+}
+
+// Reduces the user's ambient position in the pool, removing it once all of its liquidity has been burned
+fn apply_burn_ambient(ambient_positions: &mut Vec<AmbientPosition>, ba: BurnAmbientEvent) {
+    if let Some(idx) = ambient_positions.iter().position(|v| {
+        v.start_block <= ba.block_height
+            && v.base == ba.base
+            && v.quote == ba.quote
+            && v.pool_idx == ba.pool_idx
+    }) {
+        let pos = &mut ambient_positions[idx];
+        pos.base_flow += ba.base_flow;
+        pos.quote_flow += ba.quote_flow;
+        pos.liq = pos.liq.saturating_sub(ba.liq);
+        if pos.liq == 0 {
             ambient_positions.remove(idx);
+        }
+    } else {
+        error!("BurnAmbientEvent without corresponding MintAmbientEvent");
+    }
+}
+
+/// The lifecycle of a knockout (limit order) position
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KnockoutStatus {
+    // The pivot has not been crossed, the position is still providing liquidity
+    Active,
+    // The price crossed the pivot so the position was fully converted, but it has not yet been claimed
+    KnockedOut,
+    // The knocked out position has been claimed or recovered
+    Withdrawn,
+}
+
+impl KnockoutStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KnockoutStatus::Active => "active",
+            KnockoutStatus::KnockedOut => "knocked_out",
+            KnockoutStatus::Withdrawn => "withdrawn",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnockoutPosition {
+    pub start_block: Uint256,
+    pub first_block: Uint256,
+    pub user: Address,
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+    pub bid_tick: i32,
+    pub ask_tick: i32,
+    pub is_bid: bool,
+    pub liq: u128,
+    pub base_flow: i128,
+    pub quote_flow: i128,
+    pub status: KnockoutStatus,
+}
+
+impl KnockoutPosition {
+    // The tick the price must reach for the position to be knocked out, bids are knocked out when the price falls
+    // to the bid tick and asks when it rises to the ask tick
+    pub fn pivot_tick(&self) -> i32 {
+        if self.is_bid {
+            self.bid_tick
+        } else {
+            self.ask_tick
+        }
+    }
+
+    // True if the price at `tick` is past the pivot
+    pub fn knocked_out_at(&self, tick: i32) -> bool {
+        if self.is_bid {
+            tick <= self.bid_tick
+        } else {
+            tick >= self.ask_tick
+        }
+    }
+
+    fn matches(
+        &self,
+        block: Uint256,
+        base: Address,
+        quote: Address,
+        pool_idx: Uint256,
+        (bid_tick, ask_tick, is_bid): (i32, i32, bool),
+    ) -> bool {
+        self.start_block <= block
+            && self.base == base
+            && self.quote == quote
+            && self.pool_idx == pool_idx
+            && self.bid_tick == bid_tick
+            && self.ask_tick == ask_tick
+            && self.is_bid == is_bid
+    }
+}
+
+// Adds a mint to the unwithdrawn knockout position at its ticks, or opens a new position if there is none
+fn apply_mint_knockout(knockout_positions: &mut Vec<KnockoutPosition>, mk: MintKnockoutEvent) {
+    let ticks = (mk.lower_tick, mk.upper_tick, mk.is_bid);
+    let liq = knockout_liquidity(mk.base_flow, mk.quote_flow, mk.lower_tick, mk.upper_tick)
+        .unsigned_abs();
+    // Withdrawn positions are finished, a new mint at the same ticks starts a new position
+    match knockout_positions.iter_mut().find(|v| {
+        v.status != KnockoutStatus::Withdrawn
+            && v.matches(mk.block_height, mk.base, mk.quote, mk.pool_idx, ticks)
+    }) {
+        Some(pos) => {
+            pos.base_flow += mk.base_flow;
+            pos.quote_flow += mk.quote_flow;
+            pos.liq += liq;
+            // We overwrite the block because fees should only apply from the most recent effective mint
+            pos.start_block = mk.block_height;
+        }
+        None => knockout_positions.push(KnockoutPosition {
+            start_block: mk.block_height,
+            first_block: mk.block_height,
+            user: mk.user,
+            base: mk.base,
+            quote: mk.quote,
+            pool_idx: mk.pool_idx,
+            bid_tick: mk.lower_tick,
+            ask_tick: mk.upper_tick,
+            is_bid: mk.is_bid,
+            liq,
+            base_flow: mk.base_flow,
+            quote_flow: mk.quote_flow,
+            status: KnockoutStatus::Active,
+        }),
+    }
+}
+
+// Reduces the active knockout position at the burn's ticks, removing it once all of its liquidity has been burned
+fn apply_burn_knockout(knockout_positions: &mut Vec<KnockoutPosition>, bk: BurnKnockoutEvent) {
+    let ticks = (bk.lower_tick, bk.upper_tick, bk.is_bid);
+    if let Some(idx) = knockout_positions.iter().position(|v| {
+        v.status == KnockoutStatus::Active
+            && v.matches(bk.block_height, bk.base, bk.quote, bk.pool_idx, ticks)
+    }) {
+        let liq = knockout_liquidity(bk.base_flow, bk.quote_flow, bk.lower_tick, bk.upper_tick)
+            .unsigned_abs();
+        let pos = &mut knockout_positions[idx];
+        // Burned flows are negative, since they are paid out to the user
+        pos.base_flow += bk.base_flow;
+        pos.quote_flow += bk.quote_flow;
+        pos.liq = pos.liq.saturating_sub(liq);
+        // The liquidity of each burn is rounded from its flows, so burning an order in parts can leave a
+        // little behind. An order whose deposit has all been paid back is gone either way
+        let deposit = if pos.is_bid {
+            pos.base_flow
         } else {
-            error!("BurnAmbientEvent without corresponding MintAmbientEvent");
+            pos.quote_flow
+        };
+        if pos.liq == 0 || deposit <= 0 {
+            knockout_positions.remove(idx);
         }
+    } else {
+        error!("BurnKnockoutEvent without corresponding MintKnockoutEvent");
     }
-    ambient_positions
+}
+
+// Marks the active knockout position at the withdrawal's ticks as withdrawn
+fn apply_withdraw_knockout(knockout_positions: &mut [KnockoutPosition], wk: WithdrawKnockoutEvent) {
+    let ticks = (wk.lower_tick, wk.upper_tick, wk.is_bid);
+    if let Some(pos) = knockout_positions.iter_mut().find(|v| {
+        v.status == KnockoutStatus::Active
+            && v.matches(wk.block_height, wk.base, wk.quote, wk.pool_idx, ticks)
+    }) {
+        pos.status = KnockoutStatus::Withdrawn;
+    } else {
+        error!("WithdrawKnockoutEvent without corresponding MintKnockoutEvent");
+    }
+}
+
+// Marks Active knockout positions as KnockedOut when a swap has crossed their pivot since they were minted, as recorded
+// while the pool was tracked. Knockouts have no event of their own. A current price past the pivot also counts, since
+// crosses are only recorded once the pool is tracked up to the crossing swap
+fn update_knockout_status(db: &impl Storage, positions: &mut [Position]) {
+    let knockouts = positions.iter_mut().filter_map(|p| match p {
+        Position::Knockout(k) if k.status == KnockoutStatus::Active => Some(k),
+        _ => None,
+    });
+    for pos in knockouts {
+        let current_tick = get_root_price(db, pos.base, pos.quote, pos.pool_idx)
+            .map(|root| tick_from_price(root * root));
+        let pivot = if pos.is_bid {
+            pos.bid_tick
+        } else {
+            pos.ask_tick
+        };
+        let (base, quote, pool_idx) = (pos.base, pos.quote, pos.pool_idx);
+        if current_tick.is_some_and(|t| pos.knocked_out_at(t))
+            || knocked_out_since(
+                db,
+                base,
+                quote,
+                pool_idx,
+                pivot,
+                pos.is_bid,
+                pos.start_block,
+            )
+        {
+            pos.status = KnockoutStatus::KnockedOut;
+        }
+    }
+}
+
+// Builds the position events of a single user in pool 36000 for the tests, ranged positions span 100 ticks from their
+// bid tick and burned or harvested flows are negative like the events from the chain
+#[cfg(test)]
+mod test_events {
+    use super::*;
+
+    fn user() -> Address {
+        Address::from_slice(&[1; 20]).unwrap()
+    }
+
+    pub fn mint_ranged(block: u64, bid_tick: i32, liq: u128) -> MintRangedEvent {
+        MintRangedEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            bid_tick,
+            ask_tick: bid_tick + 100,
+            liq,
+            base_flow: liq as i128,
+            quote_flow: liq as i128,
+            ..Default::default()
+        }
+    }
+
+    pub fn burn_ranged(block: u64, bid_tick: i32, liq: u128) -> BurnRangedEvent {
+        BurnRangedEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            bid_tick,
+            ask_tick: bid_tick + 100,
+            liq,
+            base_flow: -(liq as i128),
+            quote_flow: -(liq as i128),
+            ..Default::default()
+        }
+    }
+
+    pub fn harvest(block: u64, bid_tick: i32, base: i128, quote: i128) -> HarvestEvent {
+        HarvestEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            bid_tick,
+            ask_tick: bid_tick + 100,
+            base_flow: -base,
+            quote_flow: -quote,
+            ..Default::default()
+        }
+    }
+
+    pub fn mint_ambient(block: u64, liq: u128) -> MintAmbientEvent {
+        MintAmbientEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            liq,
+            base_flow: liq as i128,
+            quote_flow: 2 * liq as i128,
+            ..Default::default()
+        }
+    }
+
+    pub fn burn_ambient(block: u64, liq: u128) -> BurnAmbientEvent {
+        BurnAmbientEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            liq,
+            base_flow: -(liq as i128),
+            quote_flow: -2 * liq as i128,
+            ..Default::default()
+        }
+    }
+
+    // Knockouts deposit 1,000,000 of base for bids and of quote for asks
+    pub fn mint_knockout(block: u64, lower: i32, upper: i32, is_bid: bool) -> MintKnockoutEvent {
+        MintKnockoutEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            base_flow: if is_bid { 1_000_000 } else { 0 },
+            quote_flow: if is_bid { 0 } else { 1_000_000 },
+            is_bid,
+            lower_tick: lower,
+            upper_tick: upper,
+            ..Default::default()
+        }
+    }
+
+    pub fn burn_knockout(block: u64, lower: i32, upper: i32, is_bid: bool) -> BurnKnockoutEvent {
+        BurnKnockoutEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            base_flow: if is_bid { -1_000_000 } else { 0 },
+            quote_flow: if is_bid { 0 } else { -1_000_000 },
+            is_bid,
+            lower_tick: lower,
+            upper_tick: upper,
+            ..Default::default()
+        }
+    }
+
+    pub fn withdraw_knockout(
+        block: u64,
+        lower: i32,
+        upper: i32,
+        is_bid: bool,
+    ) -> WithdrawKnockoutEvent {
+        WithdrawKnockoutEvent {
+            block_height: block.into(),
+            user: user(),
+            pool_idx: 36000u64.into(),
+            is_bid,
+            lower_tick: lower,
+            upper_tick: upper,
+            ..Default::default()
+        }
+    }
+}
+
+#[test]
+fn test_partial_burn_ranged_positions() {
+    use test_events::{burn_ranged, mint_ranged};
+
+    // A 10% burn leaves the rest of the position
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        burn_ranged(2, 0, 100).into(),
+    ])
+    .ranged;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].liq, 900);
+    assert_eq!(positions[0].base_flow, 900);
+    assert_eq!(positions[0].quote_flow, 900);
+    assert_eq!(positions[0].start_block, 1u64.into());
+
+    // Mint, partial burn, re-mint, partial burn, with a second position burned completely
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        mint_ranged(2, 200, 500).into(),
+        mint_ranged(4, 0, 300).into(),
+        burn_ranged(3, 0, 400).into(),
+        burn_ranged(5, 0, 200).into(),
+        burn_ranged(6, 200, 500).into(),
+    ])
+    .ranged;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].bid_tick, 0);
+    assert_eq!(positions[0].liq, 700);
+    assert_eq!(positions[0].base_flow, 700);
+    assert_eq!(positions[0].start_block, 4u64.into());
+
+    // Burning everything removes the position, and a later mint starts a new one
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        mint_ranged(4, 0, 250).into(),
+        burn_ranged(2, 0, 600).into(),
+        burn_ranged(3, 0, 400).into(),
+    ])
+    .ranged;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].liq, 250);
+    assert_eq!(positions[0].base_flow, 250);
+    assert_eq!(positions[0].start_block, 4u64.into());
+
+    // Events in the same block are ordered by log index
+    let mut late_mint = mint_ranged(2, 0, 100);
+    late_mint.index = 2u8.into();
+    let mut early_burn = burn_ranged(2, 0, 1000);
+    early_burn.index = 1u8.into();
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        late_mint.into(),
+        early_burn.into(),
+    ])
+    .ranged;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].liq, 100);
+}
+
+#[test]
+fn test_apply_harvests() {
+    use test_events::{burn_ranged, harvest, mint_ranged};
+
+    // Harvests on the same range add up and move the reward accounting forward, without touching the liquidity.
+    // The harvest from the burned position at tick 0 and the one on another range are ignored
+    let mut burned_harvest = harvest(1, 0, 50, 50);
+    burned_harvest.index = 1u8.into();
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        burned_harvest.into(),
+        burn_ranged(2, 0, 1000).into(),
+        mint_ranged(3, 0, 500).into(),
+        mint_ranged(4, 200, 100).into(),
+        harvest(5, 0, 10, 20).into(),
+        harvest(7, 0, 5, 0).into(),
+        harvest(8, 100, 1, 1).into(),
+    ])
+    .ranged;
+    assert_eq!(positions.len(), 2);
+    let pos = &positions[0];
+    assert_eq!(pos.liq, 500);
+    assert_eq!(pos.base_flow, 500);
+    assert_eq!((pos.harvested_base, pos.harvested_quote), (15, 20));
+    assert_eq!(pos.start_block, 3u64.into());
+    assert_eq!(pos.rewards_block, 7u64.into());
+    let pos = &positions[1];
+    assert_eq!((pos.harvested_base, pos.harvested_quote), (0, 0));
+    assert_eq!(pos.rewards_block, 4u64.into());
+
+    // A mint after a harvest restarts the reward accounting
+    let positions = PoolPositions::replay([
+        mint_ranged(1, 0, 1000).into(),
+        harvest(5, 0, 10, 10).into(),
+        mint_ranged(6, 0, 100).into(),
+    ])
+    .ranged;
+    assert_eq!(positions[0].harvested_base, 10);
+    assert_eq!(positions[0].rewards_block, 6u64.into());
+}
+
+#[test]
+fn test_partial_burn_ambient_positions() {
+    use test_events::{burn_ambient, mint_ambient};
+
+    let positions = PoolPositions::replay([
+        mint_ambient(1, 1000).into(),
+        mint_ambient(3, 500).into(),
+        burn_ambient(2, 250).into(),
+        burn_ambient(4, 750).into(),
+    ])
+    .ambient;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].liq, 500);
+    assert_eq!(positions[0].base_flow, 500);
+    assert_eq!(positions[0].quote_flow, 1000);
+
+    // Burning more than is known (e.g. after compounded rewards) still removes the position
+    let positions = PoolPositions::replay([
+        mint_ambient(1, 1000).into(),
+        mint_ambient(3, 500).into(),
+        burn_ambient(2, 1100).into(),
+        burn_ambient(4, 100).into(),
+    ])
+    .ambient;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].liq, 400);
+    assert_eq!(positions[0].start_block, 3u64.into());
+}
+
+#[test]
+fn test_concentrated_amounts() {
+    let (lower, upper) = (root_price_from_tick(-100), root_price_from_tick(100));
+    // In range the position holds both tokens
+    let (base, quote) = concentrated_amounts(1e6, 1.0, -100, 100);
+    assert!((base - 1e6 * (1.0 - lower)).abs() < 1e-6);
+    assert!((quote - 1e6 * (1.0 - 1.0 / upper)).abs() < 1e-6);
+    // Below the range it is all quote, above all base
+    let (base, quote) = concentrated_amounts(1e6, lower * 0.9, -100, 100);
+    assert_eq!(base, 0.0);
+    assert!((quote - 1e6 * (1.0 / lower - 1.0 / upper)).abs() < 1e-6);
+    let (base, quote) = concentrated_amounts(1e6, upper * 1.1, -100, 100);
+    assert!((base - 1e6 * (upper - lower)).abs() < 1e-6);
+    assert_eq!(quote, 0.0);
+}
+
+#[test]
+fn test_combine_knockout_positions() {
+    use test_events::{burn_knockout, mint_knockout, withdraw_knockout};
+
+    let positions = PoolPositions::replay([
+        mint_knockout(1, -64, -48, true).into(),
+        mint_knockout(2, -64, -48, true).into(),
+        mint_knockout(3, 16, 32, false).into(),
+        mint_knockout(4, 48, 64, false).into(),
+        burn_knockout(5, 48, 64, false).into(),
+        withdraw_knockout(6, 16, 32, false).into(),
+        // Re-minted after withdrawal, so a new position
+        mint_knockout(7, 16, 32, false).into(),
+    ])
+    .knockout;
+    assert_eq!(positions.len(), 3);
+    // The two bids are combined into one active position
+    assert_eq!(positions[0].status, KnockoutStatus::Active);
+    assert_eq!(positions[0].base_flow, 2_000_000);
+    assert_eq!(positions[0].start_block, 2u64.into());
+    assert!(positions[0].liq > 0);
+    assert_eq!(positions[0].pivot_tick(), -64);
+    // The first ask was withdrawn, the second one was burned before being knocked out
+    assert_eq!(positions[1].status, KnockoutStatus::Withdrawn);
+    assert_eq!(positions[1].start_block, 3u64.into());
+    assert_eq!(positions[2].status, KnockoutStatus::Active);
+    assert_eq!(positions[2].start_block, 7u64.into());
+    assert_eq!(positions[2].pivot_tick(), 32);
+
+    // Bids are knocked out when the price falls to their bid tick, asks when it rises to their ask tick
+    assert!(positions[0].knocked_out_at(-64));
+    assert!(!positions[0].knocked_out_at(-63));
+    assert!(positions[2].knocked_out_at(32));
+    assert!(!positions[2].knocked_out_at(31));
+
+    // A partial burn leaves the rest of the order, burning the remainder removes it
+    let mut partial = burn_knockout(3, -64, -48, true);
+    partial.base_flow = -500_000;
+    let positions = PoolPositions::replay([
+        mint_knockout(1, -64, -48, true).into(),
+        mint_knockout(2, -64, -48, true).into(),
+        partial.clone().into(),
+    ])
+    .knockout;
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].base_flow, 1_500_000);
+    let full_liq = knockout_liquidity(1_000_000, 0, -64, -48).unsigned_abs();
+    let burned_liq = knockout_liquidity(-500_000, 0, -64, -48).unsigned_abs();
+    assert_eq!(positions[0].liq, 2 * full_liq - burned_liq);
+    let positions = PoolPositions::replay([
+        mint_knockout(1, -64, -48, true).into(),
+        partial.clone().into(),
+        {
+            partial.block_height = 4u64.into();
+            partial.into()
+        },
+    ])
+    .knockout;
+    assert!(positions.is_empty());
 }
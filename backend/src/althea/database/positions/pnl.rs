@@ -0,0 +1,93 @@
+// This file compares a position's current worth against holding the tokens that were deposited into it
+
+use serde::{Deserialize, Serialize};
+
+/// The profit and loss of a position, values are in the quote token's smallest units at the pool's current price.
+/// The USD values are only available when the quote token could be priced in USD
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PositionPnl {
+    // The net deposited flows (mints less burns) and what the position could currently be redeemed for
+    pub deposited_base: f64,
+    pub deposited_quote: f64,
+    pub current_base: f64,
+    pub current_quote: f64,
+    // Fees earned on top of the liquidity
+    pub rewards_base: f64,
+    pub rewards_quote: f64,
+    // The value of simply holding the deposited tokens, of the position's liquidity, and of its rewards
+    pub hold_value: f64,
+    pub position_value: f64,
+    pub rewards_value: f64,
+    // position_value + rewards_value - hold_value
+    pub pnl: f64,
+    pub pnl_usd: Option<f64>,
+    // hold_value - position_value, positive when providing liquidity lost value compared to holding
+    pub impermanent_loss: f64,
+    pub impermanent_loss_usd: Option<f64>,
+    // impermanent_loss as a fraction of hold_value, e.g. 0.05 for 5%
+    pub impermanent_loss_ratio: f64,
+}
+
+/// Values a position with net deposits `deposited` and current redeemable amounts `current`, both (base, quote),
+/// plus `rewards` at `price` (base per quote). `quote_usd` is the USD value of a single unit of the quote token
+pub fn position_pnl(
+    deposited: (f64, f64),
+    current: (f64, f64),
+    rewards: (f64, f64),
+    price: f64,
+    quote_usd: Option<f64>,
+) -> PositionPnl {
+    let value = |(base, quote): (f64, f64)| base / price + quote;
+    let hold_value = value(deposited);
+    let position_value = value(current);
+    let rewards_value = value(rewards);
+    let pnl = position_value + rewards_value - hold_value;
+    let impermanent_loss = hold_value - position_value;
+    let impermanent_loss_ratio = if hold_value > 0.0 {
+        impermanent_loss / hold_value
+    } else {
+        0.0
+    };
+    PositionPnl {
+        deposited_base: deposited.0,
+        deposited_quote: deposited.1,
+        current_base: current.0,
+        current_quote: current.1,
+        rewards_base: rewards.0,
+        rewards_quote: rewards.1,
+        hold_value,
+        position_value,
+        rewards_value,
+        pnl,
+        pnl_usd: quote_usd.map(|p| pnl * p),
+        impermanent_loss,
+        impermanent_loss_usd: quote_usd.map(|p| impermanent_loss * p),
+        impermanent_loss_ratio,
+    }
+}
+
+#[test]
+fn test_position_pnl() {
+    // An ambient position of liquidity 1000 minted at price 1 (1000 of each token), after the price quadruples it holds
+    // 2000 base and 500 quote, worth 1000 quote in total against 1250 for holding
+    let pnl = position_pnl(
+        (1000.0, 1000.0),
+        (2000.0, 500.0),
+        (0.0, 0.0),
+        4.0,
+        Some(2.0),
+    );
+    assert_eq!(pnl.hold_value, 1250.0);
+    assert_eq!(pnl.position_value, 1000.0);
+    assert_eq!(pnl.impermanent_loss, 250.0);
+    assert_eq!(pnl.impermanent_loss_ratio, 0.2);
+    assert_eq!(pnl.impermanent_loss_usd, Some(500.0));
+    assert_eq!(pnl.pnl, -250.0);
+
+    // Rewards offset the loss
+    let pnl = position_pnl((1000.0, 1000.0), (2000.0, 500.0), (400.0, 200.0), 4.0, None);
+    assert_eq!(pnl.rewards_value, 300.0);
+    assert_eq!(pnl.pnl, 50.0);
+    assert_eq!(pnl.impermanent_loss, 250.0);
+    assert_eq!(pnl.pnl_usd, None);
+}
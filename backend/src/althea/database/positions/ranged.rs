@@ -2,6 +2,9 @@ use clarity::Address;
 use clarity::Uint256;
 use log::debug;
 
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
+
 use crate::althea::ambient::positions::HarvestEvent;
 
 use super::super::super::ambient::positions::{BurnRangedEvent, MintRangedEvent};
@@ -63,7 +66,7 @@ pub fn mint_ranged_key(
 // Gets a single MintRanged event from `db` by the other arguments, returns none if it does not exist
 #[allow(clippy::too_many_arguments)]
 pub fn get_mint_ranged(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -76,26 +79,26 @@ pub fn get_mint_ranged(
     let k = mint_ranged_key(
         user, base, quote, pool_idx, bid_tick, ask_tick, block, index,
     );
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(MINT_RANGED_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known MintRanged events from the database
-pub fn get_all_mint_ranged(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<MintRangedEvent> {
+pub fn get_all_mint_ranged(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<MintRangedEvent> {
     let prefix = prefix.unwrap_or_else(|| MINT_RANGED_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(MINT_RANGED_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let pool: MintRangedEvent = bincode::deserialize(&v).unwrap();
+                let pool: MintRangedEvent = deserialize_record(&v).unwrap();
                 events.push(pool);
             }
             Err(_) => break,
@@ -105,7 +108,7 @@ pub fn get_all_mint_ranged(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<MintR
 }
 
 pub fn get_all_mint_ranged_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<MintRangedEvent> {
@@ -117,7 +120,7 @@ pub fn get_all_mint_ranged_after_block(
         .collect()
 }
 
-pub fn save_mint_ranged(db: &rocksdb::DB, mre: MintRangedEvent) {
+pub fn save_mint_ranged(db: &impl Storage, mre: MintRangedEvent) {
     let k = mint_ranged_key(
         mre.user,
         mre.base,
@@ -129,9 +132,9 @@ pub fn save_mint_ranged(db: &rocksdb::DB, mre: MintRangedEvent) {
         mre.index,
     );
     debug!("Saving MintRangedEvent to key {}", k);
-    let v = bincode::serialize(&mre).unwrap();
+    let v = serialize_record(&mre).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(MINT_RANGED_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const BURN_RANGED_PREFIX: &str = "burn-ranged_";
@@ -191,7 +194,7 @@ pub fn burn_ranged_key(
 // Gets a single BurnRanged event from `db` by the other arguments, returns none if it does not exist
 #[allow(clippy::too_many_arguments)]
 pub fn get_burn_ranged(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -204,26 +207,26 @@ pub fn get_burn_ranged(
     let k = burn_ranged_key(
         user, base, quote, pool_idx, bid_tick, ask_tick, block, index,
     );
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(BURN_RANGED_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known BurnRanged events from the database
-pub fn get_all_burn_ranged(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<BurnRangedEvent> {
+pub fn get_all_burn_ranged(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<BurnRangedEvent> {
     let prefix = prefix.unwrap_or_else(|| BURN_RANGED_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(BURN_RANGED_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: BurnRangedEvent = bincode::deserialize(&v).unwrap();
+                let event: BurnRangedEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -233,7 +236,7 @@ pub fn get_all_burn_ranged(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<BurnR
 }
 
 pub fn get_all_burn_ranged_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<BurnRangedEvent> {
@@ -245,7 +248,7 @@ pub fn get_all_burn_ranged_after_block(
         .collect()
 }
 
-pub fn save_burn_ranged(db: &rocksdb::DB, bre: BurnRangedEvent) {
+pub fn save_burn_ranged(db: &impl Storage, bre: BurnRangedEvent) {
     let k = burn_ranged_key(
         bre.user,
         bre.base,
@@ -257,9 +260,9 @@ pub fn save_burn_ranged(db: &rocksdb::DB, bre: BurnRangedEvent) {
         bre.index,
     );
     debug!("Saving BurnRangedEvent to key {}", k);
-    let v = bincode::serialize(&bre).unwrap();
+    let v = serialize_record(&bre).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(BURN_RANGED_PREFIX, k.as_bytes(), &v).unwrap();
 }
 
 pub const HARVEST_PREFIX: &str = "harvest_";
@@ -318,7 +321,7 @@ pub fn harvest_key(
 // Gets a single Harvest event from `db` by the other arguments, returns none if it does not exist
 #[allow(clippy::too_many_arguments)]
 pub fn get_harvest(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     user: Address,
     base: Address,
     quote: Address,
@@ -327,30 +330,30 @@ pub fn get_harvest(
     ask_tick: i32,
     block: Uint256,
     index: Uint256,
-) -> Option<BurnRangedEvent> {
+) -> Option<HarvestEvent> {
     let k = harvest_key(
         user, base, quote, pool_idx, bid_tick, ask_tick, block, index,
     );
-    let v = db.get(k.as_bytes()).unwrap();
+    let v = db.get(HARVEST_PREFIX, k.as_bytes()).unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    Some(bincode::deserialize(&v.unwrap()).unwrap())
+    Some(deserialize_record(&v.unwrap()).unwrap())
 }
 
 // Gets all known Harvest events from the database
-pub fn get_all_harvest(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<HarvestEvent> {
+pub fn get_all_harvest(db: &impl Storage, prefix: Option<&[u8]>) -> Vec<HarvestEvent> {
     let prefix = prefix.unwrap_or_else(|| HARVEST_PREFIX.as_bytes());
     let mut events = vec![];
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(HARVEST_PREFIX, prefix);
     for entry in iter {
         match entry {
             Ok((k, v)) => {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let event: HarvestEvent = bincode::deserialize(&v).unwrap();
+                let event: HarvestEvent = deserialize_record(&v).unwrap();
                 events.push(event);
             }
             Err(_) => break,
@@ -360,7 +363,7 @@ pub fn get_all_harvest(db: &rocksdb::DB, prefix: Option<&[u8]>) -> Vec<HarvestEv
 }
 
 pub fn get_all_harvest_after_block(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     prefix: Option<&[u8]>,
     block: Uint256,
 ) -> Vec<HarvestEvent> {
@@ -372,8 +375,8 @@ pub fn get_all_harvest_after_block(
         .collect()
 }
 
-pub fn save_harvest(db: &rocksdb::DB, he: HarvestEvent) {
-    let k = burn_ranged_key(
+pub fn save_harvest(db: &impl Storage, he: HarvestEvent) {
+    let k = harvest_key(
         he.user,
         he.base,
         he.quote,
@@ -384,7 +387,7 @@ pub fn save_harvest(db: &rocksdb::DB, he: HarvestEvent) {
         he.index,
     );
     debug!("Saving HarvestEvent to key {}", k);
-    let v = bincode::serialize(&he).unwrap();
+    let v = serialize_record(&he).unwrap();
 
-    db.put(k.as_bytes(), v).unwrap();
+    db.put(HARVEST_PREFIX, k.as_bytes(), &v).unwrap();
 }
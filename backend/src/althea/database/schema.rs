@@ -0,0 +1,562 @@
+// This file tracks the layout version of the stored records and upgrades records written by older versions in place, so that
+// changing a stored struct or key does not require deleting the records and resyncing from the first block.
+//
+// Records are stored as the schema version they were written in followed by the bincode of their struct, see
+// serialize_record. A change to a stored struct or key bumps SCHEMA_VERSION and adds a Migration to MIGRATIONS which reads
+// the previous encoding, keeping the old struct next to the migration if it is needed to decode it, and rewrites the record
+// in the new one. Records the migrations cannot read are left for clear_invalid_entries.
+
+use std::collections::HashSet;
+use std::time::Instant;
+
+use bincode::Options;
+use log::{debug, info};
+use rocksdb::{IteratorMode, WriteBatch, DB};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::althea::ambient::positions::{BurnRangedEvent, HarvestEvent};
+
+use super::{
+    column_families::{cf, family_for_key},
+    curve::LATEST_CURVE_KEY,
+    get_latest_searched_block,
+    pools::{INIT_POOL_PREFIX, POOL_TEMPLATE_PREFIX, REVISION_PREFIX, SWAP_PREFIX},
+    positions::{
+        ambient::{BURN_AMBIENT_PREFIX, MINT_AMBIENT_PREFIX},
+        index::{check_position_index, ACTIVE_POSITIONS_PREFIX},
+        knockout::{BURN_KNOCKOUT_PREFIX, MINT_KNOCKOUT_PREFIX, WITHDRAW_KNOCKOUT_PREFIX},
+        ranged::{harvest_key, BURN_RANGED_PREFIX, HARVEST_PREFIX, MINT_RANGED_PREFIX},
+    },
+    storage::{Storage, DEFAULT_FAMILY},
+    tokens::TOKEN_METADATA_PREFIX,
+    tracking::{
+        candles::CANDLE_PREFIX, history::POOL_SNAPSHOT_PREFIX, TrackedPool, DIRTY_POOL_PREFIX,
+        TEMPLATE_FEE_UNIT, TRACKED_POOL_PREFIX,
+    },
+    transactions::POOL_TX_PREFIX,
+};
+
+pub const SCHEMA_VERSION_KEY: &str = "schema-version";
+pub const MIGRATION_PROGRESS_KEY: &str = "schema-migration-progress";
+
+/// The layout written by this version, equal to the version of the last migration
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// The version from which every record starts with the version it was written in
+pub const RECORD_VERSION_SCHEMA: u32 = 2;
+const RECORD_VERSION_LEN: usize = 4;
+
+/// The families whose records are stored with serialize_record, the others hold raw bytes
+pub const RECORD_FAMILIES: [&str; 20] = [
+    INIT_POOL_PREFIX,
+    POOL_TEMPLATE_PREFIX,
+    SWAP_PREFIX,
+    REVISION_PREFIX,
+    MINT_RANGED_PREFIX,
+    BURN_RANGED_PREFIX,
+    HARVEST_PREFIX,
+    MINT_AMBIENT_PREFIX,
+    BURN_AMBIENT_PREFIX,
+    MINT_KNOCKOUT_PREFIX,
+    BURN_KNOCKOUT_PREFIX,
+    WITHDRAW_KNOCKOUT_PREFIX,
+    POOL_TX_PREFIX,
+    CANDLE_PREFIX,
+    POOL_SNAPSHOT_PREFIX,
+    ACTIVE_POSITIONS_PREFIX,
+    TRACKED_POOL_PREFIX,
+    DIRTY_POOL_PREFIX,
+    LATEST_CURVE_KEY,
+    TOKEN_METADATA_PREFIX,
+];
+
+/// How many records are written per batch while migrating
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+/// How often a migration logs its progress, in records checked
+const MIGRATION_PROGRESS_INTERVAL: usize = 100_000;
+
+/// Encodes a record for storage, as the schema version it is written in (4 big endian bytes) followed by its bincode, so
+/// that a migration can tell which records it has to upgrade
+pub fn serialize_record<T: Serialize + ?Sized>(record: &T) -> bincode::Result<Vec<u8>> {
+    tag_record(SCHEMA_VERSION, &bincode::serialize(record)?)
+}
+
+/// Decodes a record written by serialize_record, in any version since the last migration of its family
+pub fn deserialize_record<T: DeserializeOwned>(value: &[u8]) -> bincode::Result<T> {
+    match split_record(value) {
+        Some((_, body)) => bincode::deserialize(body),
+        None => Err(Box::new(bincode::ErrorKind::Custom(
+            "Record is missing its version".to_string(),
+        ))),
+    }
+}
+
+/// Splits a stored record into the schema version it was written in and its bincode
+pub fn split_record(value: &[u8]) -> Option<(u32, &[u8])> {
+    if value.len() < RECORD_VERSION_LEN {
+        return None;
+    }
+    let (version, body) = value.split_at(RECORD_VERSION_LEN);
+    Some((u32::from_be_bytes(version.try_into().ok()?), body))
+}
+
+fn tag_record(version: u32, body: &[u8]) -> bincode::Result<Vec<u8>> {
+    let mut v = Vec::with_capacity(RECORD_VERSION_LEN + body.len());
+    v.extend_from_slice(&version.to_be_bytes());
+    v.extend_from_slice(body);
+    Ok(v)
+}
+
+/// Gets the layout version of the stored records, None for databases created before versions were stored
+pub fn get_schema_version(db: &impl Storage) -> Option<u32> {
+    let v = db
+        .get(DEFAULT_FAMILY, SCHEMA_VERSION_KEY.as_bytes())
+        .unwrap()?;
+    Some(u32::from_be_bytes(v.as_slice().try_into().ok()?))
+}
+
+pub fn save_schema_version(db: &impl Storage, version: u32) {
+    debug!("Saving schema version {}", version);
+    db.put(
+        DEFAULT_FAMILY,
+        SCHEMA_VERSION_KEY.as_bytes(),
+        &version.to_be_bytes(),
+    )
+    .unwrap();
+}
+
+/// How far an interrupted migration got, saved with every batch it writes so a restart continues after the last
+/// written record instead of upgrading records twice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationProgress {
+    pub version: u32,
+    pub family: String,
+    pub key: Vec<u8>,
+}
+
+fn get_migration_progress(db: &DB) -> Option<MigrationProgress> {
+    let v = db.get(MIGRATION_PROGRESS_KEY).unwrap()?;
+    bincode::deserialize(&v).ok()
+}
+
+/// What a migration does with one record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upgrade {
+    /// The record is already in the new layout
+    Keep,
+    /// The record is rewritten under the same key
+    Rewrite(Vec<u8>),
+    /// The record is deleted and written under a new key, possibly in another family
+    Move {
+        family: &'static str,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    /// The record is deleted, for records the migration's finish rebuilds
+    Delete,
+    /// The record is in neither layout
+    Unreadable,
+}
+
+/// Upgrades the records of some families from the previous schema version to `version`
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub families: &'static [&'static str],
+    /// Decides what to do with each record, given its key and value. Migrations after RECORD_VERSION_SCHEMA are only
+    /// given records written before `version`, without the version, and the values they write are tagged with `version`
+    pub upgrade: fn(&[u8], &[u8]) -> Upgrade,
+    /// Run once after every pending migration is written, to rebuild anything derived from the changed records
+    pub finish: Option<fn(&DB)>,
+}
+
+/// Every migration, in version order
+pub const MIGRATIONS: [Migration; 4] = [
+    Migration {
+        version: 1,
+        description: "Move harvests stored under burn-ranged keys to the harvest family",
+        families: &[BURN_RANGED_PREFIX],
+        upgrade: move_misfiled_harvest,
+        finish: Some(repair_position_index),
+    },
+    Migration {
+        version: RECORD_VERSION_SCHEMA,
+        description: "Prefix every record with the version it was written in",
+        families: &RECORD_FAMILIES,
+        upgrade: add_record_version,
+        finish: None,
+    },
+    Migration {
+        version: 3,
+        description: "Convert tracked pools' fee rate from the template's raw value to a fraction",
+        families: &[TRACKED_POOL_PREFIX],
+        upgrade: convert_tracked_pool_fee_rate,
+        finish: None,
+    },
+    Migration {
+        version: 4,
+        description: "Rebuild active positions, which now record the last event applied to them",
+        families: &[ACTIVE_POSITIONS_PREFIX],
+        upgrade: drop_active_positions,
+        finish: Some(repair_position_index),
+    },
+];
+
+/// The counts of records a migration run went through
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub checked: usize,
+    pub rewritten: usize,
+    pub moved: usize,
+    pub deleted: usize,
+    pub unreadable: usize,
+}
+
+impl MigrationReport {
+    /// The number of records which were, or in a dry run would be, written
+    pub fn changed(&self) -> usize {
+        self.rewritten + self.moved + self.deleted
+    }
+}
+
+/// Runs every migration newer than the stored schema version and stores the new version. In a dry run nothing is written
+/// and the report counts what would have changed
+pub fn migrate(db: &DB, dry_run: bool) -> MigrationReport {
+    let stored = get_schema_version(db);
+    if stored.is_none() && get_latest_searched_block(db).is_none() {
+        // A new database, there is nothing written in an older layout
+        if !dry_run {
+            save_schema_version(db, SCHEMA_VERSION);
+        }
+        return MigrationReport {
+            from: SCHEMA_VERSION,
+            to: SCHEMA_VERSION,
+            ..Default::default()
+        };
+    }
+    let from = stored.unwrap_or(0);
+    let mut report = MigrationReport {
+        from,
+        to: SCHEMA_VERSION,
+        ..Default::default()
+    };
+    if from > SCHEMA_VERSION {
+        panic!(
+            "Database schema version {} is newer than this build's version {}, refusing to open it",
+            from, SCHEMA_VERSION
+        );
+    }
+    // The records only carry their version once that migration has actually been written
+    let tagged = !dry_run || from >= RECORD_VERSION_SCHEMA;
+    let mut finishes: Vec<fn(&DB)> = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|m| m.version > from) {
+        let changed = run_migration(db, migration, dry_run, tagged, &mut report);
+        if let Some(finish) = migration.finish.filter(|_| changed) {
+            finishes.push(finish);
+        }
+    }
+    if !dry_run {
+        let mut seen = HashSet::new();
+        for finish in finishes {
+            if seen.insert(finish as usize) {
+                finish(db);
+            }
+        }
+    }
+    report
+}
+
+// Runs a single migration over its families, returning true if it changed any records (counting those changed before a
+// restart). The schema version is saved with the last batch, and every earlier batch saves the migration's progress
+fn run_migration(
+    db: &DB,
+    migration: &Migration,
+    dry_run: bool,
+    tagged: bool,
+    report: &mut MigrationReport,
+) -> bool {
+    let start = Instant::now();
+    let estimate: u64 = migration
+        .families
+        .iter()
+        .map(|family| {
+            db.property_int_value_cf(cf(db, family), "rocksdb.estimate-num-keys")
+                .ok()
+                .flatten()
+                .unwrap_or(0)
+        })
+        .sum();
+    info!(
+        "{}migration {}: {}, about {} records to check",
+        if dry_run { "Dry run of " } else { "" },
+        migration.version,
+        migration.description,
+        estimate
+    );
+    let versioned = tagged && migration.version > RECORD_VERSION_SCHEMA;
+    let retag = |value: Vec<u8>| {
+        if versioned {
+            tag_record(migration.version, &value).unwrap()
+        } else {
+            value
+        }
+    };
+    let resume = get_migration_progress(db).filter(|p| p.version == migration.version);
+    let skip_families = resume.as_ref().map_or(0, |p| {
+        migration
+            .families
+            .iter()
+            .position(|f| *f == p.family)
+            .unwrap_or(0)
+    });
+
+    let mut changed = 0;
+    let mut checked = 0;
+    let mut batch = WriteBatch::default();
+    for name in migration.families.iter().skip(skip_families) {
+        let family = cf(db, name);
+        let records = match resume.as_ref().filter(|p| p.family == *name) {
+            Some(p) => db.iterator_cf(
+                family,
+                IteratorMode::From(&p.key, rocksdb::Direction::Forward),
+            ),
+            None => db.iterator_cf(family, IteratorMode::Start),
+        };
+        // A dry run on a database from before column families finds the records still in the default family, where the
+        // real run would already have moved them into their own
+        let pending = dry_run.then(|| {
+            db.iterator(IteratorMode::From(
+                name.as_bytes(),
+                rocksdb::Direction::Forward,
+            ))
+            .flatten()
+            .take_while(|(k, _)| k.starts_with(name.as_bytes()))
+            .filter(|(k, _)| family_for_key(k) == Some(*name))
+        });
+        let records = records.flatten().chain(pending.into_iter().flatten());
+        for (k, v) in records {
+            if resume
+                .as_ref()
+                .is_some_and(|p| p.family == *name && *p.key == *k)
+            {
+                continue;
+            }
+            checked += 1;
+            let upgrade = if versioned {
+                match split_record(&v) {
+                    Some((version, _)) if version >= migration.version => Upgrade::Keep,
+                    Some((_, body)) => (migration.upgrade)(&k, body),
+                    None => Upgrade::Unreadable,
+                }
+            } else {
+                (migration.upgrade)(&k, &v)
+            };
+            match upgrade {
+                Upgrade::Keep => {}
+                Upgrade::Unreadable => report.unreadable += 1,
+                Upgrade::Rewrite(value) => {
+                    report.rewritten += 1;
+                    changed += 1;
+                    batch.put_cf(family, &k, retag(value));
+                }
+                Upgrade::Move {
+                    family: to,
+                    key,
+                    value,
+                } => {
+                    report.moved += 1;
+                    changed += 1;
+                    batch.delete_cf(family, &k);
+                    batch.put_cf(cf(db, to), key, retag(value));
+                }
+                Upgrade::Delete => {
+                    report.deleted += 1;
+                    changed += 1;
+                    batch.delete_cf(family, &k);
+                }
+            }
+            if batch.len() >= MIGRATION_BATCH_SIZE {
+                let mut full = std::mem::take(&mut batch);
+                if !dry_run {
+                    let progress = MigrationProgress {
+                        version: migration.version,
+                        family: name.to_string(),
+                        key: k.to_vec(),
+                    };
+                    full.put(
+                        MIGRATION_PROGRESS_KEY,
+                        bincode::serialize(&progress).unwrap(),
+                    );
+                    db.write(full).unwrap();
+                }
+            }
+            if checked % MIGRATION_PROGRESS_INTERVAL == 0 {
+                info!(
+                    "Migration {}: checked {} of about {} records, {} changed",
+                    migration.version, checked, estimate, changed
+                );
+            }
+        }
+    }
+    if !dry_run {
+        batch.delete(MIGRATION_PROGRESS_KEY);
+        batch.put(SCHEMA_VERSION_KEY, migration.version.to_be_bytes());
+        db.write(batch).unwrap();
+    }
+    report.checked += checked;
+    info!(
+        "Migration {}: {} {} of {} records in {:?}",
+        migration.version,
+        if dry_run { "would change" } else { "changed" },
+        changed,
+        checked,
+        start.elapsed()
+    );
+    changed > 0 || resume.is_some()
+}
+
+/// Decodes `value` as exactly a T, unlike bincode::deserialize which ignores trailing bytes and so would read a longer
+/// record as a shorter one
+fn decode_exact<T: DeserializeOwned>(value: &[u8]) -> Option<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(value)
+        .ok()
+}
+
+/// Upgrades a record whose struct changed from Old to New, for migrations which keep the key
+pub fn upgrade_from<Old, New>(value: &[u8]) -> Upgrade
+where
+    Old: DeserializeOwned + Into<New>,
+    New: Serialize,
+{
+    match decode_exact::<Old>(value) {
+        Some(old) => Upgrade::Rewrite(bincode::serialize(&old.into()).unwrap()),
+        None => Upgrade::Unreadable,
+    }
+}
+
+/// Harvests used to be saved under burn-ranged keys, where they fail to decode as burns
+fn move_misfiled_harvest(_key: &[u8], value: &[u8]) -> Upgrade {
+    if decode_exact::<BurnRangedEvent>(value).is_some() {
+        return Upgrade::Keep;
+    }
+    match decode_exact::<HarvestEvent>(value) {
+        Some(he) => Upgrade::Move {
+            family: HARVEST_PREFIX,
+            key: harvest_key(
+                he.user,
+                he.base,
+                he.quote,
+                he.pool_idx,
+                he.bid_tick,
+                he.ask_tick,
+                he.block_height,
+                he.index,
+            )
+            .into_bytes(),
+            value: value.to_vec(),
+        },
+        None => Upgrade::Unreadable,
+    }
+}
+
+/// Records written before versions were stored are all in the layout of the versions before RECORD_VERSION_SCHEMA
+fn add_record_version(_key: &[u8], value: &[u8]) -> Upgrade {
+    Upgrade::Rewrite(tag_record(RECORD_VERSION_SCHEMA, value).unwrap())
+}
+
+/// Tracked pools stored the template's raw fee rate until a revision replaced it with a fraction. Raw rates are whole
+/// numbers and fractions are below 1, so only the raw ones are divided by the template's fee unit. The pool's fees were
+/// summed with that same raw rate, as no revision has changed it since, and are scaled with it
+fn convert_tracked_pool_fee_rate(_key: &[u8], value: &[u8]) -> Upgrade {
+    let Some(mut pool) = decode_exact::<TrackedPool>(value) else {
+        return Upgrade::Unreadable;
+    };
+    if pool.fee_rate < 1.0 {
+        return Upgrade::Keep;
+    }
+    pool.fee_rate /= TEMPLATE_FEE_UNIT;
+    pool.base_fees /= TEMPLATE_FEE_UNIT;
+    pool.quote_fees /= TEMPLATE_FEE_UNIT;
+    Upgrade::Rewrite(bincode::serialize(&pool).unwrap())
+}
+
+/// Active positions are updated from the last event applied to them, which the old records do not have. They are
+/// rebuilt from the stored events instead
+fn drop_active_positions(_key: &[u8], _value: &[u8]) -> Upgrade {
+    Upgrade::Delete
+}
+
+/// The moved harvests change the rewards of positions already in the index
+fn repair_position_index(db: &DB) {
+    let check = check_position_index(db, true);
+    info!(
+        "Repaired {} of {} indexed user positions",
+        check.mismatched, check.checked
+    );
+}
+
+#[test]
+fn test_upgrade_from() {
+    use serde::Deserialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Old {
+        a: u64,
+    }
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct New {
+        a: u64,
+        b: u128,
+    }
+    impl From<Old> for New {
+        fn from(old: Old) -> Self {
+            New { a: old.a, b: 0 }
+        }
+    }
+
+    let old = bincode::serialize(&Old { a: 1 }).unwrap();
+    match upgrade_from::<Old, New>(&old) {
+        Upgrade::Rewrite(v) => {
+            assert_eq!(bincode::deserialize::<New>(&v).unwrap(), New { a: 1, b: 0 })
+        }
+        other => panic!("Expected a rewrite, got {:?}", other),
+    }
+    assert_eq!(upgrade_from::<Old, New>(&[1, 2]), Upgrade::Unreadable);
+
+    // A harvest filed as a burn moves, a burn stays
+    let burn = bincode::serialize(&BurnRangedEvent::default()).unwrap();
+    assert_eq!(move_misfiled_harvest(b"", &burn), Upgrade::Keep);
+    let harvest = bincode::serialize(&HarvestEvent::default()).unwrap();
+    match move_misfiled_harvest(b"", &harvest) {
+        Upgrade::Move { family, key, .. } => {
+            assert_eq!(family, HARVEST_PREFIX);
+            assert!(key.starts_with(HARVEST_PREFIX.as_bytes()));
+        }
+        other => panic!("Expected a move, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_record_version() {
+    let record = serialize_record(&(1u64, "a")).unwrap();
+    assert_eq!(split_record(&record).unwrap().0, SCHEMA_VERSION);
+    assert_eq!(
+        deserialize_record::<(u64, String)>(&record).unwrap(),
+        (1, "a".to_string())
+    );
+    assert!(deserialize_record::<u64>(&[0, 0]).is_err());
+
+    let untagged = bincode::serialize(&7u64).unwrap();
+    match add_record_version(b"", &untagged) {
+        Upgrade::Rewrite(v) => {
+            assert_eq!(split_record(&v).unwrap().0, RECORD_VERSION_SCHEMA);
+            assert_eq!(deserialize_record::<u64>(&v).unwrap(), 7);
+        }
+        other => panic!("Expected a rewrite, got {:?}", other),
+    }
+}
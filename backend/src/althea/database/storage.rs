@@ -0,0 +1,271 @@
+// This file defines the storage the database functions are written against, which is RocksDB in production and an in-memory
+// map in tests. Records are grouped into the families listed in column_families.rs and kept in key order within a family.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::ops::Bound;
+use std::sync::{Arc, RwLock};
+
+use rocksdb::{IteratorMode, WriteBatch, DB};
+
+use super::column_families::cf;
+
+/// The family of records which belong to none of the others, holding the latest searched block and syncing flag
+pub const DEFAULT_FAMILY: &str = rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+
+/// A key and value read from storage
+pub type Record = (Box<[u8]>, Box<[u8]>);
+/// Records in key order, or in reverse key order for reverse scans
+pub type Records<'a> = Box<dyn Iterator<Item = Result<Record, StorageError>> + 'a>;
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Storage error: {}", self.0)
+    }
+}
+impl From<rocksdb::Error> for StorageError {
+    fn from(error: rocksdb::Error) -> Self {
+        StorageError(error.to_string())
+    }
+}
+
+/// Which way a scan moves through the keys of a family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Forward,
+    Reverse,
+}
+
+/// Writes applied together, so readers see all of them or none
+#[derive(Debug, Default, Clone)]
+pub struct StorageBatch {
+    ops: Vec<BatchOp>,
+}
+
+#[derive(Debug, Clone)]
+enum BatchOp {
+    Put {
+        family: String,
+        key: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Delete {
+        family: String,
+        key: Vec<u8>,
+    },
+}
+
+impl StorageBatch {
+    pub fn put(&mut self, family: &str, key: &[u8], value: &[u8]) {
+        self.ops.push(BatchOp::Put {
+            family: family.to_string(),
+            key: key.to_vec(),
+            value: value.to_vec(),
+        });
+    }
+    pub fn delete(&mut self, family: &str, key: &[u8]) {
+        self.ops.push(BatchOp::Delete {
+            family: family.to_string(),
+            key: key.to_vec(),
+        });
+    }
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// Key-value storage split into named families
+pub trait Storage: Send + Sync {
+    fn get(&self, family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+    fn put(&self, family: &str, key: &[u8], value: &[u8]) -> Result<(), StorageError>;
+    fn delete(&self, family: &str, key: &[u8]) -> Result<(), StorageError>;
+    /// The records whose keys start with `prefix`, an empty prefix scans the whole family
+    fn prefix_scan<'a>(&'a self, family: &str, prefix: &[u8]) -> Records<'a>;
+    /// The records from `start` onwards in `direction`, beginning with `start` itself if it exists. A reverse scan begins
+    /// with the last key at or before `start`
+    fn scan_from<'a>(&'a self, family: &str, start: &[u8], direction: Direction) -> Records<'a>;
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError>;
+}
+
+impl Storage for DB {
+    fn get(&self, family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self.get_cf(cf(self, family), key)?)
+    }
+    fn put(&self, family: &str, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        Ok(self.put_cf(cf(self, family), key, value)?)
+    }
+    fn delete(&self, family: &str, key: &[u8]) -> Result<(), StorageError> {
+        Ok(self.delete_cf(cf(self, family), key)?)
+    }
+    fn prefix_scan<'a>(&'a self, family: &str, prefix: &[u8]) -> Records<'a> {
+        let prefix = prefix.to_vec();
+        Box::new(
+            self.prefix_iterator_cf(cf(self, family), &prefix)
+                .take_while(move |r| r.as_ref().map_or(true, |(k, _)| k.starts_with(&prefix)))
+                .map(|r| r.map_err(StorageError::from)),
+        )
+    }
+    fn scan_from<'a>(&'a self, family: &str, start: &[u8], direction: Direction) -> Records<'a> {
+        let direction = match direction {
+            Direction::Forward => rocksdb::Direction::Forward,
+            Direction::Reverse => rocksdb::Direction::Reverse,
+        };
+        Box::new(
+            self.iterator_cf(cf(self, family), IteratorMode::From(start, direction))
+                .map(|r| r.map_err(StorageError::from)),
+        )
+    }
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        let mut write = WriteBatch::default();
+        for op in batch.ops {
+            match op {
+                BatchOp::Put { family, key, value } => write.put_cf(cf(self, &family), key, value),
+                BatchOp::Delete { family, key } => write.delete_cf(cf(self, &family), key),
+            }
+        }
+        Ok(DB::write(self, write)?)
+    }
+}
+
+impl<S: Storage + ?Sized> Storage for Arc<S> {
+    fn get(&self, family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        (**self).get(family, key)
+    }
+    fn put(&self, family: &str, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        (**self).put(family, key, value)
+    }
+    fn delete(&self, family: &str, key: &[u8]) -> Result<(), StorageError> {
+        (**self).delete(family, key)
+    }
+    fn prefix_scan<'a>(&'a self, family: &str, prefix: &[u8]) -> Records<'a> {
+        (**self).prefix_scan(family, prefix)
+    }
+    fn scan_from<'a>(&'a self, family: &str, start: &[u8], direction: Direction) -> Records<'a> {
+        (**self).scan_from(family, start, direction)
+    }
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        (**self).write(batch)
+    }
+}
+
+type Family = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Storage held in memory, for tests which should not touch the disk. Scans copy the matching records so the lock is
+/// not held while they are read
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    families: RwLock<HashMap<String, Family>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn collect<'r>(records: impl Iterator<Item = (&'r Vec<u8>, &'r Vec<u8>)>) -> Records<'static> {
+        let records: Vec<_> = records
+            .map(|(k, v)| Ok((k.clone().into_boxed_slice(), v.clone().into_boxed_slice())))
+            .collect();
+        Box::new(records.into_iter())
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let families = self.families.read().unwrap();
+        Ok(families.get(family).and_then(|f| f.get(key)).cloned())
+    }
+    fn put(&self, family: &str, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let mut families = self.families.write().unwrap();
+        families
+            .entry(family.to_string())
+            .or_default()
+            .insert(key.to_vec(), value.to_vec());
+        Ok(())
+    }
+    fn delete(&self, family: &str, key: &[u8]) -> Result<(), StorageError> {
+        let mut families = self.families.write().unwrap();
+        if let Some(f) = families.get_mut(family) {
+            f.remove(key);
+        }
+        Ok(())
+    }
+    fn prefix_scan<'a>(&'a self, family: &str, prefix: &[u8]) -> Records<'a> {
+        let families = self.families.read().unwrap();
+        match families.get(family) {
+            Some(f) => MemoryStorage::collect(
+                f.range::<[u8], _>((Bound::Included(prefix), Bound::Unbounded))
+                    .take_while(|(k, _)| k.starts_with(prefix)),
+            ),
+            None => Box::new(std::iter::empty()),
+        }
+    }
+    fn scan_from<'a>(&'a self, family: &str, start: &[u8], direction: Direction) -> Records<'a> {
+        let families = self.families.read().unwrap();
+        match (families.get(family), direction) {
+            (Some(f), Direction::Forward) => MemoryStorage::collect(
+                f.range::<[u8], _>((Bound::Included(start), Bound::Unbounded)),
+            ),
+            (Some(f), Direction::Reverse) => MemoryStorage::collect(
+                f.range::<[u8], _>((Bound::Unbounded, Bound::Included(start)))
+                    .rev(),
+            ),
+            (None, _) => Box::new(std::iter::empty()),
+        }
+    }
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        let mut families = self.families.write().unwrap();
+        for op in batch.ops {
+            match op {
+                BatchOp::Put { family, key, value } => {
+                    families.entry(family).or_default().insert(key, value);
+                }
+                BatchOp::Delete { family, key } => {
+                    if let Some(f) = families.get_mut(&family) {
+                        f.remove(&key);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_memory_storage() {
+    let db = MemoryStorage::new();
+    for k in ["a_1", "a_2", "a_3", "b_1"] {
+        db.put("family", k.as_bytes(), k.as_bytes()).unwrap();
+    }
+    db.put("other", b"a_4", b"a_4").unwrap();
+    let keys = |records: Records| -> Vec<String> {
+        records
+            .flatten()
+            .map(|(k, _)| String::from_utf8(k.to_vec()).unwrap())
+            .collect()
+    };
+
+    assert_eq!(db.get("family", b"a_2").unwrap(), Some(b"a_2".to_vec()));
+    assert_eq!(db.get("other", b"a_2").unwrap(), None);
+    assert_eq!(keys(db.prefix_scan("family", b"a_")), ["a_1", "a_2", "a_3"]);
+    assert_eq!(keys(db.prefix_scan("family", b"")).len(), 4);
+    assert_eq!(
+        keys(db.scan_from("family", b"a_2", Direction::Forward)),
+        ["a_2", "a_3", "b_1"]
+    );
+    assert_eq!(
+        keys(db.scan_from("family", b"a_25", Direction::Reverse)),
+        ["a_2", "a_1"]
+    );
+
+    let mut batch = StorageBatch::default();
+    batch.delete("family", b"a_1");
+    batch.put("family", b"a_5", b"a_5");
+    db.write(batch).unwrap();
+    assert_eq!(keys(db.prefix_scan("family", b"a_")), ["a_2", "a_3", "a_5"]);
+}
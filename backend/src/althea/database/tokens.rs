@@ -0,0 +1,53 @@
+use clarity::Address;
+use log::debug;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
+
+/// The ERC20 metadata of a token appearing in a pool
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    pub address: Address,
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/// The metadata used for the native token, which appears in pools as the zero address
+pub fn native_token_metadata() -> TokenMetadata {
+    TokenMetadata {
+        address: Address::default(),
+        name: "Althea".to_string(),
+        symbol: "ALTHEA".to_string(),
+        decimals: 18,
+    }
+}
+
+pub const TOKEN_METADATA_PREFIX: &str = "token-metadata_";
+fn token_metadata_key(token: Address) -> String {
+    format!("{}{}", TOKEN_METADATA_PREFIX, token)
+}
+
+pub fn save_token_metadata(db: &impl Storage, metadata: TokenMetadata) {
+    let k = token_metadata_key(metadata.address);
+    debug!("Saving token metadata to key {}", k);
+    db.put(
+        TOKEN_METADATA_PREFIX,
+        k.as_bytes(),
+        &serialize_record(&metadata).unwrap(),
+    )
+    .unwrap();
+}
+
+/// Gets the stored metadata for `token`, the native token (zero address) always has metadata
+pub fn get_token_metadata(db: &impl Storage, token: Address) -> Option<TokenMetadata> {
+    if token == Address::default() {
+        return Some(native_token_metadata());
+    }
+    let v = db
+        .get(TOKEN_METADATA_PREFIX, token_metadata_key(token).as_bytes())
+        .unwrap()?;
+    Some(deserialize_record(&v).unwrap())
+}
@@ -0,0 +1,334 @@
+// This file aggregates swaps into open/high/low/close/volume candles per pool, maintained as each swap is tracked
+
+use clarity::Address;
+use clarity::Uint256;
+use log::debug;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::{Direction, Storage, StorageBatch};
+
+use super::swap_fees;
+use super::updates::PoolUpdateEvent;
+use super::TrackedPool;
+
+/// The candle widths (in seconds) which are maintained for every pool: 1m, 15m, 1h and 1d
+pub const CANDLE_PERIODS: [u64; 4] = [60, 900, 3600, 86400];
+
+/// The price and volume of a pool over a single `period` long window starting at `time`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub period: u64,
+    pub time: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub base_volume: Uint256,
+    pub quote_volume: Uint256,
+    pub base_fees: f64,
+    pub quote_fees: f64,
+    // TVL as of the last swap in the candle
+    pub base_tvl: Uint256,
+    pub quote_tvl: Uint256,
+    pub swaps: u64,
+}
+
+impl Candle {
+    /// Creates an empty candle covering `time` which carries over the price `price`
+    pub fn new(period: u64, time: u64, price: f64) -> Self {
+        Candle {
+            period,
+            time: candle_start(period, time),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            ..Default::default()
+        }
+    }
+}
+
+pub const CANDLE_PREFIX: &str = "candle_";
+pub fn candle_pool_prefix(base: Address, quote: Address, pool_idx: Uint256, period: u64) -> String {
+    format!(
+        "{}{}_{}_{}_{}_",
+        CANDLE_PREFIX, base, quote, pool_idx, period
+    )
+}
+// Start times are zero padded so that the keys sort by time
+fn candle_key(base: Address, quote: Address, pool_idx: Uint256, period: u64, start: u64) -> String {
+    format!(
+        "{}{:020}",
+        candle_pool_prefix(base, quote, pool_idx, period),
+        start
+    )
+}
+
+fn candle_start(period: u64, time: u64) -> u64 {
+    time - (time % period)
+}
+
+fn save_candle(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    candle: &Candle,
+) {
+    let k = candle_key(base, quote, pool_idx, candle.period, candle.time);
+    debug!("Saving candle to key {}", k);
+    db.put(
+        CANDLE_PREFIX,
+        k.as_bytes(),
+        &serialize_record(candle).unwrap(),
+    )
+    .unwrap();
+}
+
+/// Gets the candle of width `period` which covers `time`, if the pool had any swaps in that window
+pub fn get_candle(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    period: u64,
+    time: u64,
+) -> Option<Candle> {
+    let k = candle_key(base, quote, pool_idx, period, candle_start(period, time));
+    let v = db.get(CANDLE_PREFIX, k.as_bytes()).unwrap()?;
+    Some(deserialize_record(&v).unwrap())
+}
+
+/// Gets the latest candle of width `period` which started before `time`
+pub fn get_candle_before(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    period: u64,
+    time: u64,
+) -> Option<Candle> {
+    let prefix = candle_pool_prefix(base, quote, pool_idx, period);
+    let start = candle_start(period, time);
+    let k = candle_key(base, quote, pool_idx, period, start);
+    let iter = db.scan_from(CANDLE_PREFIX, k.as_bytes(), Direction::Reverse);
+    for entry in iter {
+        match entry {
+            Ok((k, v)) => {
+                if !k.starts_with(prefix.as_bytes()) {
+                    return None;
+                }
+                let candle: Candle = deserialize_record(&v).unwrap();
+                if candle.time < start {
+                    return Some(candle);
+                }
+            }
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Gets the stored candles of width `period` which start between `start` and `end` (inclusive), oldest first.
+/// Windows without any swaps have no stored candle.
+pub fn get_candles(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    period: u64,
+    start: u64,
+    end: u64,
+) -> Vec<Candle> {
+    let prefix = candle_pool_prefix(base, quote, pool_idx, period);
+    let k = candle_key(base, quote, pool_idx, period, candle_start(period, start));
+    let iter = db.scan_from(CANDLE_PREFIX, k.as_bytes(), Direction::Forward);
+    let mut candles = vec![];
+    for entry in iter {
+        match entry {
+            Ok((k, v)) => {
+                if !k.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                let candle: Candle = deserialize_record(&v).unwrap();
+                if candle.time > end {
+                    break;
+                }
+                candles.push(candle);
+            }
+            Err(_) => break,
+        }
+    }
+    candles
+}
+
+/// Adds a swap which happened at `time` to every candle period of the pool. `open_price` is the pool's swap price
+/// before the swap, which opens any new candle, while `pool` is the pool state after the swap.
+pub fn update_candles(
+    db: &impl Storage,
+    pool: &TrackedPool,
+    swap: &PoolUpdateEvent,
+    open_price: f64,
+    time: u64,
+) {
+    for period in CANDLE_PERIODS {
+        let candle = get_candle(db, pool.base, pool.quote, pool.pool_idx, period, time)
+            .unwrap_or_else(|| Candle::new(period, time, open_price));
+        let candle = apply_swap(candle, pool, swap);
+        save_candle(db, pool.base, pool.quote, pool.pool_idx, &candle);
+    }
+}
+
+fn apply_swap(mut candle: Candle, pool: &TrackedPool, swap: &PoolUpdateEvent) -> Candle {
+    let price = pool.last_price_swap;
+    candle.high = candle.high.max(price);
+    candle.low = candle.low.min(price);
+    candle.close = price;
+    candle.base_volume += swap.base_flow.unsigned_abs().into();
+    candle.quote_volume += swap.quote_flow.unsigned_abs().into();
+    let (base_fees, quote_fees) = swap_fees(swap, pool.fee_rate);
+    candle.base_fees += base_fees;
+    candle.quote_fees += quote_fees;
+    candle.base_tvl = pool.base_tvl;
+    candle.quote_tvl = pool.quote_tvl;
+    candle.swaps += 1;
+    candle
+}
+
+/// The length of the rolling windows reported in pool stats, in seconds
+pub const DAY_WINDOW: u64 = 86400;
+pub const WEEK_WINDOW: u64 = 7 * 86400;
+
+/// Swap activity of a pool over a rolling window
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WindowStats {
+    pub base_volume: Uint256,
+    pub quote_volume: Uint256,
+    pub base_fees: f64,
+    pub quote_fees: f64,
+    // The relative change from the price at the start of the window to `price`, e.g. 0.05 for a 5% rise
+    pub price_change: f64,
+}
+
+/// Gets the volume, fees and price change of the pool over the `window` seconds before `now`, to the nearest hour.
+/// `price` is the pool's price as of `now`.
+pub fn get_window_stats(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    now: u64,
+    window: u64,
+    price: f64,
+) -> WindowStats {
+    const PERIOD: u64 = 3600;
+    let start = now.saturating_sub(window);
+    let candles = get_candles(db, base, quote, pool_idx, PERIOD, start, now);
+    let prev = get_candle_before(db, base, quote, pool_idx, PERIOD, start);
+    window_stats(&candles, prev.map(|c| c.close), price)
+}
+
+// Sums the candles in a window, measuring the price change from `start_price` (the close before the window)
+// or the open of the first candle if the pool had no earlier swaps
+fn window_stats(candles: &[Candle], start_price: Option<f64>, price: f64) -> WindowStats {
+    let mut stats = WindowStats::default();
+    for candle in candles {
+        stats.base_volume += candle.base_volume;
+        stats.quote_volume += candle.quote_volume;
+        stats.base_fees += candle.base_fees;
+        stats.quote_fees += candle.quote_fees;
+    }
+    let start_price = start_price.or(candles.first().map(|c| c.open));
+    if let Some(start_price) = start_price {
+        if start_price > 0.0 {
+            stats.price_change = (price - start_price) / start_price;
+        }
+    }
+    stats
+}
+
+/// Removes every candle of the pool, used when the pool is reindexed from scratch
+pub fn delete_pool_candles(db: &impl Storage, base: Address, quote: Address, pool_idx: Uint256) {
+    let mut batch = StorageBatch::default();
+    for period in CANDLE_PERIODS {
+        let prefix = candle_pool_prefix(base, quote, pool_idx, period);
+        for (k, _) in db.prefix_scan(CANDLE_PREFIX, prefix.as_bytes()).flatten() {
+            batch.delete(CANDLE_PREFIX, &k);
+        }
+    }
+    db.write(batch).unwrap();
+}
+
+#[test]
+fn test_apply_swap() {
+    let mut pool = TrackedPool {
+        last_price_swap: 2.5,
+        fee_rate: 0.01,
+        base_tvl: 1000u32.into(),
+        quote_tvl: 400u32.into(),
+        ..Default::default()
+    };
+    let swap = PoolUpdateEvent {
+        base_flow: 100,
+        quote_flow: -40,
+        is_swap: true,
+        ..Default::default()
+    };
+
+    let candle = Candle::new(60, 125, 2.0);
+    assert_eq!(candle.time, 120);
+    let candle = apply_swap(candle, &pool, &swap);
+    assert_eq!(candle.open, 2.0);
+    assert_eq!(candle.high, 2.5);
+    assert_eq!(candle.low, 2.0);
+    assert_eq!(candle.close, 2.5);
+
+    pool.last_price_swap = 1.5;
+    let candle = apply_swap(candle, &pool, &swap);
+    assert_eq!(candle.open, 2.0);
+    assert_eq!(candle.high, 2.5);
+    assert_eq!(candle.low, 1.5);
+    assert_eq!(candle.close, 1.5);
+    assert_eq!(candle.base_volume, 200u32.into());
+    assert_eq!(candle.quote_volume, 80u32.into());
+    assert_eq!(candle.swaps, 2);
+    assert_eq!(candle.base_fees, 2.0);
+    assert_eq!(candle.quote_fees, 0.0);
+}
+
+#[test]
+fn test_window_stats() {
+    let candles = vec![
+        Candle {
+            open: 2.0,
+            close: 2.2,
+            base_volume: 100u32.into(),
+            quote_volume: 50u32.into(),
+            base_fees: 1.0,
+            ..Candle::new(3600, 3600, 2.0)
+        },
+        Candle {
+            open: 2.2,
+            close: 2.4,
+            base_volume: 300u32.into(),
+            quote_volume: 130u32.into(),
+            quote_fees: 1.5,
+            ..Candle::new(3600, 7200, 2.2)
+        },
+    ];
+    let stats = window_stats(&candles, Some(1.6), 2.4);
+    assert_eq!(stats.base_volume, 400u32.into());
+    assert_eq!(stats.quote_volume, 180u32.into());
+    assert_eq!(stats.base_fees, 1.0);
+    assert_eq!(stats.quote_fees, 1.5);
+    assert!((stats.price_change - 0.5).abs() < 1e-9);
+
+    // Without an earlier close the first open is used
+    let stats = window_stats(&candles, None, 2.4);
+    assert!((stats.price_change - 0.2).abs() < 1e-9);
+
+    let stats = window_stats(&[], None, 2.4);
+    assert_eq!(stats.price_change, 0.0);
+}
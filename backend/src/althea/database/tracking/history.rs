@@ -0,0 +1,161 @@
+// This file stores periodic snapshots of TrackedPool state so that pool stats can be queried as of a past time
+
+use clarity::Address;
+use clarity::Uint256;
+use log::debug;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::{Direction, Storage, StorageBatch};
+
+use super::TrackedPool;
+
+/// The width of a snapshot bucket in seconds, only the latest state within each bucket is kept
+pub const SNAPSHOT_INTERVAL: u64 = 3600;
+
+/// The state of a TrackedPool as of `time`, without the liquidity bumps
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PoolSnapshot {
+    pub time: u64,
+    pub block: Uint256,
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+    pub base_tvl: Uint256,
+    pub quote_tvl: Uint256,
+    pub base_volume: Uint256,
+    pub quote_volume: Uint256,
+    pub base_fees: f64,
+    pub quote_fees: f64,
+    pub last_price_swap: f64,
+    pub last_price_liq: f64,
+    pub last_price_indic: f64,
+    pub ambient_liq: Uint256,
+    pub conc_liq: Uint256,
+    pub fee_rate: f64,
+}
+
+impl PoolSnapshot {
+    pub fn new(pool: &TrackedPool, block: Uint256, time: u64) -> Self {
+        PoolSnapshot {
+            time,
+            block,
+            base: pool.base,
+            quote: pool.quote,
+            pool_idx: pool.pool_idx,
+            base_tvl: pool.base_tvl,
+            quote_tvl: pool.quote_tvl,
+            base_volume: pool.base_volume,
+            quote_volume: pool.quote_volume,
+            base_fees: pool.base_fees,
+            quote_fees: pool.quote_fees,
+            last_price_swap: pool.last_price_swap,
+            last_price_liq: pool.last_price_liq,
+            last_price_indic: pool.last_price_indic,
+            ambient_liq: pool.ambient_liq,
+            conc_liq: pool.conc_liq,
+            fee_rate: pool.fee_rate,
+        }
+    }
+}
+
+pub const POOL_SNAPSHOT_PREFIX: &str = "pool-snapshot_";
+pub fn pool_snapshot_pool_prefix(base: Address, quote: Address, pool_idx: Uint256) -> String {
+    format!("{}{}_{}_{}_", POOL_SNAPSHOT_PREFIX, base, quote, pool_idx)
+}
+// Buckets are zero padded so that the keys sort by time
+fn pool_snapshot_key(base: Address, quote: Address, pool_idx: Uint256, bucket: u64) -> String {
+    format!(
+        "{}{:020}",
+        pool_snapshot_pool_prefix(base, quote, pool_idx),
+        bucket
+    )
+}
+
+fn snapshot_bucket(time: u64) -> u64 {
+    time - (time % SNAPSHOT_INTERVAL)
+}
+
+/// Stores the state of `pool` as of `block` (which happened at `time`), replacing any earlier snapshot in the same bucket
+pub fn save_pool_snapshot(db: &impl Storage, pool: &TrackedPool, block: Uint256, time: u64) {
+    let k = pool_snapshot_key(pool.base, pool.quote, pool.pool_idx, snapshot_bucket(time));
+    debug!("Saving pool snapshot to key {}", k);
+    let v = serialize_record(&PoolSnapshot::new(pool, block, time)).unwrap();
+    db.put(POOL_SNAPSHOT_PREFIX, k.as_bytes(), &v).unwrap();
+}
+
+/// Gets the most recent snapshot of the pool taken at or before `time`, returns none if the pool had no state by then
+pub fn get_pool_snapshot_at(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    time: u64,
+) -> Option<PoolSnapshot> {
+    let prefix = pool_snapshot_pool_prefix(base, quote, pool_idx);
+    let k = pool_snapshot_key(base, quote, pool_idx, snapshot_bucket(time));
+    let iter = db.scan_from(POOL_SNAPSHOT_PREFIX, k.as_bytes(), Direction::Reverse);
+    for entry in iter {
+        match entry {
+            Ok((k, v)) => {
+                if !k.starts_with(prefix.as_bytes()) {
+                    return None;
+                }
+                let snapshot: PoolSnapshot = deserialize_record(&v).unwrap();
+                // The bucket may contain a snapshot later than the requested time
+                if snapshot.time <= time {
+                    return Some(snapshot);
+                }
+            }
+            Err(_) => return None,
+        }
+    }
+    None
+}
+
+/// Gets all snapshots of the pool between `start` and `end` (inclusive), oldest first
+pub fn get_pool_snapshots(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    start: u64,
+    end: u64,
+) -> Vec<PoolSnapshot> {
+    let prefix = pool_snapshot_pool_prefix(base, quote, pool_idx);
+    let k = pool_snapshot_key(base, quote, pool_idx, snapshot_bucket(start));
+    let iter = db.scan_from(POOL_SNAPSHOT_PREFIX, k.as_bytes(), Direction::Forward);
+    let mut snapshots = vec![];
+    for entry in iter {
+        match entry {
+            Ok((k, v)) => {
+                if !k.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                let snapshot: PoolSnapshot = deserialize_record(&v).unwrap();
+                if snapshot.time > end {
+                    break;
+                }
+                if snapshot.time >= start {
+                    snapshots.push(snapshot);
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    snapshots
+}
+
+/// Removes every snapshot of the pool, used when the pool is reindexed from scratch
+pub fn delete_pool_snapshots(db: &impl Storage, base: Address, quote: Address, pool_idx: Uint256) {
+    let prefix = pool_snapshot_pool_prefix(base, quote, pool_idx);
+    let mut batch = StorageBatch::default();
+    for (k, _) in db
+        .prefix_scan(POOL_SNAPSHOT_PREFIX, prefix.as_bytes())
+        .flatten()
+    {
+        batch.delete(POOL_SNAPSHOT_PREFIX, &k);
+    }
+    db.write(batch).unwrap();
+}
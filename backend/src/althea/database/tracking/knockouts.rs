@@ -0,0 +1,129 @@
+// This file records the knockout pivots crossed by swaps as pools are tracked, so a knockout position's status is a single
+// lookup rather than a search through the pool's price history
+
+use clarity::{Address, Uint256};
+use log::debug;
+use num_traits::ToPrimitive;
+
+use crate::althea::database::storage::{Direction, Storage, StorageBatch};
+
+/// A swap which crossed the pivot of a pool's bid (price falling) or ask (price rising) knockout liquidity at a tick
+pub const KNOCKOUT_CROSS_PREFIX: &str = "knockout-cross_";
+fn knockout_cross_pool_prefix(base: Address, quote: Address, pool_idx: Uint256) -> String {
+    format!("{}{}_{}_{}_", KNOCKOUT_CROSS_PREFIX, base, quote, pool_idx)
+}
+fn knockout_cross_pivot_prefix(
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    tick: i32,
+    is_bid: bool,
+) -> String {
+    let side = if is_bid { "bid" } else { "ask" };
+    format!(
+        "{}{}_{}_",
+        knockout_cross_pool_prefix(base, quote, pool_idx),
+        tick,
+        side
+    )
+}
+// Blocks are zero padded so that the crosses of a pivot sort by block
+fn knockout_cross_key(
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    tick: i32,
+    is_bid: bool,
+    block: Uint256,
+) -> String {
+    format!(
+        "{}{:020}",
+        knockout_cross_pivot_prefix(base, quote, pool_idx, tick, is_bid),
+        block.to_u64().unwrap_or(u64::MAX)
+    )
+}
+
+/// Records that a swap in `block` crossed the knockout pivot at `tick`
+pub fn save_knockout_cross(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    tick: i32,
+    is_bid: bool,
+    block: Uint256,
+) {
+    let k = knockout_cross_key(base, quote, pool_idx, tick, is_bid, block);
+    debug!("Saving knockout cross to key {}", k);
+    db.put(KNOCKOUT_CROSS_PREFIX, k.as_bytes(), &[]).unwrap();
+}
+
+/// True if the knockout pivot at `tick` has been crossed in `block` or later
+pub fn knocked_out_since(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    tick: i32,
+    is_bid: bool,
+    block: Uint256,
+) -> bool {
+    let prefix = knockout_cross_pivot_prefix(base, quote, pool_idx, tick, is_bid);
+    let start = knockout_cross_key(base, quote, pool_idx, tick, is_bid, block);
+    let next = db
+        .scan_from(KNOCKOUT_CROSS_PREFIX, start.as_bytes(), Direction::Forward)
+        .next();
+    matches!(next, Some(Ok((k, _))) if k.starts_with(prefix.as_bytes()))
+}
+
+/// Deletes every knockout cross recorded for a pool
+pub fn delete_pool_knockout_crosses(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) {
+    let prefix = knockout_cross_pool_prefix(base, quote, pool_idx);
+    let mut batch = StorageBatch::default();
+    for (k, _) in db
+        .prefix_scan(KNOCKOUT_CROSS_PREFIX, prefix.as_bytes())
+        .flatten()
+    {
+        batch.delete(KNOCKOUT_CROSS_PREFIX, &k);
+    }
+    db.write(batch).unwrap();
+}
+
+#[test]
+fn test_knocked_out_since() {
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    let base = Address::from_slice(&[1; 20]).unwrap();
+    let quote = Address::from_slice(&[2; 20]).unwrap();
+    let pool_idx = Uint256::from(36000u64);
+    save_knockout_cross(&db, base, quote, pool_idx, -16, true, 100u32.into());
+    let crossed = |tick: i32, is_bid: bool, block: u32| {
+        knocked_out_since(&db, base, quote, pool_idx, tick, is_bid, block.into())
+    };
+
+    assert!(crossed(-16, true, 50));
+    assert!(crossed(-16, true, 100));
+    // Positions minted after the cross, at another pivot or on the other side were not knocked out by it
+    assert!(!crossed(-16, true, 101));
+    assert!(!crossed(-1, true, 50));
+    assert!(!crossed(-16, false, 50));
+    let other_pool = Uint256::from(36001u64);
+    assert!(!knocked_out_since(
+        &db,
+        base,
+        quote,
+        other_pool,
+        -16,
+        true,
+        50u32.into()
+    ));
+
+    delete_pool_knockout_crosses(&db, base, quote, pool_idx);
+    assert!(!crossed(-16, true, 50));
+}
@@ -1,15 +1,24 @@
 // This file deals with inferring pool state from observed events by maintaining a cache of pool data and updating it as new events are observed.
 
+pub mod candles;
+pub mod history;
+pub mod knockouts;
 pub mod updates;
 
 use std::cmp::Ordering;
 use std::cmp::Ordering::Equal;
 
+use crate::althea::database::schema::{deserialize_record, serialize_record};
+use crate::althea::database::storage::Storage;
+use candles::delete_pool_candles;
+use candles::update_candles;
 use clarity::Address;
 use clarity::Int256;
 use clarity::Uint256;
+use history::delete_pool_snapshots;
+use history::save_pool_snapshot;
+use knockouts::{delete_pool_knockout_crosses, save_knockout_cross};
 use log::debug;
-use log::info;
 use log::warn;
 use num_traits::ToPrimitive;
 use num_traits::Zero;
@@ -17,8 +26,10 @@ use serde::Deserialize;
 use serde::Serialize;
 use updates::PoolUpdateEvent;
 
+use crate::althea::database::blocks::get_block_time;
 use crate::althea::database::pools::get_pool_template;
 
+use super::pools::get_init_pool;
 use super::pools::get_init_pools;
 use super::InitPoolEvent;
 
@@ -39,7 +50,7 @@ fn dirty_pool_key(base: Address, quote: Address, pool_idx: Uint256) -> String {
 
 /// Sets the dirty flag and last event block for a pool
 pub fn set_dirty_pool(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
@@ -55,30 +66,37 @@ pub fn set_dirty_pool(
         quote,
         pool_idx,
     };
-    db.put(k.as_bytes(), bincode::serialize(&v).unwrap())
-        .unwrap();
+    db.put(
+        DIRTY_POOL_PREFIX,
+        k.as_bytes(),
+        &serialize_record(&v).unwrap(),
+    )
+    .unwrap();
 }
 
 /// Gets the dirty flag and last event block for a pool
 pub fn get_dirty_pool(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<(bool, Uint256)> {
     let v = db
-        .get(dirty_pool_key(base, quote, pool_idx).as_bytes())
+        .get(
+            DIRTY_POOL_PREFIX,
+            dirty_pool_key(base, quote, pool_idx).as_bytes(),
+        )
         .unwrap();
     #[allow(clippy::question_mark)]
     if v.is_none() {
         return None;
     }
-    let value: DirtyPoolTracker = bincode::deserialize(&v.unwrap()).unwrap();
+    let value: DirtyPoolTracker = deserialize_record(&v.unwrap()).unwrap();
     Some((value.dirty, value.last_block))
 }
 
 pub fn mark_pool_fresh(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
@@ -87,7 +105,7 @@ pub fn mark_pool_fresh(
     set_dirty_pool(db, base, quote, pool_idx, false, block);
 }
 
-pub fn mark_pool_dirty(db: &rocksdb::DB, base: Address, quote: Address, pool_idx: Uint256) {
+pub fn mark_pool_dirty(db: &impl Storage, base: Address, quote: Address, pool_idx: Uint256) {
     let block = {
         let dirty_pool = get_dirty_pool(db, base, quote, pool_idx);
         dirty_pool.unwrap_or((true, Uint256::default())).1
@@ -95,9 +113,9 @@ pub fn mark_pool_dirty(db: &rocksdb::DB, base: Address, quote: Address, pool_idx
     set_dirty_pool(db, base, quote, pool_idx, true, block);
 }
 
-pub fn get_all_dirty_pools(db: &rocksdb::DB) -> Vec<DirtyPoolTracker> {
+pub fn get_all_dirty_pools(db: &impl Storage) -> Vec<DirtyPoolTracker> {
     let prefix = DIRTY_POOL_PREFIX.as_bytes();
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(DIRTY_POOL_PREFIX, prefix);
     let mut ret = vec![];
     for value in iter {
         match value {
@@ -105,7 +123,7 @@ pub fn get_all_dirty_pools(db: &rocksdb::DB) -> Vec<DirtyPoolTracker> {
                 if !k.starts_with(prefix) {
                     break;
                 }
-                let value: DirtyPoolTracker = bincode::deserialize(&v).unwrap();
+                let value: DirtyPoolTracker = deserialize_record(&v).unwrap();
                 ret.push(value);
             }
             Err(_) => continue,
@@ -226,45 +244,49 @@ impl PartialOrd for LiquidityBump {
     }
 }
 
+/// Template and revision fee rates are in hundredths of a basis point, dividing them by this gives the fraction stored
+/// in TrackedPool.fee_rate
+pub const TEMPLATE_FEE_UNIT: f64 = 1_000_000.0;
+
 pub const TRACKED_POOL_PREFIX: &str = "tracked-pool_";
 fn tracked_pool_key(base: Address, quote: Address, pool_idx: Uint256) -> String {
     format!("{}{}_{}_{}", TRACKED_POOL_PREFIX, base, quote, pool_idx)
 }
 
 /// Stores the cached pool state for a tracked pool
-pub fn set_tracked_pool(db: &rocksdb::DB, pool: TrackedPool) {
+pub fn set_tracked_pool(db: &impl Storage, pool: TrackedPool) {
     let k = tracked_pool_key(pool.base, pool.quote, pool.pool_idx);
     debug!("Setting tracked pool at key {}", k);
-    db.put(k.as_bytes(), bincode::serialize(&pool).unwrap())
-        .unwrap();
+    db.put(
+        TRACKED_POOL_PREFIX,
+        k.as_bytes(),
+        &serialize_record(&pool).unwrap(),
+    )
+    .unwrap();
 }
 
 /// Gets the latest known inferred pool state for the given pool
 pub fn get_tracked_pool(
-    db: &rocksdb::DB,
+    db: &impl Storage,
     base: Address,
     quote: Address,
     pool_idx: Uint256,
 ) -> Option<TrackedPool> {
     let v = db
-        .get(tracked_pool_key(base, quote, pool_idx).as_bytes())
+        .get(
+            TRACKED_POOL_PREFIX,
+            tracked_pool_key(base, quote, pool_idx).as_bytes(),
+        )
         .unwrap()?;
-    Some(bincode::deserialize(&v).unwrap())
+    Some(deserialize_record(&v).unwrap())
 }
 
-pub fn reset_all_pool_indexes(db: &rocksdb::DB) {
+pub fn reset_all_pool_indexes(db: &impl Storage) {
     let dirty = get_all_dirty_pools(db);
-    let pools_iter = dirty.iter().map(|p| (p.base, p.quote, p.pool_idx));
 
     // First, delete the dirty and tracked pool objects in the database
-    for (base, quote, pool_idx) in pools_iter {
-        let dpk = dirty_pool_key(base, quote, pool_idx);
-        let tpk = tracked_pool_key(base, quote, pool_idx);
-
-        db.delete(dpk.as_bytes())
-            .unwrap_or_else(|e| panic!("Unable to delete dirty pool at key {}: {e}", dpk));
-        db.delete(tpk.as_bytes())
-            .unwrap_or_else(|e| panic!("Unable to delete tracked pool at key {}: {e}", tpk));
+    for pool in dirty {
+        delete_pool_index(db, pool.base, pool.quote, pool.pool_idx);
     }
 
     // Now recreate the dirty pool objects from the InitPoolEvents already stored - this should trigger the pools to be tracked again
@@ -280,19 +302,75 @@ pub fn reset_all_pool_indexes(db: &rocksdb::DB) {
     }
 }
 
-pub fn update_pool(db: &rocksdb::DB, update: PoolUpdateEvent) {
+/// Deletes everything tracked for a single pool: its dirty flag, tracked state, snapshots, candles and knockout crosses.
+/// The stored events are kept
+pub fn delete_pool_index(db: &impl Storage, base: Address, quote: Address, pool_idx: Uint256) {
+    let dpk = dirty_pool_key(base, quote, pool_idx);
+    let tpk = tracked_pool_key(base, quote, pool_idx);
+
+    db.delete(DIRTY_POOL_PREFIX, dpk.as_bytes())
+        .unwrap_or_else(|e| panic!("Unable to delete dirty pool at key {}: {e}", dpk));
+    db.delete(TRACKED_POOL_PREFIX, tpk.as_bytes())
+        .unwrap_or_else(|e| panic!("Unable to delete tracked pool at key {}: {e}", tpk));
+    delete_pool_snapshots(db, base, quote, pool_idx);
+    delete_pool_candles(db, base, quote, pool_idx);
+    delete_pool_knockout_crosses(db, base, quote, pool_idx);
+}
+
+/// Deletes a single pool's tracked state and marks it dirty from the start, so it is rebuilt from its InitPool and later
+/// events the next time pools are tracked. Returns false and leaves the pool alone when there is no InitPool to rebuild from
+pub fn reset_pool_index(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+) -> bool {
+    if get_init_pool(db, base, quote, pool_idx).is_none() {
+        return false;
+    }
+    delete_pool_index(db, base, quote, pool_idx);
+    set_dirty_pool(db, base, quote, pool_idx, true, Uint256::default());
+    true
+}
+
+pub fn update_pool(db: &impl Storage, update: PoolUpdateEvent) {
     let dirty_status = get_dirty_pool(db, update.base, update.quote, update.pool_idx);
     let not_initialized =
         dirty_status.is_none() || dirty_status.is_some_and(|(_, last_block)| last_block.is_zero());
-    let pool = if not_initialized {
-        handle_init_pool(db, &update)
+    // The swap price before the update opens any new candle
+    let (pool, prev_price) = if not_initialized {
+        let pool = handle_init_pool(db, &update);
+        let price = pool.last_price_swap;
+        (pool, price)
     } else {
         let pool = get_tracked_pool(db, update.base, update.quote, update.pool_idx)
             .expect("Missing tracked pool for update");
-        handle_update(pool, &update)
+        let price = pool.last_price_swap;
+        if update.is_swap && !update.is_liq {
+            let (pool, crossed) = handle_swap_crossing(pool, &update);
+            for (tick, is_bid) in crossed {
+                let (base, quote, pool_idx) = (update.base, update.quote, update.pool_idx);
+                save_knockout_cross(db, base, quote, pool_idx, tick, is_bid, update.block);
+            }
+            (pool, price)
+        } else {
+            (handle_update(pool, &update), price)
+        }
     };
     mark_pool_fresh(db, update.base, update.quote, update.pool_idx, update.block);
 
+    match get_block_time(db, update.block) {
+        Some(time) => {
+            save_pool_snapshot(db, &pool, update.block, time);
+            if update.is_swap {
+                update_candles(db, &pool, &update, prev_price, time);
+            }
+        }
+        None => debug!(
+            "No known time for block {}, skipping pool snapshot and candles",
+            update.block
+        ),
+    }
     set_tracked_pool(db, pool);
 }
 
@@ -306,7 +384,7 @@ pub fn handle_update(pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPool
     }
 }
 
-pub fn handle_init_pool(db: &rocksdb::DB, update: &PoolUpdateEvent) -> TrackedPool {
+pub fn handle_init_pool(db: &impl Storage, update: &PoolUpdateEvent) -> TrackedPool {
     assert!(
         update.base_flow >= 0 && update.quote_flow >= 0,
         "Invalid pool initialization flows"
@@ -333,7 +411,8 @@ pub fn handle_init_pool(db: &rocksdb::DB, update: &PoolUpdateEvent) -> TrackedPo
         ambient_liq,
         bumps: vec![],
         conc_liq: 0u128.into(),
-        fee_rate: template.fee_rate.into(),
+        // Track the rate as a fraction to match revisions
+        fee_rate: template.fee_rate as f64 / TEMPLATE_FEE_UNIT,
     }
 }
 
@@ -344,43 +423,45 @@ fn root_price_from_reserves(base: u128, quote: u128) -> f64 {
     ((base as f64) / (quote as f64)).sqrt()
 }
 
-fn root_price_from_tick(tick: i32) -> f64 {
+pub fn root_price_from_tick(tick: i32) -> f64 {
     let tick = tick as f64;
     let price = 1.0001f64.powf(tick);
     price.sqrt()
 }
 
-// fn tick_from_root_price(price: f64) -> i32 {
-//     if price.abs() <= 0.0001 {
-//         return 0;
-//     }
-//     let price = price * price;
-//     let tick = price.log(1.0001f64);
-//     tick as i32
-// }
+/// Converts a (non-root) price to the tick it falls in
+pub fn tick_from_price(price: f64) -> i32 {
+    if price.abs() <= 0.0001 {
+        return 0;
+    }
+    price.log(1.0001f64).floor() as i32
+}
 
 pub fn handle_liq(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPool {
     // Calculate TVL by inc/dec-rementing by the flows
     pool.base_tvl = add_uint256_int256(pool.base_tvl, update.base_flow.into());
     pool.quote_tvl = add_uint256_int256(pool.quote_tvl, update.quote_flow.into());
 
-    let base_mag = update.base_flow.unsigned_abs();
-    let quote_mag = update.quote_flow.unsigned_abs();
-
-    if base_mag < 1000 || quote_mag < 1000 {
-        return pool;
-    }
     // flows_at_market is a confusing value coming from the croc-subgraph repo
     // it's only true for mints, burns, harvests, and swaps
     if !update.flows_at_market {
         return pool;
     }
 
-    // let mut remove_bid_bump = false;
-    // let mut remove_ask_bump = false;
-
     // is_tick_skewed is a confusing value coming from the croc-subgraph repo
     // it's only true when ask tick != bid tick and this is not a harvest
+    // The bumps are updated even when one of the flows is small, since out of range positions only deposit one token
+    if update.is_tick_skewed {
+        update_bumps(&mut pool, update);
+    }
+
+    let base_mag = update.base_flow.unsigned_abs();
+    let quote_mag = update.quote_flow.unsigned_abs();
+
+    if base_mag < 1000 || quote_mag < 1000 {
+        return pool;
+    }
+
     if update.is_tick_skewed {
         // Handle concentrated liquidity
         let (bid_tick, ask_tick) = (update.bid_tick.unwrap(), update.ask_tick.unwrap());
@@ -393,47 +474,6 @@ pub fn handle_liq(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPoo
         } else {
             warn!("Unable to compute price from concentrated flow (is something zero?): base_flow: {}, quote_flow: {}, bid_tick: {}, ask_tick: {}", update.base_flow, update.quote_flow, bid_tick, ask_tick);
         }
-        // // Initialize or fetch the liquidity bumps at bid and ask tick
-        // pool.init_bump(bid_tick);
-        // pool.init_bump(ask_tick);
-        // let liq_magn = liquidity_magnitude(update);
-
-        // let ko_bid = update.is_knockout && update.is_bid;
-        // let ko_ask = update.is_knockout && !update.is_bid;
-
-        // // We separate the bid and ask bump updates to avoid mut borrowing issues
-        // let bid_bump = pool.get_bump_mut(bid_tick).unwrap();
-        // if update.is_burn {
-        //     bid_bump.liquidity_delta -= liq_magn;
-        //     if ko_bid {
-        //         bid_bump.knockout_bid_liq -= liq_magn;
-        //         bid_bump.knockout_bid_width = 0;
-        //     }
-        // } else {
-        //     bid_bump.liquidity_delta += liq_magn;
-        //     if ko_bid {
-        //         bid_bump.knockout_bid_liq += liq_magn;
-        //         bid_bump.knockout_bid_width = ask_tick - bid_tick;
-        //     }
-        // }
-        // remove_bid_bump = should_remove_bump(bid_bump);
-
-        // let ask_bump = pool.get_bump_mut(ask_tick).unwrap();
-        // if update.is_burn {
-        //     ask_bump.liquidity_delta += liq_magn;
-        //     if ko_ask {
-        //         ask_bump.knockout_ask_liq += liq_magn;
-        //         ask_bump.knockout_ask_width = 0;
-        //     }
-        // } else {
-        //     ask_bump.liquidity_delta -= liq_magn;
-        //     if ko_ask {
-        //         ask_bump.knockout_ask_liq -= liq_magn;
-        //         ask_bump.knockout_ask_width = ask_tick - bid_tick;
-        //     }
-        // }
-        // remove_ask_bump = should_remove_bump(ask_bump);
-
         // Finally update the ambient liquidity (in the event rewards were collected)
         // pool.ambient_liq = add_uint256_int256(pool.ambient_liq, update.ambient_liq);
     } else {
@@ -445,50 +485,95 @@ pub fn handle_liq(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPoo
         pool.last_price_indic = price;
     }
 
-    // if remove_bid_bump {
-    //     pool.bumps.retain(|b| b.tick != update.bid_tick.unwrap());
-    // }
-    // if remove_ask_bump {
-    //     pool.bumps.retain(|b| b.tick != update.ask_tick.unwrap());
-    // }
-
     pool
 }
 
-// fn liquidity_magnitude(update: &PoolUpdateEvent) -> f64 {
-//     let (b_mag, q_mag) = (
-//         update.base_flow.abs() as f64,
-//         update.quote_flow.abs() as f64,
-//     );
-//     // If the flows are both less than 1k then the liquidity is "not numerically stable" and 0 is returned
-//     if b_mag < 1000f64 && q_mag < 1000f64 {
-//         0.0
-//     } else if update.conc_liq != 0u8.into() {
-//         conc_liquidity_magnitude(update, b_mag, q_mag)
-//     } else {
-//         amb_liquidity_magnitude(b_mag, q_mag)
-//     }
-// }
+// Adds (or removes when burning) a concentrated position's liquidity to the bumps at its bid and ask ticks
+fn update_bumps(pool: &mut TrackedPool, update: &PoolUpdateEvent) {
+    let (bid_tick, ask_tick) = (update.bid_tick.unwrap(), update.ask_tick.unwrap());
+    // Initialize or fetch the liquidity bumps at bid and ask tick
+    pool.init_bump(bid_tick);
+    pool.init_bump(ask_tick);
+    let liq_magn = liquidity_magnitude(update);
+
+    let ko_bid = update.is_knockout && update.is_bid;
+    let ko_ask = update.is_knockout && !update.is_bid;
+
+    // We separate the bid and ask bump updates to avoid mut borrowing issues
+    let bid_bump = pool.get_bump_mut(bid_tick).unwrap();
+    if update.is_burn {
+        bid_bump.liquidity_delta -= liq_magn;
+        if ko_bid {
+            bid_bump.knockout_bid_liq -= liq_magn;
+            bid_bump.knockout_bid_width = 0;
+        }
+    } else {
+        bid_bump.liquidity_delta += liq_magn;
+        if ko_bid {
+            bid_bump.knockout_bid_liq += liq_magn;
+            bid_bump.knockout_bid_width = ask_tick - bid_tick;
+        }
+    }
+    let remove_bid_bump = should_remove_bump(bid_bump);
+
+    let ask_bump = pool.get_bump_mut(ask_tick).unwrap();
+    if update.is_burn {
+        ask_bump.liquidity_delta += liq_magn;
+        if ko_ask {
+            ask_bump.knockout_ask_liq += liq_magn;
+            ask_bump.knockout_ask_width = 0;
+        }
+    } else {
+        ask_bump.liquidity_delta -= liq_magn;
+        if ko_ask {
+            ask_bump.knockout_ask_liq -= liq_magn;
+            ask_bump.knockout_ask_width = ask_tick - bid_tick;
+        }
+    }
+    let remove_ask_bump = should_remove_bump(ask_bump);
 
-// fn conc_liquidity_magnitude(update: &PoolUpdateEvent, base_mag: f64, quote_mag: f64) -> f64 {
-//     let bid_price = root_price_from_tick(update.bid_tick.unwrap());
-//     let ask_price = root_price_from_tick(update.ask_tick.unwrap());
+    if remove_bid_bump {
+        pool.bumps.retain(|b| b.tick != bid_tick);
+    }
+    if remove_ask_bump {
+        pool.bumps.retain(|b| b.tick != ask_tick);
+    }
+}
 
-//     if update.quote_flow == 0 {
-//         base_mag / (ask_price - bid_price)
-//     } else if update.base_flow == 0 {
-//         quote_mag / (1.0 / bid_price - 1.0 / ask_price)
-//     } else {
-//         let curr_price = derive_root_price_from_conc_flow(
-//             base_mag,
-//             quote_mag,
-//             update.bid_tick.unwrap(),
-//             update.ask_tick.unwrap(),
-//         )
-//         .unwrap_or_default();
-//         base_mag / (curr_price - bid_price)
-//     }
-// }
+fn liquidity_magnitude(update: &PoolUpdateEvent) -> f64 {
+    let (b_mag, q_mag) = (
+        update.base_flow.abs() as f64,
+        update.quote_flow.abs() as f64,
+    );
+    // If the flows are both less than 1k then the liquidity is "not numerically stable" and 0 is returned
+    if b_mag < 1000f64 && q_mag < 1000f64 {
+        0.0
+    } else if update.conc_liq != 0u8.into() {
+        conc_liquidity_magnitude(update, b_mag, q_mag)
+    } else {
+        amb_liquidity_magnitude(b_mag, q_mag)
+    }
+}
+
+fn conc_liquidity_magnitude(update: &PoolUpdateEvent, base_mag: f64, quote_mag: f64) -> f64 {
+    let bid_price = root_price_from_tick(update.bid_tick.unwrap());
+    let ask_price = root_price_from_tick(update.ask_tick.unwrap());
+
+    if update.quote_flow == 0 {
+        base_mag / (ask_price - bid_price)
+    } else if update.base_flow == 0 {
+        quote_mag / (1.0 / bid_price - 1.0 / ask_price)
+    } else {
+        let curr_price = derive_root_price_from_conc_flow(
+            update.base_flow.abs(),
+            update.quote_flow.abs(),
+            update.bid_tick.unwrap(),
+            update.ask_tick.unwrap(),
+        )
+        .unwrap_or_default();
+        base_mag / (curr_price - bid_price)
+    }
+}
 
 fn derive_price_conc_flow(
     base_flow: i128,
@@ -534,15 +619,15 @@ fn derive_root_price_from_conc_flow(
     })
 }
 
-// fn amb_liquidity_magnitude(base_mag: f64, quote_mag: f64) -> f64 {
-//     (base_mag * quote_mag).sqrt()
-// }
+fn amb_liquidity_magnitude(base_mag: f64, quote_mag: f64) -> f64 {
+    (base_mag * quote_mag).sqrt()
+}
 
-// fn should_remove_bump(bump: &LiquidityBump) -> bool {
-//     bump.liquidity_delta.abs() < 0.0001
-//         && bump.knockout_bid_liq.abs() < 0.0001
-//         && bump.knockout_ask_liq.abs() < 0.0001
-// }
+fn should_remove_bump(bump: &LiquidityBump) -> bool {
+    bump.liquidity_delta.abs() < 0.0001
+        && bump.knockout_bid_liq.abs() < 0.0001
+        && bump.knockout_ask_liq.abs() < 0.0001
+}
 
 fn derive_price_from_amb_flow(base_flow: i128, quote_flow: i128) -> f64 {
     if quote_flow == 0 {
@@ -552,7 +637,16 @@ fn derive_price_from_amb_flow(base_flow: i128, quote_flow: i128) -> f64 {
     price.abs()
 }
 
-pub fn handle_swap(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPool {
+pub fn handle_swap(pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPool {
+    handle_swap_crossing(pool, update).0
+}
+
+/// Applies a swap like handle_swap, also returning the (tick, is_bid) pivots of the knockout liquidity it crossed
+pub fn handle_swap_crossing(
+    mut pool: TrackedPool,
+    update: &PoolUpdateEvent,
+) -> (TrackedPool, Vec<(i32, bool)>) {
+    let mut crossed = vec![];
     // magnitude == absolute value
     let base_mag = update.base_flow.unsigned_abs();
     let quote_mag = update.quote_flow.unsigned_abs();
@@ -565,11 +659,9 @@ pub fn handle_swap(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPo
     pool.base_volume += base_mag.into();
     pool.quote_volume += quote_mag.into();
     // Accumulate fees and add to ambient liquidity
-    if update.in_base_qty {
-        pool.quote_fees += (quote_mag as f64) * pool.fee_rate;
-    } else {
-        pool.base_fees += (base_mag as f64) * pool.fee_rate;
-    }
+    let (base_fees, quote_fees) = swap_fees(update, pool.fee_rate);
+    pool.base_fees += base_fees;
+    pool.quote_fees += quote_fees;
 
     if is_flow_dual_stable(update.base_flow as f64, update.quote_flow as f64) {
         let new_price = derive_price_swap(
@@ -578,22 +670,35 @@ pub fn handle_swap(mut pool: TrackedPool, update: &PoolUpdateEvent) -> TrackedPo
             pool.fee_rate,
             update.base_flow < 0,
         );
-        // let old_price = pool.last_price_swap;
+        let old_price = pool.last_price_swap;
         pool.last_price_swap = new_price;
         pool.last_price_indic = new_price;
 
-        // // Determine if any knockouts were crossed and handle those changes to liquidity
-        // // using updateKOCross in graphcache-go model/liquidityCurve.go
-        // let old_tick = tick_from_root_price(old_price);
-        // let new_tick = tick_from_root_price(new_price);
-        // let ko_bumps: Vec<LiquidityBump> = get_crossed_ko_bumps(&pool, old_tick, new_tick);
+        // Determine if any knockouts were crossed and handle those changes to liquidity
+        // using updateKOCross in graphcache-go model/liquidityCurve.go
+        let old_tick = tick_from_price(old_price);
+        let new_tick = tick_from_price(new_price);
+        let ko_bumps: Vec<LiquidityBump> = get_crossed_ko_bumps(&pool, old_tick, new_tick);
 
-        // for bump in ko_bumps {
-        //     cross_ko_bump(&mut pool, &bump, new_price < old_price);
-        // }
+        let is_bid = new_price < old_price;
+        for bump in &ko_bumps {
+            cross_ko_bump(&mut pool, bump, is_bid);
+        }
+        pool.bumps.retain(|b| !should_remove_bump(b));
+        crossed = ko_bumps.iter().map(|b| (b.tick, is_bid)).collect();
     }
 
-    pool
+    (pool, crossed)
+}
+
+/// Computes the (base, quote) fees paid by a swap given the pool's fee rate as a fraction, fees are charged
+/// on the side opposite the fixed quantity
+pub fn swap_fees(update: &PoolUpdateEvent, fee_rate: f64) -> (f64, f64) {
+    if update.in_base_qty {
+        (0.0, (update.quote_flow.unsigned_abs() as f64) * fee_rate)
+    } else {
+        ((update.base_flow.unsigned_abs() as f64) * fee_rate, 0.0)
+    }
 }
 
 fn add_uint256_int256(a: Uint256, b: Int256) -> Uint256 {
@@ -621,23 +726,23 @@ fn derive_price_swap(base_mag: f64, quote_mag: f64, _fee_rate: f64, _is_sell: bo
     // }
 }
 
-// fn get_crossed_ko_bumps(pool: &TrackedPool, old_tick: i32, new_tick: i32) -> Vec<LiquidityBump> {
-//     if new_tick > old_tick {
-//         // Moving in the positive direction, we care about "ask" knockouts
-//         pool.bumps
-//             .iter()
-//             .filter(|b| b.tick > old_tick && b.tick <= new_tick && b.knockout_ask_liq > 0.0)
-//             .cloned()
-//             .collect()
-//     } else {
-//         // Moving in the negative direction, we care about "bid" knockouts
-//         pool.bumps
-//             .iter()
-//             .filter(|b| b.tick < old_tick && b.tick >= new_tick && b.knockout_bid_liq > 0.0)
-//             .cloned()
-//             .collect()
-//     }
-// }
+fn get_crossed_ko_bumps(pool: &TrackedPool, old_tick: i32, new_tick: i32) -> Vec<LiquidityBump> {
+    if new_tick > old_tick {
+        // Moving in the positive direction, we care about "ask" knockouts
+        pool.bumps
+            .iter()
+            .filter(|b| b.tick > old_tick && b.tick <= new_tick && b.knockout_ask_liq > 0.0)
+            .cloned()
+            .collect()
+    } else {
+        // Moving in the negative direction, we care about "bid" knockouts
+        pool.bumps
+            .iter()
+            .filter(|b| b.tick < old_tick && b.tick >= new_tick && b.knockout_bid_liq > 0.0)
+            .cloned()
+            .collect()
+    }
+}
 
 // Knockout liquidity can be removed from a pool in a bid (price reduced) or ask (price increased) direction
 // Once a knockout "pivot" is crossed, the position's liquidity must be removed from both bumps to cancel out the position
@@ -1108,3 +1213,50 @@ fn knockout_burn() {
         );
     }
 }
+
+#[test]
+fn test_reset_pool_index() {
+    use crate::althea::database::pools::save_init_pool;
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    let base = Address::default();
+    let quote = Address::from_slice(&[1u8; 20]).unwrap();
+    let other = Address::from_slice(&[2u8; 20]).unwrap();
+    let pool_idx: Uint256 = 36000u32.into();
+    save_init_pool(
+        &db,
+        InitPoolEvent {
+            base,
+            quote,
+            pool_idx,
+            ..Default::default()
+        },
+    );
+    for q in [quote, other] {
+        set_tracked_pool(
+            &db,
+            TrackedPool {
+                base,
+                quote: q,
+                pool_idx,
+                ..Default::default()
+            },
+        );
+        mark_pool_fresh(&db, base, q, pool_idx, 100u32.into());
+    }
+
+    assert!(reset_pool_index(&db, base, quote, pool_idx));
+    assert!(get_tracked_pool(&db, base, quote, pool_idx).is_none());
+    assert_eq!(
+        get_dirty_pool(&db, base, quote, pool_idx),
+        Some((true, Uint256::default()))
+    );
+    // Other pools are untouched, and a pool without an InitPool can not be rebuilt
+    assert!(get_tracked_pool(&db, base, other, pool_idx).is_some());
+    assert!(!reset_pool_index(&db, base, other, pool_idx));
+    assert_eq!(
+        get_dirty_pool(&db, base, other, pool_idx),
+        Some((false, 100u32.into()))
+    );
+}
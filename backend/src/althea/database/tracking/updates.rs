@@ -19,6 +19,7 @@ use super::derive_root_price_from_conc_flow;
 use super::root_price_from_reserves;
 use super::root_price_from_tick;
 use super::InitPoolEvent;
+use super::TEMPLATE_FEE_UNIT;
 
 /// Encodes various pool update evetns (swap, mint burn, ...) into a single format which can be used to update
 /// inferred pool state in a TrackedPool
@@ -75,7 +76,7 @@ impl From<InitPoolEvent> for PoolUpdateEvent {
 
 impl From<PoolRevisionEvent> for PoolUpdateEvent {
     fn from(value: PoolRevisionEvent) -> Self {
-        let rate = value.fee_rate as f64 / 1000000f64;
+        let rate = value.fee_rate as f64 / TEMPLATE_FEE_UNIT;
         PoolUpdateEvent {
             block: value.block_height,
             base: value.base,
@@ -211,27 +212,9 @@ impl From<SwapEvent> for PoolUpdateEvent {
 
 impl From<MintKnockoutEvent> for PoolUpdateEvent {
     fn from(event: MintKnockoutEvent) -> Self {
-        let base_mag = event.base_flow.abs();
-        let quote_mag = event.quote_flow.abs();
         let (bid_tick, ask_tick) = (event.lower_tick, event.upper_tick);
-        let lower_price = root_price_from_tick(bid_tick);
-        let upper_price = root_price_from_tick(ask_tick);
-        let conc_liq = {
-            if quote_mag == 0 {
-                base_mag * (upper_price - lower_price) as i128
-            } else if base_mag == 0 {
-                (quote_mag as f64 / (1.0 / lower_price - 1.0 / upper_price)) as i128
-            } else {
-                let price =
-                    derive_root_price_from_conc_flow(base_mag, quote_mag, bid_tick, ask_tick);
-                if price.is_none() {
-                    0
-                } else {
-                    ((base_mag as f64) / (price.unwrap() - lower_price)) as i128
-                }
-            }
-        }
-        .into();
+        let conc_liq =
+            knockout_liquidity(event.base_flow, event.quote_flow, bid_tick, ask_tick).into();
         PoolUpdateEvent {
             block: event.block_height,
             base: event.base,
@@ -253,6 +236,25 @@ impl From<MintKnockoutEvent> for PoolUpdateEvent {
     }
 }
 
+/// Infers the concentrated liquidity of a knockout position between `bid_tick` and `ask_tick` from the flows of its mint
+pub fn knockout_liquidity(base_flow: i128, quote_flow: i128, bid_tick: i32, ask_tick: i32) -> i128 {
+    let base_mag = base_flow.abs();
+    let quote_mag = quote_flow.abs();
+    let lower_price = root_price_from_tick(bid_tick);
+    let upper_price = root_price_from_tick(ask_tick);
+    if quote_mag == 0 {
+        (base_mag as f64 / (upper_price - lower_price)) as i128
+    } else if base_mag == 0 {
+        (quote_mag as f64 / (1.0 / lower_price - 1.0 / upper_price)) as i128
+    } else {
+        let price = derive_root_price_from_conc_flow(base_mag, quote_mag, bid_tick, ask_tick);
+        match price {
+            Some(price) => ((base_mag as f64) / (price - lower_price)) as i128,
+            None => 0,
+        }
+    }
+}
+
 impl From<BurnKnockoutEvent> for PoolUpdateEvent {
     fn from(value: BurnKnockoutEvent) -> Self {
         let ambient_liq = -(value.fee_rewards as i128);
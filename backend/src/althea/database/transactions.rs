@@ -0,0 +1,737 @@
+// This file merges the swap, position and knockout events stored under each user's keys into a single typed activity feed,
+// and keeps a copy of every event indexed by pool for reading a pool's recent history
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use clarity::{Address, Uint256};
+use log::{debug, error, info};
+use num_traits::ToPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::althea::ambient::{
+    knockout::{BurnKnockoutEvent, MintKnockoutEvent, WithdrawKnockoutEvent},
+    positions::{
+        BurnAmbientEvent, BurnRangedEvent, HarvestEvent, MintAmbientEvent, MintRangedEvent,
+    },
+    swap::SwapEvent,
+};
+
+use super::{
+    has_prefix,
+    pools::{get_all_swap, swap_user_pool_prefix, swap_user_prefix, SWAP_PREFIX},
+    positions::{
+        ambient::{
+            burn_ambient_user_pool_prefix, burn_ambient_user_prefix, get_all_burn_ambient,
+            get_all_mint_ambient, mint_ambient_user_pool_prefix, mint_ambient_user_prefix,
+            BURN_AMBIENT_PREFIX, MINT_AMBIENT_PREFIX,
+        },
+        knockout::{
+            burn_knockout_user_pool_prefix, burn_knockout_user_prefix, get_all_burn_knockout,
+            get_all_mint_knockout, get_all_withdraw_knockout, mint_knockout_user_pool_prefix,
+            mint_knockout_user_prefix, withdraw_knockout_user_pool_prefix,
+            withdraw_knockout_user_prefix, BURN_KNOCKOUT_PREFIX, MINT_KNOCKOUT_PREFIX,
+            WITHDRAW_KNOCKOUT_PREFIX,
+        },
+        ranged::{
+            burn_ranged_user_pool_prefix, burn_ranged_user_prefix, get_all_burn_ranged,
+            get_all_harvest, get_all_mint_ranged, harvest_user_pool_prefix, harvest_user_prefix,
+            mint_ranged_user_pool_prefix, mint_ranged_user_prefix, BURN_RANGED_PREFIX,
+            HARVEST_PREFIX, MINT_RANGED_PREFIX,
+        },
+    },
+    schema::{deserialize_record, serialize_record},
+    storage::{Direction, Storage},
+};
+
+/// The kind of event behind a Tx
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxType {
+    #[default]
+    Swap,
+    MintRanged,
+    BurnRanged,
+    Harvest,
+    MintAmbient,
+    BurnAmbient,
+    MintKnockout,
+    BurnKnockout,
+    WithdrawKnockout,
+}
+
+impl TxType {
+    pub const ALL: [TxType; 9] = [
+        TxType::Swap,
+        TxType::MintRanged,
+        TxType::BurnRanged,
+        TxType::Harvest,
+        TxType::MintAmbient,
+        TxType::BurnAmbient,
+        TxType::MintKnockout,
+        TxType::BurnKnockout,
+        TxType::WithdrawKnockout,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxType::Swap => "swap",
+            TxType::MintRanged => "mint_ranged",
+            TxType::BurnRanged => "burn_ranged",
+            TxType::Harvest => "harvest",
+            TxType::MintAmbient => "mint_ambient",
+            TxType::BurnAmbient => "burn_ambient",
+            TxType::MintKnockout => "mint_knockout",
+            TxType::BurnKnockout => "burn_knockout",
+            TxType::WithdrawKnockout => "withdraw_knockout",
+        }
+    }
+}
+
+impl FromStr for TxType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TxType::ALL
+            .into_iter()
+            .find(|t| t.as_str() == s)
+            .ok_or_else(|| format!("Unknown tx type {}", s))
+    }
+}
+
+/// Parses a comma separated list of tx types, e.g. "swap,mint_ranged"
+pub fn parse_tx_types(types: &str) -> Result<Vec<TxType>, String> {
+    types
+        .split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(TxType::from_str)
+        .collect()
+}
+
+/// A single event in a user's or pool's history, fields which do not apply to the event type are left empty.
+/// Flows are from the user's perspective like the events themselves: positive when paid into the pool
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tx {
+    pub block_height: Uint256,
+    pub index: Uint256,
+    pub tx_type: TxType,
+    pub user: Address,
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+    pub base_flow: i128,
+    pub quote_flow: i128,
+    // Swaps only, true when paying the base token
+    pub is_buy: Option<bool>,
+    // The range of concentrated positions and knockouts
+    pub bid_tick: Option<i32>,
+    pub ask_tick: Option<i32>,
+    // Knockouts only
+    pub is_bid: Option<bool>,
+    // The liquidity minted or burned by ranged and ambient position events
+    pub liq: Option<u128>,
+}
+
+impl Tx {
+    /// The (block, log index) position of the event on chain
+    pub fn order(&self) -> (Uint256, Uint256) {
+        (self.block_height, self.index)
+    }
+
+    pub fn in_pool(&self, base: Address, quote: Address, pool_idx: Uint256) -> bool {
+        self.base == base && self.quote == quote && self.pool_idx == pool_idx
+    }
+}
+
+impl From<SwapEvent> for Tx {
+    fn from(e: SwapEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::Swap,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            is_buy: Some(e.is_buy),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<MintRangedEvent> for Tx {
+    fn from(e: MintRangedEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::MintRanged,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.bid_tick),
+            ask_tick: Some(e.ask_tick),
+            liq: Some(e.liq),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<BurnRangedEvent> for Tx {
+    fn from(e: BurnRangedEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::BurnRanged,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.bid_tick),
+            ask_tick: Some(e.ask_tick),
+            liq: Some(e.liq),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<HarvestEvent> for Tx {
+    fn from(e: HarvestEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::Harvest,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.bid_tick),
+            ask_tick: Some(e.ask_tick),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<MintAmbientEvent> for Tx {
+    fn from(e: MintAmbientEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::MintAmbient,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            liq: Some(e.liq),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<BurnAmbientEvent> for Tx {
+    fn from(e: BurnAmbientEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::BurnAmbient,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            liq: Some(e.liq),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<MintKnockoutEvent> for Tx {
+    fn from(e: MintKnockoutEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::MintKnockout,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.lower_tick),
+            ask_tick: Some(e.upper_tick),
+            is_bid: Some(e.is_bid),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<BurnKnockoutEvent> for Tx {
+    fn from(e: BurnKnockoutEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::BurnKnockout,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.lower_tick),
+            ask_tick: Some(e.upper_tick),
+            is_bid: Some(e.is_bid),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<WithdrawKnockoutEvent> for Tx {
+    fn from(e: WithdrawKnockoutEvent) -> Self {
+        Tx {
+            block_height: e.block_height,
+            index: e.index,
+            tx_type: TxType::WithdrawKnockout,
+            user: e.user,
+            base: e.base,
+            quote: e.quote,
+            pool_idx: e.pool_idx,
+            base_flow: e.base_flow,
+            quote_flow: e.quote_flow,
+            bid_tick: Some(e.lower_tick),
+            ask_tick: Some(e.upper_tick),
+            is_bid: Some(e.is_bid),
+            ..Default::default()
+        }
+    }
+}
+
+type UserPrefixFn = fn(Address) -> String;
+type UserPoolPrefixFn = fn(Address, Address, Address, Uint256) -> String;
+
+// The prefix of `user`'s events of `tx_type`, narrowed to the (base, quote, pool_idx) `pool` if given
+fn user_tx_prefix(
+    tx_type: TxType,
+    user: Address,
+    pool: Option<(Address, Address, Uint256)>,
+) -> String {
+    let (user_prefix, user_pool_prefix): (UserPrefixFn, UserPoolPrefixFn) = match tx_type {
+        TxType::Swap => (swap_user_prefix, swap_user_pool_prefix),
+        TxType::MintRanged => (mint_ranged_user_prefix, mint_ranged_user_pool_prefix),
+        TxType::BurnRanged => (burn_ranged_user_prefix, burn_ranged_user_pool_prefix),
+        TxType::Harvest => (harvest_user_prefix, harvest_user_pool_prefix),
+        TxType::MintAmbient => (mint_ambient_user_prefix, mint_ambient_user_pool_prefix),
+        TxType::BurnAmbient => (burn_ambient_user_prefix, burn_ambient_user_pool_prefix),
+        TxType::MintKnockout => (mint_knockout_user_prefix, mint_knockout_user_pool_prefix),
+        TxType::BurnKnockout => (burn_knockout_user_prefix, burn_knockout_user_pool_prefix),
+        TxType::WithdrawKnockout => (
+            withdraw_knockout_user_prefix,
+            withdraw_knockout_user_pool_prefix,
+        ),
+    };
+    match pool {
+        Some((base, quote, pool_idx)) => user_pool_prefix(user, base, quote, pool_idx),
+        None => user_prefix(user),
+    }
+}
+
+/// Gets all events of `tx_type` under `prefix` (or every one if None) as Txs
+pub fn get_all_txs(db: &impl Storage, tx_type: TxType, prefix: Option<&[u8]>) -> Vec<Tx> {
+    fn txs<E: Into<Tx>>(events: Vec<E>) -> Vec<Tx> {
+        events.into_iter().map(Into::into).collect()
+    }
+    match tx_type {
+        TxType::Swap => txs(get_all_swap(db, prefix)),
+        TxType::MintRanged => txs(get_all_mint_ranged(db, prefix)),
+        TxType::BurnRanged => txs(get_all_burn_ranged(db, prefix)),
+        TxType::Harvest => txs(get_all_harvest(db, prefix)),
+        TxType::MintAmbient => txs(get_all_mint_ambient(db, prefix)),
+        TxType::BurnAmbient => txs(get_all_burn_ambient(db, prefix)),
+        TxType::MintKnockout => txs(get_all_mint_knockout(db, prefix)),
+        TxType::BurnKnockout => txs(get_all_burn_knockout(db, prefix)),
+        TxType::WithdrawKnockout => txs(get_all_withdraw_knockout(db, prefix)),
+    }
+}
+
+// The family storing events of `tx_type`
+fn tx_family(tx_type: TxType) -> &'static str {
+    match tx_type {
+        TxType::Swap => SWAP_PREFIX,
+        TxType::MintRanged => MINT_RANGED_PREFIX,
+        TxType::BurnRanged => BURN_RANGED_PREFIX,
+        TxType::Harvest => HARVEST_PREFIX,
+        TxType::MintAmbient => MINT_AMBIENT_PREFIX,
+        TxType::BurnAmbient => BURN_AMBIENT_PREFIX,
+        TxType::MintKnockout => MINT_KNOCKOUT_PREFIX,
+        TxType::BurnKnockout => BURN_KNOCKOUT_PREFIX,
+        TxType::WithdrawKnockout => WITHDRAW_KNOCKOUT_PREFIX,
+    }
+}
+
+// Decodes a stored event of `tx_type` as a Tx
+fn decode_tx(tx_type: TxType, v: &[u8]) -> bincode::Result<Tx> {
+    Ok(match tx_type {
+        TxType::Swap => deserialize_record::<SwapEvent>(v)?.into(),
+        TxType::MintRanged => deserialize_record::<MintRangedEvent>(v)?.into(),
+        TxType::BurnRanged => deserialize_record::<BurnRangedEvent>(v)?.into(),
+        TxType::Harvest => deserialize_record::<HarvestEvent>(v)?.into(),
+        TxType::MintAmbient => deserialize_record::<MintAmbientEvent>(v)?.into(),
+        TxType::BurnAmbient => deserialize_record::<BurnAmbientEvent>(v)?.into(),
+        TxType::MintKnockout => deserialize_record::<MintKnockoutEvent>(v)?.into(),
+        TxType::BurnKnockout => deserialize_record::<BurnKnockoutEvent>(v)?.into(),
+        TxType::WithdrawKnockout => deserialize_record::<WithdrawKnockoutEvent>(v)?.into(),
+    })
+}
+
+// The keys of `user`'s events of `tx_type` (in `pool` if given) with their (block, log index), in chronological order.
+// Every user event key ends in "_{block}_{index}", but the numbers are not padded so the keys themselves are unordered
+fn user_tx_keys(
+    db: &impl Storage,
+    tx_type: TxType,
+    user: Address,
+    pool: Option<(Address, Address, Uint256)>,
+) -> Vec<((Uint256, Uint256), Vec<u8>)> {
+    let prefix = user_tx_prefix(tx_type, user, pool);
+    let mut keys = vec![];
+    for (k, _) in db
+        .prefix_scan(tx_family(tx_type), prefix.as_bytes())
+        .flatten()
+    {
+        if !k.starts_with(prefix.as_bytes()) {
+            break;
+        }
+        // A pool index prefix also matches longer indices, e.g. 36000 and 360001
+        if pool.is_some() && k.get(prefix.len()) != Some(&b'_') {
+            continue;
+        }
+        let order = std::str::from_utf8(&k).ok().and_then(|key| {
+            let mut parts = key.rsplitn(3, '_');
+            let index = Uint256::from_str(parts.next()?).ok()?;
+            let block = Uint256::from_str(parts.next()?).ok()?;
+            Some((block, index))
+        });
+        match order {
+            Some(order) => keys.push((order, k.to_vec())),
+            None => error!("Unexpected {} key {:?}", tx_type.as_str(), k),
+        }
+    }
+    keys.sort();
+    keys
+}
+
+/// Gets up to `limit` of `user`'s events of the given `types` after skipping the first `skip`, in chronological order (or
+/// newest first if `descending`), optionally only those in the (base, quote, pool_idx) `pool`. Also returns how many
+/// matching events there are in total.
+/// Only the keys are read up front, the events of each type are merged by (block, log index) and decoded for the page alone
+pub fn get_user_txs(
+    db: &impl Storage,
+    user: Address,
+    pool: Option<(Address, Address, Uint256)>,
+    types: &[TxType],
+    descending: bool,
+    skip: usize,
+    limit: usize,
+) -> (Vec<Tx>, usize) {
+    let mut scans = vec![];
+    let mut total = 0;
+    for tx_type in types {
+        let mut keys = user_tx_keys(db, *tx_type, user, pool);
+        if descending {
+            keys.reverse();
+        }
+        total += keys.len();
+        scans.push((*tx_type, keys.into_iter().peekable()));
+    }
+
+    let mut txs = vec![];
+    let mut seen = 0;
+    while txs.len() < limit {
+        // The scan whose next event comes first in the requested order
+        let heads = scans
+            .iter_mut()
+            .filter_map(|(tx_type, keys)| Some((keys.peek()?.0, *tx_type, keys)));
+        let next = if descending {
+            heads.max_by(|a, b| a.0.cmp(&b.0))
+        } else {
+            heads.min_by(|a, b| a.0.cmp(&b.0))
+        };
+        let Some((_, tx_type, keys)) = next else {
+            break;
+        };
+        let (_, k) = keys.next().unwrap();
+        seen += 1;
+        if seen <= skip {
+            continue;
+        }
+        match db.get(tx_family(tx_type), &k) {
+            Ok(Some(v)) => txs.push(decode_tx(tx_type, &v).unwrap()),
+            _ => error!("Unable to read {} event {:?}", tx_type.as_str(), k),
+        }
+    }
+    (txs, total)
+}
+
+/// An index of every event by pool, ordered by (block, log index) so that a pool's history can be read backwards from any point
+pub const POOL_TX_PREFIX: &str = "pool-tx_";
+// The trailing separator stops pool index 36000 from matching 360001
+fn pool_tx_pool_prefix(base: Address, quote: Address, pool_idx: Uint256) -> String {
+    format!("{}{}_{}_{}_", POOL_TX_PREFIX, base, quote, pool_idx)
+}
+// Blocks and log indices are zero padded so that the keys sort numerically
+fn pool_tx_key(base: Address, quote: Address, pool_idx: Uint256, cursor: TxCursor) -> String {
+    format!(
+        "{}{}",
+        pool_tx_pool_prefix(base, quote, pool_idx),
+        cursor.key()
+    )
+}
+
+/// A position in a pool's history, given to clients as "{block}_{index}" to continue a feed from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TxCursor {
+    pub block: u64,
+    pub index: u64,
+}
+
+impl TxCursor {
+    fn key(&self) -> String {
+        format!("{:020}_{:020}", self.block, self.index)
+    }
+}
+
+impl From<&Tx> for TxCursor {
+    fn from(tx: &Tx) -> Self {
+        TxCursor {
+            block: tx.block_height.to_u64().unwrap_or(u64::MAX),
+            index: tx.index.to_u64().unwrap_or(u64::MAX),
+        }
+    }
+}
+
+impl Display for TxCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.block, self.index)
+    }
+}
+
+impl FromStr for TxCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid cursor {}", s);
+        let (block, index) = s.split_once('_').ok_or_else(invalid)?;
+        Ok(TxCursor {
+            block: block.parse().map_err(|_| invalid())?,
+            index: index.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Adds `tx` to its pool's history
+pub fn save_pool_tx(db: &impl Storage, tx: &Tx) {
+    let k = pool_tx_key(tx.base, tx.quote, tx.pool_idx, tx.into());
+    debug!("Saving Tx to key {}", k);
+    let v = serialize_record(tx).unwrap();
+
+    db.put(POOL_TX_PREFIX, k.as_bytes(), &v).unwrap();
+}
+
+/// Gets up to `limit` of a pool's events of the given `types`, newest first, starting from the event just before `before`
+/// or from the latest event if None
+pub fn get_pool_txs(
+    db: &impl Storage,
+    base: Address,
+    quote: Address,
+    pool_idx: Uint256,
+    types: &[TxType],
+    before: Option<TxCursor>,
+    limit: usize,
+) -> Vec<Tx> {
+    let prefix = pool_tx_pool_prefix(base, quote, pool_idx);
+    // Every key in the pool sorts below the prefix followed by '~', so None starts from the latest event
+    let start = match before {
+        Some(cursor) => pool_tx_key(base, quote, pool_idx, cursor),
+        None => format!("{}~", prefix),
+    };
+    let mut txs = vec![];
+    let iter = db.scan_from(POOL_TX_PREFIX, start.as_bytes(), Direction::Reverse);
+    for (k, v) in iter.flatten() {
+        if !k.starts_with(prefix.as_bytes()) || txs.len() >= limit {
+            break;
+        }
+        // The cursor itself was already returned in the previous page
+        if k.as_ref() == start.as_bytes() {
+            continue;
+        }
+        let tx: Tx = deserialize_record(&v).unwrap();
+        if types.contains(&tx.tx_type) {
+            txs.push(tx);
+        }
+    }
+    txs
+}
+
+/// Whether any events have been indexed without also being added to the pool histories, e.g. by an older version
+pub fn pool_tx_index_missing(db: &impl Storage) -> bool {
+    !has_prefix(db, POOL_TX_PREFIX) && has_prefix(db, SWAP_PREFIX)
+}
+
+/// Fills the pool histories from every stored event
+pub fn rebuild_pool_tx_index(db: &impl Storage) {
+    for tx_type in TxType::ALL {
+        let txs = get_all_txs(db, tx_type, None);
+        info!("Indexing {} {} events by pool", txs.len(), tx_type.as_str());
+        for tx in txs {
+            save_pool_tx(db, &tx);
+        }
+    }
+}
+
+#[test]
+fn test_parse_tx_types() {
+    assert_eq!(
+        parse_tx_types("swap, mint_ranged,withdraw_knockout"),
+        Ok(vec![
+            TxType::Swap,
+            TxType::MintRanged,
+            TxType::WithdrawKnockout
+        ])
+    );
+    assert_eq!(parse_tx_types(""), Ok(vec![]));
+    assert!(parse_tx_types("swap,mint").is_err());
+    for t in TxType::ALL {
+        assert_eq!(TxType::from_str(t.as_str()), Ok(t));
+        // The query names match the serialized names
+        assert_eq!(
+            serde_json::to_string(&t).unwrap(),
+            format!("\"{}\"", t.as_str())
+        );
+    }
+}
+
+#[test]
+fn test_tx_cursor() {
+    let cursor = TxCursor {
+        block: 1234,
+        index: 7,
+    };
+    assert_eq!(cursor.to_string(), "1234_7");
+    assert_eq!(TxCursor::from_str("1234_7"), Ok(cursor));
+    assert!(TxCursor::from_str("1234").is_err());
+    assert!(TxCursor::from_str("a_7").is_err());
+
+    // Keys sort in (block, index) order, and every key in a pool sorts below the '~' used to start from the latest
+    let (base, quote) = (Address::default(), Address::default());
+    let key = |block: u64, index: u64| {
+        pool_tx_key(base, quote, 36000u64.into(), TxCursor { block, index })
+    };
+    assert!(key(9, 100) < key(10, 2));
+    assert!(key(10, 2) < key(10, 10));
+    assert!(
+        key(u64::MAX, u64::MAX) < format!("{}~", pool_tx_pool_prefix(base, quote, 36000u64.into()))
+    );
+    assert!(!key(1, 1).starts_with(&pool_tx_pool_prefix(base, quote, 3600u64.into())));
+}
+
+#[test]
+fn test_get_pool_txs() {
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    let (base, quote) = (Address::default(), Address::default());
+    let tx = |block: u64, pool_idx: u64, tx_type: TxType| Tx {
+        block_height: block.into(),
+        tx_type,
+        pool_idx: pool_idx.into(),
+        ..Default::default()
+    };
+    for block in 1..=5 {
+        save_pool_tx(&db, &tx(block, 36000, TxType::Swap));
+    }
+    save_pool_tx(&db, &tx(6, 36000, TxType::MintAmbient));
+    save_pool_tx(&db, &tx(7, 36001, TxType::Swap));
+
+    let blocks = |txs: Vec<Tx>| -> Vec<u64> {
+        txs.iter()
+            .map(|tx| tx.block_height.to_u64().unwrap())
+            .collect()
+    };
+    let page = get_pool_txs(&db, base, quote, 36000u64.into(), &TxType::ALL, None, 3);
+    assert_eq!(blocks(page.clone()), [6, 5, 4]);
+    let next = get_pool_txs(
+        &db,
+        base,
+        quote,
+        36000u64.into(),
+        &TxType::ALL,
+        Some(page.last().unwrap().into()),
+        3,
+    );
+    assert_eq!(blocks(next), [3, 2, 1]);
+    let mints = get_pool_txs(
+        &db,
+        base,
+        quote,
+        36000u64.into(),
+        &[TxType::MintAmbient],
+        None,
+        3,
+    );
+    assert_eq!(blocks(mints), [6]);
+}
+
+#[test]
+fn test_get_user_txs() {
+    use super::{pools::save_swap, positions::ambient::save_mint_ambient};
+    use crate::althea::database::storage::MemoryStorage;
+
+    let db = MemoryStorage::new();
+    let (user, base, quote) = (Address::default(), Address::default(), Address::default());
+    let swap = |block: u64, pool_idx: u64| SwapEvent {
+        block_height: block.into(),
+        pool_idx: pool_idx.into(),
+        ..Default::default()
+    };
+    // Block 10 is stored before block 2, since the keys are not padded
+    save_swap(&db, swap(2, 36000));
+    save_swap(&db, swap(10, 36000));
+    save_swap(&db, swap(7, 360001));
+    save_mint_ambient(
+        &db,
+        MintAmbientEvent {
+            block_height: 5u64.into(),
+            pool_idx: 36000u64.into(),
+            ..Default::default()
+        },
+    );
+
+    let blocks = |(txs, total): (Vec<Tx>, usize)| -> (Vec<u64>, usize) {
+        let blocks = txs
+            .iter()
+            .map(|tx| tx.block_height.to_u64().unwrap())
+            .collect();
+        (blocks, total)
+    };
+    let all = get_user_txs(&db, user, None, &TxType::ALL, false, 0, 10);
+    assert_eq!(blocks(all), (vec![2, 5, 7, 10], 4));
+    let pool = Some((base, quote, 36000u64.into()));
+    let in_pool = get_user_txs(&db, user, pool, &TxType::ALL, false, 0, 10);
+    assert_eq!(blocks(in_pool), (vec![2, 5, 10], 3));
+    let page = get_user_txs(&db, user, pool, &TxType::ALL, true, 1, 1);
+    assert_eq!(blocks(page), (vec![5], 3));
+    let swaps = get_user_txs(&db, user, None, &[TxType::Swap], true, 0, 2);
+    assert_eq!(blocks(swaps), (vec![10, 7], 3));
+}
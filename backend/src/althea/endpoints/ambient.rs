@@ -1,24 +1,43 @@
 use crate::althea::{
+    ambient::{
+        croc_query::{get_ambient_tokens, get_conc_rewards},
+        router::{best_route, get_pool_edges, get_usd_price, PoolEdge},
+    },
     database::{
-        curve::get_price,
-        pools::{get_init_pool, get_init_pools},
+        blocks::get_block_time,
+        curve::{get_latest_price, get_root_price},
+        pools::{get_init_pool, get_init_pools, get_pool_template, Pool},
         positions::{
             ambient::{get_all_burn_ambient, get_all_mint_ambient},
-            get_active_user_pool_positions, get_active_user_positions,
+            apr::{get_position_apr, PositionApr},
+            get_active_user_pool_positions, get_active_user_position, get_active_user_positions,
             ranged::{get_all_burn_ranged, get_all_mint_ranged},
         },
+        tokens::{get_token_metadata, TokenMetadata},
         tracking::get_tracked_pool,
+        transactions::{get_pool_txs, get_user_txs, parse_tx_types, Tx, TxCursor, TxType},
     },
-    get_mainnet_web3, ALTHEA_MAINNET_EVM_CHAIN_ID, DEFAULT_POOL_TEMPLATES, MAINNET_QUERIER,
+    get_althea_web3, get_mainnet_web3, ALTHEA_MAINNET_EVM_CHAIN_ID, DEFAULT_POOL_TEMPLATES,
+    MAINNET_QUERIER,
 };
 use crate::{
     althea::database::{
         get_syncing,
         positions::{
+            concentrated_amounts,
             knockout::{get_all_burn_knockout, get_all_mint_knockout},
-            Position::{Ambient, Ranged},
+            pnl::{position_pnl, PositionPnl},
+            KnockoutStatus,
+            Position::{self, Ambient, Knockout, Ranged},
+        },
+        tracking::{
+            candles::{
+                get_candle_before, get_candles, get_window_stats, Candle, CANDLE_PERIODS,
+                DAY_WINDOW, WEEK_WINDOW,
+            },
+            history::{get_pool_snapshot_at, get_pool_snapshots, PoolSnapshot},
+            tick_from_price, LiquidityBump, TrackedPool,
         },
-        tracking::{LiquidityBump, TrackedPool},
     },
     Opts,
 };
@@ -34,7 +53,12 @@ use num_traits::ToPrimitive;
 use rocksdb::DB;
 use serde::{Deserialize, Serialize};
 
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PoolRequest {
@@ -247,15 +271,9 @@ pub async fn query_price(db: web::Data<Arc<DB>>, q: web::Query<PriceQuery>) -> i
     } else {
         (q.to, q.from, true)
     };
-    let q64_price = get_price(&db, base, quote, q.pool_idx);
-    match q64_price {
+    match get_latest_price(&db, base, quote, q.pool_idx) {
         None => HttpResponse::NotFound().body("No known price"),
-        Some(q64_price) => {
-            // The stored price is the sqrt price as a Q64 number, so first we divide by 2^64 and then square it.
-            let price = (q64_price.to_f64().unwrap() / 2.0f64.powf(64.0)).powf(2.0);
-
-            HttpResponse::Ok().json(if flip { 1.0 / price } else { price })
-        }
+        Some(price) => HttpResponse::Ok().json(if flip { 1.0 / price } else { price }),
     }
 }
 /// A request for a user's positions in a pool
@@ -303,6 +321,120 @@ pub struct UserPosition {
     pub apr_contributed_liq: f64,
     pub apr: f64,
     pub position_id: f64,
+    // One of "active", "knocked_out" or "withdrawn" for knockout positions, not part of the graphcache response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub knockout_status: Option<String>,
+
+    // ADDED: The position's state at the pool's current price, filled by add_current_state
+    // The tokens the position's liquidity is worth, in the tokens' smallest units
+    pub base_qty: Uint256,
+    pub quote_qty: Uint256,
+    pub current_tick: i32,
+    // True if the current price is within [bid_tick, ask_tick), always true for ambient positions
+    pub in_range: bool,
+    // The ticks between the current price and each edge of the range, negative when the price is outside of that edge.
+    // Ambient positions have no edges and leave these empty
+    pub lower_distance: Option<i32>,
+    pub upper_distance: Option<i32>,
+    // ADDED: The rewards already collected from a concentrated position by harvests, in the tokens' smallest units
+    pub harvested_base: Uint256,
+    pub harvested_quote: Uint256,
+    // ADDED: The unclaimed fees the position is estimated to have earned since its last mint or harvest (see
+    // get_position_apr), along with the seconds of that time the price spent in its range. The apr fields above are
+    // estimated from the same data
+    pub est_base_fees: f64,
+    pub est_quote_fees: f64,
+    pub time_in_range: u64,
+    // ADDED: Profit and impermanent loss against holding the deposited tokens, in the quote token's smallest units
+    // (see position_pnl), the USD values are only given when a --usd-token is configured and reachable
+    pub pnl: f64,
+    pub pnl_usd: Option<f64>,
+    pub impermanent_loss: f64,
+    pub impermanent_loss_usd: Option<f64>,
+}
+
+impl UserPosition {
+    /// Fills the fields describing the position at the pool's current price, which are left at their defaults if
+    /// the price is unknown
+    pub fn add_current_state(&mut self, db: &DB) {
+        let root_price = match get_root_price(db, self.base, self.quote, self.pool_idx) {
+            Some(root_price) => root_price,
+            None => return,
+        };
+        self.current_tick = tick_from_price(root_price * root_price);
+        let ambient = self.position_type == "ambient";
+        let withdrawn = self.knockout_status.as_deref() == Some(KnockoutStatus::Withdrawn.as_str());
+        let (base_qty, quote_qty) = if ambient {
+            let liq = self.ambient_liq.to_f64().unwrap_or_default();
+            (liq * root_price, liq / root_price)
+        } else if withdrawn {
+            (0.0, 0.0)
+        } else {
+            let liq = self.conc_liq.to_f64().unwrap_or_default();
+            concentrated_amounts(liq, root_price, self.bid_tick, self.ask_tick)
+        };
+        self.base_qty = (base_qty as u128).into();
+        self.quote_qty = (quote_qty as u128).into();
+        if ambient {
+            self.in_range = true;
+            self.lower_distance = None;
+            self.upper_distance = None;
+        } else {
+            self.in_range = self.bid_tick <= self.current_tick && self.current_tick < self.ask_tick;
+            self.lower_distance = Some(self.current_tick - self.bid_tick);
+            self.upper_distance = Some(self.ask_tick - self.current_tick);
+        }
+    }
+}
+
+impl From<Position> for UserPosition {
+    fn from(position: Position) -> Self {
+        match position {
+            Ranged(p) => UserPosition {
+                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
+                user: p.user,
+                base: p.base,
+                quote: p.quote,
+                pool_idx: p.pool_idx,
+                bid_tick: p.bid_tick,
+                ask_tick: p.ask_tick,
+                is_bid: p.base_flow > 0,
+                ambient_liq: 0u8.into(),
+                conc_liq: p.liq.into(),
+                position_type: "concentrated".to_string(),
+                harvested_base: p.harvested_base.into(),
+                harvested_quote: p.harvested_quote.into(),
+                ..Default::default()
+            },
+            Ambient(p) => UserPosition {
+                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
+                user: p.user,
+                base: p.base,
+                quote: p.quote,
+                pool_idx: p.pool_idx,
+                is_bid: p.base_flow > 0,
+                conc_liq: 0u8.into(),
+                ambient_liq: p.liq.into(),
+                position_type: "ambient".to_string(),
+                ..Default::default()
+            },
+            Knockout(p) => UserPosition {
+                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
+                user: p.user,
+                base: p.base,
+                quote: p.quote,
+                pool_idx: p.pool_idx,
+                bid_tick: p.bid_tick,
+                ask_tick: p.ask_tick,
+                is_bid: p.is_bid,
+                ambient_liq: 0u8.into(),
+                conc_liq: p.liq.into(),
+                position_type: "knockout".to_string(),
+                knockout_status: Some(p.status.as_str().to_string()),
+                ..Default::default()
+            },
+        }
+    }
 }
 
 /// This struct is used to populate the `strange` field in `UserPosition`, which becomes renamed to `-`
@@ -339,6 +471,7 @@ pub struct StrangeInnerStruct {
 pub async fn user_pool_positions(
     req: web::Query<UserPoolPositionsRequest>,
     db: web::Data<Arc<DB>>,
+    opts: web::Data<Opts>,
 ) -> impl Responder {
     if get_syncing(&db) {
         return HttpResponse::ServiceUnavailable().body("Syncing");
@@ -348,35 +481,11 @@ pub async fn user_pool_positions(
     if positions.is_empty() {
         HttpResponse::NotFound().body("No pool positions found for user");
     }
-    let mut results = vec![];
-    for position in positions {
-        results.push(match position {
-            Ranged(p) => UserPosition {
-                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
-                user: p.user,
-                base: p.base,
-                quote: p.quote,
-                pool_idx: p.pool_idx,
-                bid_tick: p.bid_tick,
-                ask_tick: p.ask_tick,
-                is_bid: p.base_flow > 0,
-                ambient_liq: 0u8.into(),
-                conc_liq: p.liq.into(),
-                ..Default::default()
-            },
-            Ambient(p) => UserPosition {
-                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
-                user: p.user,
-                base: p.base,
-                quote: p.quote,
-                pool_idx: p.pool_idx,
-                is_bid: p.base_flow > 0,
-                conc_liq: 0u8.into(),
-                ambient_liq: p.liq.into(),
-                ..Default::default()
-            },
-        });
-    }
+    let mut usd_prices = UsdPrices::new(&opts);
+    let results: Vec<UserPosition> = positions
+        .into_iter()
+        .map(|p| user_position_report(&db, &mut usd_prices, p))
+        .collect();
     HttpResponse::Ok().json(results)
 }
 
@@ -403,6 +512,7 @@ pub struct UserPositionsRequest {
 pub async fn user_positions(
     req: web::Query<UserPositionsRequest>,
     db: web::Data<Arc<DB>>,
+    opts: web::Data<Opts>,
 ) -> impl Responder {
     if get_syncing(&db) {
         return HttpResponse::ServiceUnavailable().body("Syncing");
@@ -411,38 +521,464 @@ pub async fn user_positions(
     if positions.is_empty() {
         HttpResponse::NotFound().body("No positions found for user");
     }
-    let mut results = vec![];
-    for position in positions {
-        results.push(match position {
-            Ranged(p) => UserPosition {
-                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
-                user: p.user,
-                base: p.base,
-                quote: p.quote,
-                pool_idx: p.pool_idx,
-                bid_tick: p.bid_tick,
-                ask_tick: p.ask_tick,
-                is_bid: p.base_flow > 0,
-                ambient_liq: 0u8.into(),
-                conc_liq: p.liq.into(),
-                ..Default::default()
-            },
-            Ambient(p) => UserPosition {
-                chainId: ALTHEA_MAINNET_EVM_CHAIN_ID.into(),
-                user: p.user,
-                base: p.base,
-                quote: p.quote,
-                pool_idx: p.pool_idx,
-                is_bid: p.base_flow > 0,
-                conc_liq: 0u8.into(),
-                ambient_liq: p.liq.into(),
-                ..Default::default()
-            },
-        });
-    }
+    let mut usd_prices = UsdPrices::new(&opts);
+    let results: Vec<UserPosition> = positions
+        .into_iter()
+        .map(|p| user_position_report(&db, &mut usd_prices, p))
+        .collect();
     HttpResponse::Ok().json(results)
 }
 
+/// A request for a single user position, ambient positions are requested with bidTick and askTick both 0
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct PositionStatsRequest {
+    pub chainId: Option<String>,
+    pub user: Address,
+    pub base: Address,
+    pub quote: Address,
+    pub poolIdx: Uint256,
+    pub bidTick: i32,
+    pub askTick: i32,
+}
+
+/// A single position's UserPosition report along with its accrued rewards, in the tokens' smallest units
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct PositionStatsResp {
+    #[serde(flatten)]
+    pub position: UserPosition,
+    pub base_rewards: Uint256,
+    pub quote_rewards: Uint256,
+}
+
+/// Retrieves the details of a single user position
+///
+/// # Query
+///
+/// A query string with the following parameters:
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+/// - user: The user's address as a EIP 55 string
+/// - base: The address of the base token in the pool (0 if native token) as a EIP 55 string
+/// - quote: The address of the quote token in the pool as a EIP 55 string
+/// - poolIdx: A number representing the pool's template index, needed for identifying the specific pool
+/// - bidTick: The lower tick of the position, 0 for ambient positions
+/// - askTick: The upper tick of the position, 0 for ambient positions
+///
+/// # Response
+///
+/// A json response body containing a UserPosition object, along with these additional fields:
+/// - base_rewards, quote_rewards: The unclaimed fees accrued by the position since its last mint or harvest, queried from
+///   the CrocQuery contract. Rewards already harvested are reported in harvested_base and harvested_quote.
+///   Ambient rewards are compounded into the position, knockout rewards are only known once claimed and are reported as 0
+///
+/// reward_liq is the position's actual reward liquidity, while the apr fields are estimated from the indexed swaps.
+/// A 404 Not Found response is returned if the user has no such position, and a 500 if the rewards could not be queried
+#[get("/position_stats")]
+pub async fn position_stats(
+    req: web::Query<PositionStatsRequest>,
+    db: web::Data<Arc<DB>>,
+    opts: web::Data<Opts>,
+) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let position = match get_active_user_position(
+        &db,
+        req.user,
+        req.base,
+        req.quote,
+        req.poolIdx,
+        req.bidTick,
+        req.askTick,
+    ) {
+        Some(position) => position,
+        None => return HttpResponse::NotFound().body("No such position found for user"),
+    };
+    let root_price = match get_root_price(&db, req.base, req.quote, req.poolIdx) {
+        Some(root_price) => root_price,
+        None => return HttpResponse::NotFound().body("No known price"),
+    };
+
+    // Ranged rewards are held separately from the position, ambient rewards are compounded into its liquidity
+    let web3 = get_althea_web3(&opts, Duration::from_secs(30));
+    let (reward_liq, base_rewards, quote_rewards) = match &position {
+        Ranged(p) => match get_conc_rewards(
+            &web3,
+            opts.query_contract,
+            p.user,
+            p.base,
+            p.quote,
+            p.pool_idx,
+            p.bid_tick,
+            p.ask_tick,
+        )
+        .await
+        {
+            Ok(r) => (r.liq, r.base_qty as f64, r.quote_qty as f64),
+            Err(e) => return HttpResponse::InternalServerError().body(format!("Error: {}", e)),
+        },
+        Ambient(p) => {
+            match get_ambient_tokens(
+                &web3,
+                opts.query_contract,
+                p.user,
+                p.base,
+                p.quote,
+                p.pool_idx,
+            )
+            .await
+            {
+                Ok(t) => {
+                    let liq = t.liq.saturating_sub(p.liq);
+                    (liq, liq as f64 * root_price, liq as f64 / root_price)
+                }
+                Err(e) => return HttpResponse::InternalServerError().body(format!("Error: {}", e)),
+            }
+        }
+        Knockout(_) => (0, 0.0, 0.0),
+    };
+    // The estimated reward_liq is replaced with the position's actual rewards
+    let report = UserPosition {
+        reward_liq: reward_liq.into(),
+        ..user_position_report(&db, &mut UsdPrices::new(&opts), position)
+    };
+    HttpResponse::Ok().json(PositionStatsResp {
+        position: report,
+        base_rewards: (base_rewards as u128).into(),
+        quote_rewards: (quote_rewards as u128).into(),
+    })
+}
+
+// Converts `position` into a UserPosition with its mint times, current state, estimated fees and APR, and PnL filled in
+fn user_position_report(db: &DB, usd_prices: &mut UsdPrices, position: Position) -> UserPosition {
+    let apr = get_position_apr(db, &position, unix_now());
+    let pnl = get_pnl(db, usd_prices, &position, apr.as_ref());
+    let first_mint = get_block_time(db, position.first_block()).unwrap_or_default();
+    let latest_mint = get_block_time(db, position.start_block()).unwrap_or_default();
+    let mut report = UserPosition {
+        time_first_mint: first_mint as i32,
+        latest_update_time: latest_mint as i32,
+        ..UserPosition::from(position)
+    };
+    if let Some(apr) = apr {
+        report.apr = apr.apr;
+        report.apr_duration = apr.duration as f64;
+        report.apr_post_liq = apr.post_liq;
+        report.apr_contributed_liq = apr.contributed_liq;
+        report.reward_liq = (apr.reward_liq as u128).into();
+        report.est_base_fees = apr.base_fees;
+        report.est_quote_fees = apr.quote_fees;
+        report.time_in_range = apr.time_in_range;
+    }
+    if let Some(pnl) = pnl {
+        report.pnl = pnl.pnl;
+        report.pnl_usd = pnl.pnl_usd;
+        report.impermanent_loss = pnl.impermanent_loss;
+        report.impermanent_loss_usd = pnl.impermanent_loss_usd;
+    }
+    report.add_current_state(db);
+    report
+}
+
+// The USD prices of tokens for a single request, routing is only set up once however many positions are valued
+struct UsdPrices<'a> {
+    usd_token: Option<Address>,
+    templates: &'a [u64],
+    pools: Option<Vec<PoolEdge>>,
+    prices: HashMap<Address, Option<f64>>,
+}
+
+impl<'a> UsdPrices<'a> {
+    fn new(opts: &'a Opts) -> Self {
+        UsdPrices {
+            usd_token: opts.usd_token,
+            templates: pool_templates(opts),
+            pools: None,
+            prices: HashMap::new(),
+        }
+    }
+
+    // The USD value of one of `token`'s smallest units (see get_usd_price), None without a --usd-token
+    fn get(&mut self, db: &DB, token: Address) -> Option<f64> {
+        let usd_token = self.usd_token?;
+        if let Some(price) = self.prices.get(&token) {
+            return *price;
+        }
+        let templates = self.templates;
+        let pools = self
+            .pools
+            .get_or_insert_with(|| get_pool_edges(db, templates));
+        let price = get_usd_price(db, pools, token, usd_token);
+        self.prices.insert(token, price);
+        price
+    }
+}
+
+// Compares `position` to holding its deposits, with its harvests and the estimated fees in `apr` as its rewards.
+// Returns None for withdrawn knockouts, which no longer hold anything, or if the pool's price is unknown
+fn get_pnl(
+    db: &DB,
+    usd_prices: &mut UsdPrices,
+    position: &Position,
+    apr: Option<&PositionApr>,
+) -> Option<PositionPnl> {
+    if let Knockout(p) = position {
+        if p.status == KnockoutStatus::Withdrawn {
+            return None;
+        }
+    }
+    let (base, quote, pool_idx) = match position {
+        Ranged(p) => (p.base, p.quote, p.pool_idx),
+        Ambient(p) => (p.base, p.quote, p.pool_idx),
+        Knockout(p) => (p.base, p.quote, p.pool_idx),
+    };
+    let root_price = get_root_price(db, base, quote, pool_idx)?;
+    let (base_flow, quote_flow) = position.flows();
+    let (harvested_base, harvested_quote) = position.harvested();
+    let rewards = apr.map_or((0.0, 0.0), |a| (a.base_fees, a.quote_fees));
+    let rewards = (
+        rewards.0 + harvested_base as f64,
+        rewards.1 + harvested_quote as f64,
+    );
+    let quote_usd = usd_prices.get(db, quote);
+    Some(position_pnl(
+        (base_flow as f64, quote_flow as f64),
+        position.token_amounts(root_price),
+        rewards,
+        root_price * root_price,
+        quote_usd,
+    ))
+}
+
+/// Retrieves the profit and loss of a single user position, compared to holding the tokens deposited into it
+///
+/// # Query
+///
+/// The same query string as /position_stats: chainId (unused), user, base, quote, poolIdx, bidTick and askTick,
+/// with bidTick and askTick both 0 for ambient positions
+///
+/// # Response
+///
+/// A json response body containing a PositionPnl object with these fields, all values are in the quote token's smallest
+/// units at the pool's current price:
+/// - deposited_base, deposited_quote: The net flows into the position (mints less burns)
+/// - current_base, current_quote: The tokens the position's liquidity is currently worth
+/// - rewards_base, rewards_quote: The rewards already harvested from the position, plus the fees it is estimated to
+///   have earned since its last mint or harvest
+/// - hold_value, position_value, rewards_value: The value of holding the deposits, of the liquidity and of the rewards
+/// - pnl: position_value + rewards_value - hold_value
+/// - impermanent_loss: hold_value - position_value, and impermanent_loss_ratio as a fraction of hold_value
+/// - pnl_usd, impermanent_loss_usd: The same values in USD, null unless the quote token can be routed to the --usd-token
+///
+/// A 404 Not Found response is returned if the user has no such position or the pool's price is unknown
+#[get("/position_pnl")]
+pub async fn position_pnl_stats(
+    req: web::Query<PositionStatsRequest>,
+    db: web::Data<Arc<DB>>,
+    opts: web::Data<Opts>,
+) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let position = match get_active_user_position(
+        &db,
+        req.user,
+        req.base,
+        req.quote,
+        req.poolIdx,
+        req.bidTick,
+        req.askTick,
+    ) {
+        Some(position) => position,
+        None => return HttpResponse::NotFound().body("No such position found for user"),
+    };
+    let apr = get_position_apr(&db, &position, unix_now());
+    match get_pnl(&db, &mut UsdPrices::new(&opts), &position, apr.as_ref()) {
+        Some(pnl) => HttpResponse::Ok().json(pnl),
+        None => HttpResponse::NotFound().body("No known price"),
+    }
+}
+
+/// A request for a page of a user's transactions, optionally in a single pool and of certain types
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct UserTxsRequest {
+    pub chainId: Option<String>,
+    pub user: Address,
+    pub base: Option<Address>,
+    pub quote: Option<Address>,
+    pub poolIdx: Option<Uint256>,
+    pub txTypes: Option<String>,
+    pub order: Option<String>,
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// The default and maximum page sizes for transaction feeds
+pub const DEFAULT_TXS_LIMIT: usize = 100;
+pub const MAX_TXS_LIMIT: usize = 1000;
+
+/// A transaction along with the (interpolated) time of its block
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct TxResp {
+    #[serde(flatten)]
+    pub tx: Tx,
+    pub time: Option<u64>,
+}
+
+impl TxResp {
+    fn new(db: &DB, tx: Tx) -> Self {
+        TxResp {
+            time: get_block_time(db, tx.block_height),
+            tx,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UserTxsResp {
+    pub txs: Vec<TxResp>,
+    pub total: usize,
+    pub page: usize,
+    pub limit: usize,
+}
+
+/// Retrieves a user's activity feed: their swaps, position changes, harvests and knockout events merged in chronological order
+///
+/// # Query
+///
+/// A query string with the following parameters:
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+/// - user: The user's address as a EIP 55 string
+/// - base, quote, poolIdx: Optionally limits the feed to a single pool, all three must be given together
+/// - txTypes: An optional comma separated list of the event types to include, any of "swap", "mint_ranged", "burn_ranged",
+///   "harvest", "mint_ambient", "burn_ambient", "mint_knockout", "burn_knockout" and "withdraw_knockout" (default all)
+/// - order: "asc" for oldest first (default) or "desc" for newest first
+/// - page: The page to return, starting at 1 (default 1)
+/// - limit: The number of transactions per page (default 100, max 1000)
+///
+/// # Response
+///
+/// A json response body containing a UserTxsResp object with the requested page of transactions and the total number of
+/// matching transactions. Each transaction has its tx_type, block_height and log index, pool, flows (positive when paid
+/// into the pool), the fields specific to its type (is_buy, bid_tick, ask_tick, is_bid, liq) and the time of its block.
+/// A 400 Bad Request is returned for an unknown tx type or order, or an incomplete pool.
+#[get("/user_txs")]
+pub async fn user_txs(req: web::Query<UserTxsRequest>, db: web::Data<Arc<DB>>) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let pool = match (req.base, req.quote, req.poolIdx) {
+        (Some(base), Some(quote), Some(pool_idx)) => Some((base, quote, pool_idx)),
+        (None, None, None) => None,
+        _ => {
+            return HttpResponse::BadRequest()
+                .body("base, quote and poolIdx must be given together")
+        }
+    };
+    let types = match req.txTypes.as_deref().map(parse_tx_types) {
+        None => TxType::ALL.to_vec(),
+        Some(Ok(types)) => types,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e),
+    };
+    let descending = match req.order.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(order) => return HttpResponse::BadRequest().body(format!("Unknown order {}", order)),
+    };
+    let page = req.page.unwrap_or(1).max(1);
+    let limit = req
+        .limit
+        .unwrap_or(DEFAULT_TXS_LIMIT)
+        .clamp(1, MAX_TXS_LIMIT);
+
+    let (txs, total) = get_user_txs(
+        &db,
+        req.user,
+        pool,
+        &types,
+        descending,
+        (page - 1).saturating_mul(limit),
+        limit,
+    );
+    let txs = txs.into_iter().map(|tx| TxResp::new(&db, tx)).collect();
+    HttpResponse::Ok().json(UserTxsResp {
+        txs,
+        total,
+        page,
+        limit,
+    })
+}
+
+/// A request for a page of a pool's transactions, continuing from a cursor
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct PoolTxsRequest {
+    pub chainId: Option<String>,
+    pub base: Address,
+    pub quote: Address,
+    pub poolIdx: Uint256,
+    pub txTypes: Option<String>,
+    pub before: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PoolTxsResp {
+    pub txs: Vec<TxResp>,
+    // Passed as `before` to get the next page, absent once the pool's history is exhausted
+    pub next_cursor: Option<String>,
+}
+
+/// Retrieves a pool's recent swaps, position changes, harvests and knockout events, newest first
+///
+/// # Query
+///
+/// A query string with the following parameters:
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+/// - base: The address of the base token in the pool (0 if native token) as a EIP 55 string
+/// - quote: The address of the quote token in the pool as a EIP 55 string
+/// - poolIdx: A number representing the pool's template index, needed for identifying the specific pool
+/// - txTypes: An optional comma separated list of the event types to include, with the same names as /user_txs (default all)
+/// - before: An optional cursor from a previous response's next_cursor, only older transactions are returned
+/// - limit: The number of transactions to return (default 100, max 1000)
+///
+/// # Response
+///
+/// A json response body containing a PoolTxsResp object with the transactions (in the same format as /user_txs) and the
+/// cursor for the next page. A 400 Bad Request is returned for an unknown tx type or an invalid cursor.
+#[get("/pool_txs")]
+pub async fn pool_txs(req: web::Query<PoolTxsRequest>, db: web::Data<Arc<DB>>) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let types = match req.txTypes.as_deref().map(parse_tx_types) {
+        None => TxType::ALL.to_vec(),
+        Some(Ok(types)) => types,
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e),
+    };
+    let before = match req.before.as_deref().map(TxCursor::from_str) {
+        None => None,
+        Some(Ok(cursor)) => Some(cursor),
+        Some(Err(e)) => return HttpResponse::BadRequest().body(e),
+    };
+    let limit = req
+        .limit
+        .unwrap_or(DEFAULT_TXS_LIMIT)
+        .clamp(1, MAX_TXS_LIMIT);
+
+    let txs = get_pool_txs(&db, req.base, req.quote, req.poolIdx, &types, before, limit);
+    // A short page means there is nothing older left
+    let next_cursor = match txs.last() {
+        Some(last) if txs.len() == limit => Some(TxCursor::from(last).to_string()),
+        _ => None,
+    };
+    HttpResponse::Ok().json(PoolTxsResp {
+        txs: txs.into_iter().map(|tx| TxResp::new(&db, tx)).collect(),
+        next_cursor,
+    })
+}
+
 /// A request which specifies a pool (and the unused chain id)
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[allow(non_snake_case)]
@@ -509,7 +1045,7 @@ pub struct PoolStatsRequest {
     pub quote: Address,
     pub poolIdx: Uint256,
 
-    // Not used
+    // A unix timestamp, if provided the stats are returned as of that time
     pub histTime: Option<isize>,
 }
 
@@ -529,6 +1065,56 @@ pub struct PoolStatsResp {
     pub quote_fees: f64,
     pub last_price_liq: f64,
     pub last_price_indic: f64,
+
+    // Rolling windows ending at latest_time (or now), price changes are relative e.g. 0.05 for a 5% rise
+    pub base_volume_24h: f64,
+    pub quote_volume_24h: f64,
+    pub base_fees_24h: f64,
+    pub quote_fees_24h: f64,
+    pub price_change_24h: f64,
+    pub base_volume_7d: f64,
+    pub quote_volume_7d: f64,
+    pub base_fees_7d: f64,
+    pub quote_fees_7d: f64,
+    pub price_change_7d: f64,
+}
+
+impl PoolStatsResp {
+    /// The stats of a tracked pool as of `now`, with the latest queried price in place of the tracked swap price
+    pub fn current(db: &DB, pool: TrackedPool, now: u64) -> Self {
+        let (base, quote, pool_idx) = (pool.base, pool.quote, pool.pool_idx);
+        let mut stats = PoolStatsResp::from(pool);
+        if let Some(price) = get_latest_price(db, base, quote, pool_idx) {
+            stats.last_price_swap = price;
+        }
+        stats.add_window_stats(db, base, quote, pool_idx, now);
+        stats
+    }
+
+    /// Fills in the 24h and 7d window stats for the pool as of `now`, measuring price changes against last_price_swap
+    pub fn add_window_stats(
+        &mut self,
+        db: &DB,
+        base: Address,
+        quote: Address,
+        pool_idx: Uint256,
+        now: u64,
+    ) {
+        let price = self.last_price_swap;
+        let day = get_window_stats(db, base, quote, pool_idx, now, DAY_WINDOW, price);
+        let week = get_window_stats(db, base, quote, pool_idx, now, WEEK_WINDOW, price);
+        // TODO: This is a temporary conversion - need a better Uint256->f64 conversion
+        self.base_volume_24h = day.base_volume.to_u128().unwrap().to_f64().unwrap();
+        self.quote_volume_24h = day.quote_volume.to_u128().unwrap().to_f64().unwrap();
+        self.base_fees_24h = day.base_fees;
+        self.quote_fees_24h = day.quote_fees;
+        self.price_change_24h = day.price_change;
+        self.base_volume_7d = week.base_volume.to_u128().unwrap().to_f64().unwrap();
+        self.quote_volume_7d = week.quote_volume.to_u128().unwrap().to_f64().unwrap();
+        self.base_fees_7d = week.base_fees;
+        self.quote_fees_7d = week.quote_fees;
+        self.price_change_7d = week.price_change;
+    }
 }
 
 impl From<TrackedPool> for PoolStatsResp {
@@ -540,7 +1126,31 @@ impl From<TrackedPool> for PoolStatsResp {
             last_price_swap: pool.last_price_swap,
             last_price_indic: pool.last_price_indic,
             last_price_liq: pool.last_price_liq,
-            fee_rate: pool.fee_rate * 0.0001,
+            fee_rate: pool.fee_rate,
+            base_volume: pool.base_volume.to_u128().unwrap().to_f64().unwrap(),
+            quote_volume: pool.quote_volume.to_u128().unwrap().to_f64().unwrap(),
+            base_fees: pool.base_fees,
+            quote_fees: pool.quote_fees,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<PoolSnapshot> for PoolStatsResp {
+    fn from(snapshot: PoolSnapshot) -> Self {
+        Self {
+            // TODO: This is a temporary conversion - need a better Uint256->f64 conversion
+            base_tvl: snapshot.base_tvl.to_i128().unwrap().to_f64().unwrap(),
+            quote_tvl: snapshot.quote_tvl.to_i128().unwrap().to_f64().unwrap(),
+            last_price_swap: snapshot.last_price_swap,
+            last_price_indic: snapshot.last_price_indic,
+            last_price_liq: snapshot.last_price_liq,
+            fee_rate: snapshot.fee_rate,
+            base_volume: snapshot.base_volume.to_u128().unwrap().to_f64().unwrap(),
+            quote_volume: snapshot.quote_volume.to_u128().unwrap().to_f64().unwrap(),
+            base_fees: snapshot.base_fees,
+            quote_fees: snapshot.quote_fees,
+            latest_time: snapshot.time as usize,
             ..Default::default()
         }
     }
@@ -556,12 +1166,14 @@ impl From<TrackedPool> for PoolStatsResp {
 /// - base: The address of the base token in the pool (0 if native token) as a EIP 55 string
 /// - quote: The address of the quote token in the pool as a EIP 55 string
 /// - pool_idx: A number representing the pool's template index, needed for identifying the specific pool
-/// - hist_time: An unused parameter added for compatibility with legacy frontend queries
+/// - histTime: An optional unix timestamp, when provided the stats are taken from the latest pool snapshot at or before that time
 ///
 /// # Response
 ///
-/// A json response body containing a PoolStatsResp object, otherwise a 404 Not Found response if the pool is unknown.
-/// Notably the response includes baseTvl, quoteTvl, lastPriceSwap, and feeRate for the pool (other fields are unused by the backend and included for legacy compatibility)
+/// A json response body containing a PoolStatsResp object, otherwise a 404 Not Found response if the pool is unknown
+/// (or had no recorded state at histTime).
+/// Notably the response includes baseTvl, quoteTvl, lastPriceSwap, and feeRate for the pool along with lifetime and rolling 24h/7d
+/// volume, fees and price change (other fields are unused by the backend and included for legacy compatibility)
 #[get("/pool_stats")]
 pub async fn pool_stats(
     req: web::Query<PoolStatsRequest>,
@@ -570,73 +1182,425 @@ pub async fn pool_stats(
     if get_syncing(&db) {
         return HttpResponse::ServiceUnavailable().body("Syncing");
     }
+    if let Some(hist_time) = req.histTime {
+        let hist_time = u64::try_from(hist_time).unwrap_or_default();
+        return match get_pool_snapshot_at(&db, req.base, req.quote, req.poolIdx, hist_time) {
+            Some(snapshot) => {
+                let mut psr = PoolStatsResp::from(snapshot);
+                psr.add_window_stats(&db, req.base, req.quote, req.poolIdx, hist_time);
+                HttpResponse::Ok().json(psr)
+            }
+            None => HttpResponse::NotFound()
+                .body("No pool state found for base quote poolIdx triple at histTime"),
+        };
+    }
     let pool = get_tracked_pool(&db, req.base, req.quote, req.poolIdx);
 
     match pool {
         Some(pool) => {
-            let mut psr = PoolStatsResp::from(pool);
+            let psr = PoolStatsResp::current(&db, pool, unix_now());
             debug!("Returning pool stats: {:?}", psr);
-            if let Some(q64_price) = get_price(&db, req.base, req.quote, req.poolIdx) {
-                psr.last_price_swap = (q64_price.to_f64().unwrap() / 2.0f64.powf(64.0)).powf(2.0);
-            }
             HttpResponse::Ok().json(psr)
         }
         None => HttpResponse::NotFound().body("No pool found for base quote poolIdx triple"),
     }
 }
 
+/// A request for the stats of every pool (with the unused chain id)
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct AllPoolStatsRequest {
+    pub chainId: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct AllPoolStatsEntry {
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+    pub stats: PoolStatsResp,
+}
+
+/// Retrieves the statistics for every tracked pool
+///
+/// # Query
+///
+/// A query string with the following parameters:
+///
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+///
+/// # Response
+///
+/// A json response body containing a list of AllPoolStatsEntry objects, each holding a pool's identifying triple and the same
+/// PoolStatsResp returned by pool_stats (including the rolling 24h/7d windows)
+#[get("/all_pool_stats")]
+pub async fn all_pool_stats(
+    _req: web::Query<AllPoolStatsRequest>,
+    db: web::Data<Arc<DB>>,
+) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let now = unix_now();
+    let mut results = vec![];
+    for init in get_init_pools(&db) {
+        let pool = match get_tracked_pool(&db, init.base, init.quote, init.pool_idx) {
+            Some(pool) => pool,
+            None => continue,
+        };
+        let stats = PoolStatsResp::current(&db, pool, now);
+        results.push(AllPoolStatsEntry {
+            base: init.base,
+            quote: init.quote,
+            pool_idx: init.pool_idx,
+            stats,
+        });
+    }
+    HttpResponse::Ok().json(results)
+}
+
+/// A request for a page of the pool listing
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DexPairsRequest {
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub page: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+/// The default and maximum page sizes for the pool listing
+pub const DEFAULT_PAIRS_LIMIT: usize = 50;
+pub const MAX_PAIRS_LIMIT: usize = 500;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DexPair {
+    pub base: Address,
+    pub quote: Address,
+    pub pool_idx: Uint256,
+    // Token metadata is absent until the indexer has queried it
+    pub base_token: Option<TokenMetadata>,
+    pub quote_token: Option<TokenMetadata>,
+    pub template: Option<Pool>,
+    pub fee_rate: f64,
+    // The price of the quote token in base token units, unadjusted and adjusted for token decimals
+    pub price: f64,
+    pub price_decimalized: Option<f64>,
+    pub base_tvl: f64,
+    pub quote_tvl: f64,
+    // Total value locked measured in base token units
+    pub tvl: f64,
+    pub base_volume: f64,
+    pub quote_volume: f64,
+    pub base_volume_24h: f64,
+    pub quote_volume_24h: f64,
+    pub base_fees_24h: f64,
+    pub quote_fees_24h: f64,
+    pub price_change_24h: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DexPairsResp {
+    pub pairs: Vec<DexPair>,
+    pub total: usize,
+    pub page: usize,
+    pub limit: usize,
+}
+
+/// Lists every tracked pool with token metadata, price, TVL, volume, fee rate and template
+///
+/// # Query
+///
+/// A query string with the following optional parameters:
+///
+/// - sort: The field to sort by, one of "tvl" (default), "volume", "volume_24h", "price_change_24h" or "fee_rate".
+///   TVL and volume are compared in base token units.
+/// - order: "desc" (default) or "asc"
+/// - page: The page to return, starting at 1. Opts in to pagination
+/// - limit: The number of pools per page (default 50, max 500). Opts in to pagination
+///
+/// # Response
+///
+/// A json response body containing a list of every DexPair, or when page or limit is given a DexPairsResp object with the
+/// requested page of DexPair objects and the total number of pools. A 400 Bad Request if the sort or order is unknown
+#[get("/pairs")]
+pub async fn dex_pairs(req: web::Query<DexPairsRequest>, db: web::Data<Arc<DB>>) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let sort = req.sort.clone().unwrap_or("tvl".to_string());
+    let sort_key: fn(&DexPair) -> f64 = match sort.as_str() {
+        "tvl" => |p| p.tvl,
+        "volume" => |p| p.base_volume + p.quote_volume * p.price,
+        "volume_24h" => |p| p.base_volume_24h + p.quote_volume_24h * p.price,
+        "price_change_24h" => |p| p.price_change_24h,
+        "fee_rate" => |p| p.fee_rate,
+        _ => return HttpResponse::BadRequest().body(format!("Unknown sort {}", sort)),
+    };
+    let descending = match req.order.as_deref() {
+        None | Some("desc") => true,
+        Some("asc") => false,
+        Some(order) => return HttpResponse::BadRequest().body(format!("Unknown order {}", order)),
+    };
+    let now = unix_now();
+    let mut pairs = vec![];
+    for init in get_init_pools(&db) {
+        let pool = match get_tracked_pool(&db, init.base, init.quote, init.pool_idx) {
+            Some(pool) => pool,
+            None => continue,
+        };
+        let stats = PoolStatsResp::current(&db, pool, now);
+
+        let base_token = get_token_metadata(&db, init.base);
+        let quote_token = get_token_metadata(&db, init.quote);
+        let price = stats.last_price_swap;
+        let price_decimalized = match (&base_token, &quote_token) {
+            (Some(b), Some(q)) => Some(price * 10f64.powi(q.decimals as i32 - b.decimals as i32)),
+            _ => None,
+        };
+        pairs.push(DexPair {
+            base: init.base,
+            quote: init.quote,
+            pool_idx: init.pool_idx,
+            base_token,
+            quote_token,
+            template: get_pool_template(&db, init.pool_idx),
+            fee_rate: stats.fee_rate,
+            price,
+            price_decimalized,
+            base_tvl: stats.base_tvl,
+            quote_tvl: stats.quote_tvl,
+            tvl: stats.base_tvl + stats.quote_tvl * price,
+            base_volume: stats.base_volume,
+            quote_volume: stats.quote_volume,
+            base_volume_24h: stats.base_volume_24h,
+            quote_volume_24h: stats.quote_volume_24h,
+            base_fees_24h: stats.base_fees_24h,
+            quote_fees_24h: stats.quote_fees_24h,
+            price_change_24h: stats.price_change_24h,
+        });
+    }
+
+    pairs.sort_by(|a, b| {
+        let ord = sort_key(a).total_cmp(&sort_key(b));
+        if descending {
+            ord.reverse()
+        } else {
+            ord
+        }
+    });
+    // Existing consumers expect every pair in a plain list, so pages are only returned when asked for
+    if req.page.is_none() && req.limit.is_none() {
+        return HttpResponse::Ok().json(pairs);
+    }
+    let page = req.page.unwrap_or(1).max(1);
+    let limit = req
+        .limit
+        .unwrap_or(DEFAULT_PAIRS_LIMIT)
+        .clamp(1, MAX_PAIRS_LIMIT);
+    let total = pairs.len();
+    let pairs = pairs
+        .into_iter()
+        .skip((page - 1) * limit)
+        .take(limit)
+        .collect();
+    HttpResponse::Ok().json(DexPairsResp {
+        pairs,
+        total,
+        page,
+        limit,
+    })
+}
+
+/// A request which specifies a pool and an optional time range
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct PoolHistoryRequest {
+    pub chainId: Option<String>,
+    pub base: Address,
+    pub quote: Address,
+    pub poolIdx: Uint256,
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+/// Retrieves a time series of statistics for a pool, one entry per snapshot interval in which the pool changed
+///
+/// # Query
+///
+/// A query string with the following parameters:
+///
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+/// - base: The address of the base token in the pool (0 if native token) as a EIP 55 string
+/// - quote: The address of the quote token in the pool as a EIP 55 string
+/// - poolIdx: A number representing the pool's template index, needed for identifying the specific pool
+/// - from: An optional unix timestamp to start the series at, defaults to the start of the pool's history
+/// - to: An optional unix timestamp to end the series at, defaults to now
+///
+/// # Response
+///
+/// A json response body containing a list of PoolStatsResp objects ordered oldest first, with latest_time set to the time of each snapshot
+#[get("/pool_history")]
+pub async fn pool_history(
+    req: web::Query<PoolHistoryRequest>,
+    db: web::Data<Arc<DB>>,
+) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let from = req.from.unwrap_or(0);
+    let to = req.to.unwrap_or(u64::MAX);
+    let snapshots = get_pool_snapshots(&db, req.base, req.quote, req.poolIdx, from, to);
+    let history: Vec<PoolStatsResp> = snapshots.into_iter().map(PoolStatsResp::from).collect();
+    HttpResponse::Ok().json(history)
+}
+
+/// A request for the candles of a pool, matching graphcache-go's pool_candles query
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[allow(non_snake_case)]
+pub struct PoolCandlesRequest {
+    pub chainId: Option<String>,
+    pub base: Address,
+    pub quote: Address,
+    pub poolIdx: Uint256,
+    pub period: u64,
+    pub n: Option<u64>,
+    pub time: Option<u64>,
+}
+
+/// The default and maximum number of candles returned by pool_candles
+pub const DEFAULT_CANDLE_COUNT: u64 = 200;
+pub const MAX_CANDLE_COUNT: u64 = 3000;
+
+/// A single candle in the format used by graphcache-go
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+#[allow(non_snake_case)]
+pub struct PoolCandleResp {
+    pub priceOpen: f64,
+    pub priceClose: f64,
+    pub minPrice: f64,
+    pub maxPrice: f64,
+    pub volumeBase: f64,
+    pub volumeQuote: f64,
+    pub tvlBase: f64,
+    pub tvlQuote: f64,
+    pub swapCount: u64,
+    pub period: u64,
+    pub time: u64,
+}
+
+impl From<Candle> for PoolCandleResp {
+    fn from(candle: Candle) -> Self {
+        Self {
+            priceOpen: candle.open,
+            priceClose: candle.close,
+            minPrice: candle.low,
+            maxPrice: candle.high,
+            // TODO: This is a temporary conversion - need a better Uint256->f64 conversion
+            volumeBase: candle.base_volume.to_u128().unwrap().to_f64().unwrap(),
+            volumeQuote: candle.quote_volume.to_u128().unwrap().to_f64().unwrap(),
+            tvlBase: candle.base_tvl.to_u128().unwrap().to_f64().unwrap(),
+            tvlQuote: candle.quote_tvl.to_u128().unwrap().to_f64().unwrap(),
+            swapCount: candle.swaps,
+            period: candle.period,
+            time: candle.time,
+        }
+    }
+}
+
+/// Retrieves open/high/low/close/volume candles for a pool, compatible with graphcache-go's pool_candles endpoint
+///
+/// # Query
+///
+/// A query string with the following parameters:
+///
+/// - chainId: A number representing the id of the chain to use (not used, added for compatibility with legacy frontend queries)
+/// - base: The address of the base token in the pool (0 if native token) as a EIP 55 string
+/// - quote: The address of the quote token in the pool as a EIP 55 string
+/// - poolIdx: A number representing the pool's template index, needed for identifying the specific pool
+/// - period: The width of each candle in seconds, one of 60, 900, 3600, or 86400
+/// - n: The number of candles to return (default 200, max 3000)
+/// - time: An optional unix timestamp of the last candle to return, defaults to now
+///
+/// # Response
+///
+/// A json response body containing a list of PoolCandleResp objects ordered oldest first, or a 400 Bad Request if the period is unsupported.
+/// Periods without any swaps are filled in with a flat candle at the previous close, periods before the pool's first swap are omitted.
+#[get("/pool_candles")]
+pub async fn pool_candles(
+    req: web::Query<PoolCandlesRequest>,
+    db: web::Data<Arc<DB>>,
+) -> impl Responder {
+    if get_syncing(&db) {
+        return HttpResponse::ServiceUnavailable().body("Syncing");
+    }
+    let period = req.period;
+    if !CANDLE_PERIODS.contains(&period) {
+        return HttpResponse::BadRequest().body(format!(
+            "Unsupported candle period {}, expected one of {:?}",
+            period, CANDLE_PERIODS
+        ));
+    }
+    let n = req
+        .n
+        .unwrap_or(DEFAULT_CANDLE_COUNT)
+        .clamp(1, MAX_CANDLE_COUNT);
+    let end = req.time.unwrap_or_else(unix_now);
+    let end = end - (end % period);
+    let start = end.saturating_sub(period * (n - 1));
+
+    let stored = get_candles(&db, req.base, req.quote, req.poolIdx, period, start, end);
+    let mut prev = get_candle_before(&db, req.base, req.quote, req.poolIdx, period, start);
+    let mut stored = stored.into_iter().peekable();
+    let mut candles: Vec<PoolCandleResp> = vec![];
+    let mut time = start;
+    while time <= end {
+        if stored.peek().is_some_and(|c| c.time == time) {
+            let candle = stored.next().unwrap();
+            candles.push(candle.clone().into());
+            prev = Some(candle);
+        } else if let Some(prev) = &prev {
+            // No swaps in this period, carry the previous close forward
+            let flat = Candle {
+                base_tvl: prev.base_tvl,
+                quote_tvl: prev.quote_tvl,
+                ..Candle::new(period, time, prev.close)
+            };
+            candles.push(flat.into());
+        }
+        time += period;
+    }
+    HttpResponse::Ok().json(candles)
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 #[allow(non_snake_case)]
 pub struct SlingshotTradeRequest {
     pub fromAmount: Option<String>,
     pub from: Address,
     pub to: Address,
+    // The accepted slippage as a fraction used for finalAmountOutMin, e.g. 0.005 for 0.5%
+    pub slippage: Option<f64>,
 }
 
-/// This post endpoint fulfils a lot of unnecessary structure to ultimately return just a single field: estimatedOutput
-/// this field indicates the price of req.from in terms of req.to obtained via a simple swap path.
-/// In reality, this endpoint is only ever used to query the native token price in terms of USDC.
+/// The slippage used for finalAmountOutMin when the request does not specify one
+pub const DEFAULT_SLIPPAGE: f64 = 0.005;
+
+/// This post endpoint fulfils a lot of unnecessary structure around a quote for swapping fromAmount of req.from into req.to.
+/// The swap is routed through up to three of the configured pool templates' pools, simulating each hop against the
+/// pool's liquidity curve. The best route is returned in route (one swap per hop, with threeHop set when it takes three)
+/// and its output in estimatedOutput, along with marketImpact (in basis points) and finalAmountOutMin at the requested slippage.
+/// If fromAmount is not given, one whole req.from token is quoted, which the frontend uses to price the native token in USDC.
+/// A trade which no route has the liquidity to fill is not quoted.
 #[post("/trade")]
 pub async fn slingshot_trade(
     req: web::Json<SlingshotTradeRequest>,
     db: web::Data<Arc<DB>>,
     opts: web::Data<Opts>,
 ) -> impl Responder {
-    let req = req.into_inner();
     // Note: Strange part of the request includes "liquidityZone" as a header field
-
-    // We want to return the token price in USDC as the "estimatedOutput" field
-    // The frontend will then divide this value by 10^6, not sure how critical it is we account for that
-
-    let template: Uint256 = if opts.pool_templates.is_empty() {
-        DEFAULT_POOL_TEMPLATES
-    } else {
-        &opts.pool_templates
-    }
-    .first()
-    .map(|x| Uint256::from(*x))
-    .unwrap();
-
-    let mut flip = false;
-    let (base, quote) = if req.from < req.to {
-        (req.from, req.to)
-    } else {
-        flip = true;
-        (req.to, req.from)
-    };
-    let raw_price = get_price(&db, base, quote, template);
-    match raw_price {
-        None => HttpResponse::Ok().body("No known price"),
-        Some(price) => {
-            let mut price: f64 = price.to_f64().unwrap();
-            if flip {
-                price = 1.0 / price;
-            }
-            HttpResponse::Ok().json(SlingshotTradeResponse {
-                estimatedOutput: format!("{:e}", price),
-                ..Default::default()
-            })
-        }
+    match quote_trade(&db, &opts, &req.into_inner()) {
+        Ok(resp) => HttpResponse::Ok().json(resp),
+        Err(e) => HttpResponse::Ok().body(e),
     }
 }
 
@@ -648,43 +1612,83 @@ pub async fn slingshot_trade_get(
     opts: web::Data<Opts>,
     db: web::Data<Arc<rocksdb::DB>>,
 ) -> impl Responder {
-    let req = req.into_inner();
-    // Note: Strange part of the request includes "liquidityZone" as a header field
-
-    // We want to return the token price in USDC as the "estimatedOutput" field
-    // The frontend will then divide this value by 10^6, not sure how critical it is we account for that
-
-    let template: Uint256 = if opts.pool_templates.is_empty() {
-        DEFAULT_POOL_TEMPLATES
-    } else {
-        &opts.pool_templates
+    match quote_trade(&db, &opts, &req.into_inner()) {
+        Ok(resp) => HttpResponse::Ok().json(resp),
+        Err(e) => HttpResponse::Ok().body(e),
     }
-    .first()
-    .map(|x| Uint256::from(*x))
-    .unwrap();
+}
 
-    let mut flip = false;
-    let (base, quote) = if req.from < req.to {
-        (req.from, req.to)
-    } else {
-        flip = true;
-        (req.to, req.from)
-    };
-    let raw_price = get_price(&db, base, quote, template);
-    match raw_price {
-        None => HttpResponse::Ok().body("No known price"),
-        Some(price) => {
-            let mut price: f64 = price.to_f64().unwrap();
-            if flip {
-                price = 1.0 / price;
-            }
-            HttpResponse::Ok().json(SlingshotTradeResponse {
-                estimatedOutput: format!("{:e}", price),
-                ..Default::default()
-            })
+// Quotes the trade along the best route of up to three hops through the configured templates' pools
+fn quote_trade(
+    db: &DB,
+    opts: &Opts,
+    req: &SlingshotTradeRequest,
+) -> Result<SlingshotTradeResponse, String> {
+    // Amounts are in the token's smallest units and often exceed what an f64 holds exactly, so the requested amount is
+    // parsed as an integer and echoed back unchanged, only the simulation works in floating point
+    let from_amount = match &req.fromAmount {
+        Some(amount) => {
+            Uint256::from_str(amount).map_err(|e| format!("Invalid fromAmount: {}", e))?
+        }
+        None => {
+            let decimals = get_token_metadata(db, req.from).map_or(18, |t| t.decimals);
+            10u128
+                .checked_pow(decimals as u32)
+                .map(Uint256::from)
+                .ok_or_else(|| format!("Unsupported decimals {} for {}", decimals, req.from))?
         }
+    };
+    let amount = from_amount
+        .to_string()
+        .parse::<f64>()
+        .map_err(|e| format!("Invalid fromAmount: {}", e))?;
+    let slippage = req.slippage.unwrap_or(DEFAULT_SLIPPAGE);
+
+    let pools = get_pool_edges(db, pool_templates(opts));
+    let route = match best_route(db, &pools, req.from, req.to, amount) {
+        Some(route) => route,
+        None => return Err("No known price".to_string()),
+    };
+    // The best route is only unfilled when no route has enough liquidity, and a swap along it would revert or fill partially
+    if !route.filled {
+        return Err("Not enough liquidity to fill fromAmount".to_string());
     }
+
+    // The whole amount goes through a single route, so each hop is its own leg with the full weight
+    let swaps: Vec<Vec<SlingshotTradeResponseRouteSwap>> = route
+        .hops
+        .iter()
+        .map(|hop| {
+            vec![SlingshotTradeResponseRouteSwap {
+                tokenA: hop.from.to_string(),
+                tokenB: hop.to.to_string(),
+                dex: "ambient".to_string(),
+                pair: format!("{}_{}_{}", hop.pool.base, hop.pool.quote, hop.pool.pool_idx),
+            }]
+        })
+        .collect();
+    Ok(SlingshotTradeResponse {
+        route: SlingshotTradeResponseRoute {
+            weights: vec![100],
+            weightsSum: 100,
+            swaps,
+        },
+        estimatedOutput: format!("{:.0}", route.output),
+        executionPrice: route.execution_price.to_string(),
+        marketImpact: (route.price_impact * 10000.0).round() as i64,
+        finalAmountOutMin: format!("{:.0}", route.output * (1.0 - slippage)),
+        request: SlingshotTradeResponseRequest {
+            fromAmount: from_amount.to_string(),
+            from: req.from.to_string(),
+            to: req.to.to_string(),
+            threeHop: route.hops.len() > 2,
+            ..Default::default()
+        },
+        timestamp: unix_now() as i64,
+        ..Default::default()
+    })
 }
+
 #[derive(Clone, Serialize, Deserialize, Debug, Default)]
 #[allow(non_snake_case)]
 pub struct SlingshotTradeResponse {
@@ -692,6 +1696,8 @@ pub struct SlingshotTradeResponse {
     pub gasEstimateBlockchain: String,
     pub gasEstimateHardcode: String,
     pub estimatedOutput: String,
+    // Output per unit of input over the whole swap, not part of the Slingshot response
+    pub executionPrice: String,
     pub gasEstimate: String,
     pub marketImpact: i64,
     pub request: SlingshotTradeResponseRequest,
@@ -771,3 +1777,20 @@ pub async fn moralis_eth_in_usdc(
         Err(e) => HttpResponse::InternalServerError().body(format!("Error: {}", e)),
     }
 }
+
+// The pool templates the indexer tracks
+fn pool_templates(opts: &Opts) -> &[u64] {
+    if opts.pool_templates.is_empty() {
+        DEFAULT_POOL_TEMPLATES
+    } else {
+        &opts.pool_templates
+    }
+}
+
+// The current unix time in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
@@ -1,5 +1,8 @@
 use crate::althea::cosmos::{
-    delegations::fetch_delegations,
+    cache::CosmosCaches,
+    delegations::{
+        fetch_delegations, fetch_redelegations, fetch_redelegations_or_empty, StakingOverview,
+    },
     governance::{fetch_proposals, fetch_proposals_filtered},
     staking::fetch_staking_info,
     validators::{fetch_validator_by_address, fetch_validators_filtered},
@@ -14,7 +17,6 @@ use deep_space::Address as CosmosAddress;
 use deep_space::Contact;
 use log::error;
 use log::info;
-use rocksdb::DB;
 use serde::Deserialize;
 
 use std::sync::Arc;
@@ -50,7 +52,7 @@ pub struct ValidatorQuery {
 #[get("/validators")]
 pub async fn get_validators(
     query: web::Query<ValidatorQuery>,
-    db: web::Data<Arc<DB>>,
+    caches: web::Data<Arc<CosmosCaches>>,
     contact: web::Data<Arc<Contact>>,
 ) -> impl Responder {
     info!(
@@ -60,7 +62,7 @@ pub async fn get_validators(
 
     // If operator_address is provided, fetch specific validator
     if let Some(addr) = &query.operator_address {
-        match fetch_validator_by_address(&db, &contact, addr).await {
+        match fetch_validator_by_address(&caches, &contact, addr).await {
             Ok(Some(validator)) => return HttpResponse::Ok().json(vec![validator]),
             Ok(None) => return HttpResponse::NotFound().body("Validator not found"),
             Err(e) => {
@@ -71,7 +73,7 @@ pub async fn get_validators(
     }
 
     // Otherwise use existing logic for filtered validators
-    match fetch_validators_filtered(&db, &contact, query.active).await {
+    match fetch_validators_filtered(&caches, &contact, query.active).await {
         Ok(validators) => {
             if validators.is_empty() {
                 HttpResponse::NotFound().body("No validators found")
@@ -129,7 +131,7 @@ pub struct ProposalQuery {
 #[get("/proposals")]
 pub async fn get_proposals(
     query: web::Query<ProposalQuery>,
-    db: web::Data<Arc<DB>>,
+    caches: web::Data<Arc<CosmosCaches>>,
     contact: web::Data<Arc<Contact>>,
 ) -> impl Responder {
     info!(
@@ -138,7 +140,7 @@ pub async fn get_proposals(
     );
 
     let result = if query.status.is_some() {
-        match fetch_proposals(&db, &contact).await {
+        match fetch_proposals(&caches, &contact).await {
             Ok(proposals) => Ok(proposals
                 .into_iter()
                 .filter(|p| p.status_value == query.status.unwrap())
@@ -146,7 +148,7 @@ pub async fn get_proposals(
             Err(e) => Err(e),
         }
     } else {
-        fetch_proposals_filtered(&db, &contact, query.active).await
+        fetch_proposals_filtered(&caches, &contact, query.active).await
     };
 
     match result {
@@ -180,13 +182,25 @@ pub struct DelegatorQuery {
 /// Returns a JSON array of delegation information including validator addresses
 /// and delegation amounts. If no delegations are found, returns a 404 Not Found response.
 ///
+/// `rewards` holds the rewards owed by each validator under `rewards` and their sum under `total`. Rewards can be paid
+/// in several denoms, so each is a list of coins with the fields:
+///
+/// - `denom`: The coin's base denom
+/// - `amount`: For aalthea, the amount as this endpoint has always reported it, the chain's 18 decimal place integer
+///   encoding of the reward divided by 10^14 and rounded down, with 18 zero decimal places. Kept for older clients, for
+///   every other denom it is the same as `base_amount`
+/// - `base_amount`: The amount in base units, with all 18 decimal places of the chain's reward accounting
+/// - `display_denom`, `decimals`: The denom the amount is usually shown in and its exponent, from the bank denom
+///   metadata, or null for denoms without metadata
+/// - `display_amount`: The amount in the display denom, or null for denoms without metadata
+///
 /// # Example
 ///
 /// - `GET /delegations?address=althea1...` - Returns all delegations for the specified address
 #[get("/delegations")]
 pub async fn get_delegations(
     query: web::Query<DelegatorQuery>,
-    db: web::Data<Arc<DB>>,
+    caches: web::Data<Arc<CosmosCaches>>,
     contact: web::Data<Arc<Contact>>,
 ) -> impl Responder {
     info!("Querying delegations for address: {}", query.address);
@@ -199,7 +213,7 @@ pub async fn get_delegations(
         }
     };
 
-    match fetch_delegations(&db, &contact, delegator_address).await {
+    match fetch_delegations(&caches, &contact, delegator_address).await {
         Ok(response) => {
             if response.delegations.is_empty() && response.unbonding_delegations.is_none() {
                 HttpResponse::Ok().json(serde_json::json!({
@@ -226,6 +240,103 @@ pub async fn get_delegations(
     }
 }
 
+/// Retrieves the pending redelegations of a specific address
+///
+/// # Query Parameters
+///
+/// - `address`: The delegator's address to query redelegations for
+///
+/// # Response
+///
+/// Returns a JSON array with one object per source and destination validator pair, with the fields:
+///
+/// - `validator_src_address`, `validator_dst_address`: The validators the tokens were moved from and to
+/// - `entries`: The redelegations which have not completed, each with `creation_height`, `completion_time` (RFC 3339),
+///   `initial_balance` and `balance` in the bond denom's base units, and `shares_dst`, the destination validator shares
+///   received
+///
+/// Tokens in an entry can not be redelegated again until its completion time. Delegators without redelegations get an
+/// empty array.
+///
+/// # Example
+///
+/// - `GET /redelegations?address=althea1...`
+#[get("/redelegations")]
+pub async fn get_redelegations(
+    query: web::Query<DelegatorQuery>,
+    caches: web::Data<Arc<CosmosCaches>>,
+    contact: web::Data<Arc<Contact>>,
+) -> impl Responder {
+    info!("Querying redelegations for address: {}", query.address);
+
+    let delegator_address = match CosmosAddress::from_bech32(query.address.clone()) {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("Invalid address format: {}", e);
+            return HttpResponse::BadRequest().body("Invalid address format");
+        }
+    };
+
+    match fetch_redelegations(&caches, &contact, delegator_address).await {
+        Ok(redelegations) => HttpResponse::Ok().json(redelegations),
+        Err(e) => {
+            error!("Error fetching redelegations: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Retrieves everything a specific address has staked in one response
+///
+/// # Query Parameters
+///
+/// - `address`: The delegator's address
+///
+/// # Response
+///
+/// Returns a JSON object with the fields:
+///
+/// - `delegations`: The active delegations, as returned by /delegations
+/// - `unbondings`: One object per validator being unbonded from, with its `validator_address` and `entries`, each with
+///   `creation_height`, `completion_time` (RFC 3339), and `initial_balance` and `balance` in the bond denom's base units
+/// - `redelegations`: The pending redelegations, as returned by /redelegations
+/// - `rewards`: The rewards which can be claimed now, per validator and in total, as returned by /delegations
+///
+/// Unlike /delegations, a delegator without any stake gets empty arrays rather than nulls. If the redelegations can not be
+/// fetched the rest of the overview is still returned, with an empty `redelegations`.
+///
+/// # Example
+///
+/// - `GET /staking_overview?address=althea1...`
+#[get("/staking_overview")]
+pub async fn get_staking_overview(
+    query: web::Query<DelegatorQuery>,
+    caches: web::Data<Arc<CosmosCaches>>,
+    contact: web::Data<Arc<Contact>>,
+) -> impl Responder {
+    info!("Querying staking overview for address: {}", query.address);
+
+    let delegator_address = match CosmosAddress::from_bech32(query.address.clone()) {
+        Ok(addr) => addr,
+        Err(e) => {
+            error!("Invalid address format: {}", e);
+            return HttpResponse::BadRequest().body("Invalid address format");
+        }
+    };
+
+    match fetch_delegations(&caches, &contact, delegator_address).await {
+        Ok(response) => {
+            let redelegations =
+                fetch_redelegations_or_empty(&caches, &contact, delegator_address).await;
+            HttpResponse::Ok().json(StakingOverview::new(response, redelegations))
+        }
+        Err(e) => {
+            error!("Error fetching staking overview: {}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
 /// Retrieves the apr from the Cosmos staking layer
 ///
 /// # Query
@@ -239,11 +350,11 @@ pub async fn get_delegations(
 /// - `apr`: The current annual percentage rate for staking rewards
 #[get("/apr")]
 pub async fn get_staking_info(
-    db: web::Data<Arc<DB>>,
+    caches: web::Data<Arc<CosmosCaches>>,
     contact: web::Data<Arc<Contact>>,
 ) -> impl Responder {
     info!("Fetching staking info");
-    match fetch_staking_info(&db, &contact).await {
+    match fetch_staking_info(&caches, &contact).await {
         Ok(info) => HttpResponse::Ok().json(info),
         Err(e) => {
             error!("Failed to fetch staking info: {}", e);
@@ -251,3 +362,26 @@ pub async fn get_staking_info(
         }
     }
 }
+
+/// Reports how the in-memory caches of Cosmos RPC responses are being used
+///
+/// # Query
+///
+/// A simple GET request
+///
+/// # Response
+///
+/// Returns a JSON array with one object per cache (validators, proposals, staking_info, delegations, redelegations,
+/// active_delegators, the delegators whose delegations are refreshed in the background, and denom_metadata) with the
+/// fields:
+///
+/// - `name`: The cache's name
+/// - `entries`: The number of entries currently held, including expired entries not yet evicted
+/// - `capacity`: The most entries the cache holds before evicting the least recently used
+/// - `hits`, `misses`: The lookups answered from the cache and those which went to the RPC
+/// - `evictions`: The entries removed to make room for new ones
+/// - `expirations`: The lookups which found an entry past its time to live
+#[get("/cosmos_cache_stats")]
+pub async fn cosmos_cache_stats(caches: web::Data<Arc<CosmosCaches>>) -> impl Responder {
+    HttpResponse::Ok().json(caches.stats())
+}
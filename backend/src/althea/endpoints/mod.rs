@@ -2,11 +2,34 @@ use actix_web::{get, web, HttpResponse, Responder};
 use clarity::Uint256;
 use serde::{Deserialize, Serialize};
 
+use crate::althea::database::storage::{Direction, Records, Storage, StorageBatch, StorageError};
 use crate::Opts;
 
 pub mod ambient;
 pub mod cosmos;
 
+// Lets handlers pass their shared database straight to the database functions
+impl<S: Storage + ?Sized> Storage for web::Data<S> {
+    fn get(&self, family: &str, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        (**self).get(family, key)
+    }
+    fn put(&self, family: &str, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        (**self).put(family, key, value)
+    }
+    fn delete(&self, family: &str, key: &[u8]) -> Result<(), StorageError> {
+        (**self).delete(family, key)
+    }
+    fn prefix_scan<'a>(&'a self, family: &str, prefix: &[u8]) -> Records<'a> {
+        (**self).prefix_scan(family, prefix)
+    }
+    fn scan_from<'a>(&'a self, family: &str, start: &[u8], direction: Direction) -> Records<'a> {
+        (**self).scan_from(family, start, direction)
+    }
+    fn write(&self, batch: StorageBatch) -> Result<(), StorageError> {
+        (**self).write(batch)
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct FrontendConstants {
     pub dex: String,
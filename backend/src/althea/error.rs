@@ -8,12 +8,14 @@ pub enum AltheaError {
     EthereumRestError(Web3Error),
     ClarityError(ClarityError),
     InvalidEventLogError(String),
+    InvalidResponseError(String),
 }
 impl fmt::Display for AltheaError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             AltheaError::EthereumRestError(val) => write!(f, "Web3 error: {}", val),
             AltheaError::InvalidEventLogError(val) => write!(f, "Invalid ethereum logs: {}", val),
+            AltheaError::InvalidResponseError(val) => write!(f, "Invalid response: {}", val),
             AltheaError::ClarityError(error) => write!(f, "Clarity error: {}", error),
         }
     }
@@ -4,9 +4,11 @@ use actix_web::rt::System;
 use actix_web::web::{self};
 use ambient::pools::InitPoolEvent;
 use ambient::{
-    initialize_templates, possible_pools, query_latest, search_for_pool_events, track_pools,
+    backfill_block_times, initialize_templates, possible_pools, query_latest, query_token_metadata,
+    record_block_times, search_for_pool_events, track_pools,
 };
 use clarity::{Address, Uint256};
+use cosmos::cache::CosmosCaches;
 use cosmos::delegations::start_delegation_cache_refresh_task;
 use cosmos::governance::start_proposal_cache_refresh_task;
 use cosmos::staking::start_staking_info_cache_refresh_task;
@@ -69,22 +71,21 @@ pub fn get_mainnet_web3(opts: &Opts, timeout: Duration) -> Web3 {
     Web3::new(&opts.mainnet_rpc_url, timeout)
 }
 
-pub fn start_ambient_indexer(opts: Opts, db: Arc<rocksdb::DB>) {
+pub fn start_ambient_indexer(opts: Opts, db: Arc<rocksdb::DB>, caches: Arc<CosmosCaches>) {
     let tokens = get_tokens(&opts);
     let templates = get_templates(&opts);
 
     // Start cache refresh tasks
     let contact = get_althea_contact(&opts, TIMEOUT);
-    start_validator_cache_refresh_task(db.clone(), contact.clone());
-    start_proposal_cache_refresh_task(db.clone(), contact.clone());
-    start_delegation_cache_refresh_task(db.clone(), contact.clone());
-    start_staking_info_cache_refresh_task(db.clone(), contact.clone());
-
-    if opts.reindex {
-        info!("Reindexing database");
-        reset_all_pool_indexes(&db);
-        track_pools(&db).expect("Error reindexing pools");
-    }
+    start_validator_cache_refresh_task(caches.clone(), contact.clone());
+    start_proposal_cache_refresh_task(caches.clone(), contact.clone());
+    start_delegation_cache_refresh_task(
+        caches.clone(),
+        contact.clone(),
+        Duration::from_secs(opts.delegation_refresh_secs),
+        opts.delegation_refresh_concurrency,
+    );
+    start_staking_info_cache_refresh_task(caches, contact.clone());
 
     thread::spawn(move || {
         let db = db.clone();
@@ -96,6 +97,20 @@ pub fn start_ambient_indexer(opts: Opts, db: Arc<rocksdb::DB>) {
                 .await
                 .unwrap();
 
+            // Pools are retracked once the block times are known, so the backfilled blocks get their history and candles
+            let backfilled = match backfill_block_times(&db, &web3).await {
+                Ok(backfilled) => backfilled,
+                Err(e) => {
+                    error!("Error backfilling block times: {}", e);
+                    0
+                }
+            };
+            if opts.reindex || backfilled > 0 {
+                info!("Reindexing database");
+                reset_all_pool_indexes(&db);
+                track_pools(&db).expect("Error reindexing pools");
+            }
+
             loop {
                 let start_block =
                     get_latest_searched_block(&db).unwrap_or(DEFAULT_START_SEARCH_BLOCK.into());
@@ -130,6 +145,9 @@ pub fn start_ambient_indexer(opts: Opts, db: Arc<rocksdb::DB>) {
                 {
                     error!("Error searching for positions: {}", e);
                 }
+                if let Err(e) = record_block_times(&db, &web3, &[start_block, end_block]).await {
+                    error!("Error recording block times: {}", e);
+                }
                 save_latest_searched_block(&db, end_block);
 
                 if end_block != start_block {
@@ -141,12 +159,13 @@ pub fn start_ambient_indexer(opts: Opts, db: Arc<rocksdb::DB>) {
                         .collect::<Vec<_>>();
                     let pools = potential_pools
                         .into_iter()
-                        .chain(discovered_pools)
+                        .chain(discovered_pools.clone())
                         .unique()
                         .collect::<Vec<_>>();
                     if let Err(e) = query_latest(&db, &web3, opts.query_contract, &pools).await {
                         error!("Error querying latest: {}", e);
                     }
+                    query_token_metadata(&db, &web3, &discovered_pools).await;
                 }
 
                 let tracking = track_pools(&db);
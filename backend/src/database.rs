@@ -12,6 +12,11 @@ use crate::althea::ambient::positions::HarvestEvent;
 use crate::althea::ambient::positions::MintAmbientEvent;
 use crate::althea::ambient::positions::MintRangedEvent;
 use crate::althea::ambient::swap::SwapEvent;
+use crate::althea::database::column_families::cf;
+use crate::althea::database::column_families::existing_family_descriptors;
+use crate::althea::database::column_families::family_for_key;
+use crate::althea::database::column_families::DbMemory;
+use crate::althea::database::column_families::FAMILIES;
 use crate::althea::database::curve::LATEST_CURVE_KEY;
 use crate::althea::database::pools::Pool;
 use crate::althea::database::pools::INIT_POOL_PREFIX;
@@ -20,21 +25,37 @@ use crate::althea::database::pools::REVISION_PREFIX;
 use crate::althea::database::pools::SWAP_PREFIX;
 use crate::althea::database::positions::ambient::BURN_AMBIENT_PREFIX;
 use crate::althea::database::positions::ambient::MINT_AMBIENT_PREFIX;
+use crate::althea::database::positions::index::ACTIVE_POSITIONS_PREFIX;
 use crate::althea::database::positions::knockout::BURN_KNOCKOUT_PREFIX;
 use crate::althea::database::positions::knockout::MINT_KNOCKOUT_PREFIX;
 use crate::althea::database::positions::knockout::WITHDRAW_KNOCKOUT_PREFIX;
 use crate::althea::database::positions::ranged::BURN_RANGED_PREFIX;
 use crate::althea::database::positions::ranged::HARVEST_PREFIX;
 use crate::althea::database::positions::ranged::MINT_RANGED_PREFIX;
+use crate::althea::database::positions::PoolPositions;
+use crate::althea::database::schema::deserialize_record;
+use crate::althea::database::schema::migrate;
+use crate::althea::database::storage::Storage;
+use crate::althea::database::tokens::TokenMetadata;
+use crate::althea::database::tokens::TOKEN_METADATA_PREFIX;
+use crate::althea::database::tracking::candles::Candle;
+use crate::althea::database::tracking::candles::CANDLE_PREFIX;
+use crate::althea::database::tracking::history::PoolSnapshot;
+use crate::althea::database::tracking::history::POOL_SNAPSHOT_PREFIX;
 use crate::althea::database::tracking::DirtyPoolTracker;
 use crate::althea::database::tracking::TrackedPool;
 use crate::althea::database::tracking::DIRTY_POOL_PREFIX;
 use crate::althea::database::tracking::TRACKED_POOL_PREFIX;
+use crate::althea::database::transactions::Tx;
+use crate::althea::database::transactions::POOL_TX_PREFIX;
 use crate::Opts;
 use log::info;
+use rocksdb::IteratorMode;
 use rocksdb::Options;
+use rocksdb::WriteBatch;
 use rocksdb::DB;
 use std::borrow::Borrow;
+use std::path::Path;
 use std::time::Instant;
 
 /// Creates a new RocksDB database in the current directory
@@ -46,7 +67,36 @@ pub fn open_database(opts: Opts) -> DB {
     db_options.set_max_background_jobs(num_cpus / 2);
     db_options.set_max_subcompactions(16);
     db_options.create_if_missing(true);
-    let db = DB::open(&db_options, opts.database_path).expect("Failed to open database");
+    db_options.create_missing_column_families(true);
+    let path = Path::new(&opts.database_path);
+    let memory = DbMemory::new(opts.db_memory_mb);
+    let db = DB::open_cf_descriptors(
+        &db_options,
+        path,
+        existing_family_descriptors(path, &memory),
+    )
+    .expect("Failed to open database");
+    let moved = migrate_to_column_families(&db, opts.migrate_dry_run);
+    let report = migrate(&db, opts.migrate_dry_run);
+    if opts.migrate_dry_run {
+        info!(
+            "Migration dry run complete: {} records would move families, schema {} to {} would change {} of {} records, {} unreadable. Halting",
+            moved,
+            report.from,
+            report.to,
+            report.changed(),
+            report.checked,
+            report.unreadable
+        );
+        std::process::exit(0);
+    } else if opts.migrate_and_halt {
+        compact_db(&db);
+        info!("Database migration complete, halting");
+        std::process::exit(0);
+    } else if moved > 0 || report.changed() > 0 {
+        // The moved and rewritten keys leave tombstones behind
+        compact_db(&db);
+    }
     if opts.compact {
         compact_db(&db);
     } else if opts.compact_and_halt {
@@ -63,47 +113,93 @@ pub fn compact_db(db: &DB) {
     let start = Instant::now();
     info!("Starting DB compaction");
     let typed_none: Option<[u8; 1]> = None;
-    db.compact_range(typed_none, typed_none);
+    for family in FAMILIES.iter() {
+        db.compact_range_cf(cf(db, family.name), typed_none, typed_none);
+    }
     info!("DB compaction took: {:?}", start.elapsed());
 }
 
-// Clears invalid entries in the database by attempting to deserialize every known entry
-pub fn clear_invalid_entries(db: &rocksdb::DB) -> bool {
+/// How many keys are moved per write batch when migrating
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+/// Moves records left in the default column family by versions which stored everything there into the family
+/// their key prefix names, returning the number of records moved. Records which belong to no family stay where they are.
+/// Does nothing on databases which are already split, and in a dry run only counts the records
+pub fn migrate_to_column_families(db: &DB, dry_run: bool) -> usize {
+    let start = Instant::now();
+    let mut moved = 0;
+    let mut batch = WriteBatch::default();
+    for (k, v) in db.iterator(IteratorMode::Start).flatten() {
+        let family = family_for_key(&k);
+        let family = match family {
+            Some(family) => family,
+            None => continue,
+        };
+        if moved == 0 && !dry_run {
+            info!("Moving records from the default column family into their own families");
+        }
+        batch.put_cf(cf(db, family), &k, &v);
+        batch.delete(&k);
+        moved += 1;
+        if batch.len() >= MIGRATION_BATCH_SIZE * 2 {
+            let full = std::mem::take(&mut batch);
+            if !dry_run {
+                db.write(full).unwrap();
+                info!("Moved {} records", moved);
+            }
+        }
+    }
+    if !batch.is_empty() && !dry_run {
+        db.write(batch).unwrap();
+    }
+    if moved > 0 && !dry_run {
+        info!("Moved {} records in {:?}", moved, start.elapsed());
+    }
+    moved
+}
+
+// Clears invalid entries in the database by attempting to deserialize every known entry. Records written in an older
+// layout are upgraded by the schema migrations when the database is opened, so this only deletes records which are
+// corrupt, and any deletion triggers a resync
+pub fn clear_invalid_entries(db: &impl Storage) -> bool {
     let mut deleted = false;
-    deleted |= clear_invalid::<CurveState>(db, LATEST_CURVE_KEY.as_bytes());
-    deleted |= clear_invalid::<InitPoolEvent>(db, INIT_POOL_PREFIX.as_bytes());
-    deleted |= clear_invalid::<Pool>(db, POOL_TEMPLATE_PREFIX.as_bytes());
-    deleted |= clear_invalid::<SwapEvent>(db, SWAP_PREFIX.as_bytes());
-    deleted |= clear_invalid::<PoolRevisionEvent>(db, REVISION_PREFIX.as_bytes());
-    deleted |= clear_invalid::<MintAmbientEvent>(db, MINT_AMBIENT_PREFIX.as_bytes());
-    deleted |= clear_invalid::<BurnAmbientEvent>(db, BURN_AMBIENT_PREFIX.as_bytes());
-    deleted |= clear_invalid::<MintRangedEvent>(db, MINT_RANGED_PREFIX.as_bytes());
-    deleted |= clear_invalid::<BurnRangedEvent>(db, BURN_RANGED_PREFIX.as_bytes());
-    deleted |= clear_invalid::<HarvestEvent>(db, HARVEST_PREFIX.as_bytes());
-    deleted |= clear_invalid::<MintKnockoutEvent>(db, MINT_KNOCKOUT_PREFIX.as_bytes());
-    deleted |= clear_invalid::<BurnKnockoutEvent>(db, BURN_KNOCKOUT_PREFIX.as_bytes());
-    deleted |= clear_invalid::<WithdrawKnockoutEvent>(db, WITHDRAW_KNOCKOUT_PREFIX.as_bytes());
-    deleted |= clear_invalid::<DirtyPoolTracker>(db, DIRTY_POOL_PREFIX.as_bytes());
-    deleted |= clear_invalid::<TrackedPool>(db, TRACKED_POOL_PREFIX.as_bytes());
+    deleted |= clear_invalid::<CurveState>(db, LATEST_CURVE_KEY);
+    deleted |= clear_invalid::<InitPoolEvent>(db, INIT_POOL_PREFIX);
+    deleted |= clear_invalid::<Pool>(db, POOL_TEMPLATE_PREFIX);
+    deleted |= clear_invalid::<SwapEvent>(db, SWAP_PREFIX);
+    deleted |= clear_invalid::<PoolRevisionEvent>(db, REVISION_PREFIX);
+    deleted |= clear_invalid::<MintAmbientEvent>(db, MINT_AMBIENT_PREFIX);
+    deleted |= clear_invalid::<BurnAmbientEvent>(db, BURN_AMBIENT_PREFIX);
+    deleted |= clear_invalid::<MintRangedEvent>(db, MINT_RANGED_PREFIX);
+    deleted |= clear_invalid::<BurnRangedEvent>(db, BURN_RANGED_PREFIX);
+    deleted |= clear_invalid::<HarvestEvent>(db, HARVEST_PREFIX);
+    deleted |= clear_invalid::<MintKnockoutEvent>(db, MINT_KNOCKOUT_PREFIX);
+    deleted |= clear_invalid::<BurnKnockoutEvent>(db, BURN_KNOCKOUT_PREFIX);
+    deleted |= clear_invalid::<WithdrawKnockoutEvent>(db, WITHDRAW_KNOCKOUT_PREFIX);
+    deleted |= clear_invalid::<DirtyPoolTracker>(db, DIRTY_POOL_PREFIX);
+    deleted |= clear_invalid::<TrackedPool>(db, TRACKED_POOL_PREFIX);
+    deleted |= clear_invalid::<PoolSnapshot>(db, POOL_SNAPSHOT_PREFIX);
+    deleted |= clear_invalid::<Candle>(db, CANDLE_PREFIX);
+    deleted |= clear_invalid::<TokenMetadata>(db, TOKEN_METADATA_PREFIX);
+    deleted |= clear_invalid::<Tx>(db, POOL_TX_PREFIX);
+    deleted |= clear_invalid::<PoolPositions>(db, ACTIVE_POSITIONS_PREFIX);
 
     deleted
 }
 
-fn clear_invalid<T>(db: &rocksdb::DB, prefix: &[u8]) -> bool
+/// Deletes every record in the family named `family` which does not deserialize as T
+fn clear_invalid<T>(db: &impl Storage, family: &str) -> bool
 where
     T: for<'a> serde::de::Deserialize<'a>,
 {
     let mut deleted = false;
-    let iter = db.prefix_iterator(prefix);
+    let iter = db.prefix_scan(family, &[]);
     for (k, v) in iter.flatten() {
-        if !k.starts_with(prefix) {
-            break;
-        }
         let ptr = v.borrow();
-        let error: bool = bincode::deserialize::<T>(ptr).is_err();
+        let error: bool = deserialize_record::<T>(ptr).is_err();
         if error {
             deleted = true;
-            db.delete(k).unwrap();
+            db.delete(family, &k).unwrap();
         }
     }
 
@@ -112,11 +208,42 @@ where
 
 #[test]
 fn test_clear_invalid() {
+    use crate::althea::database::schema::serialize_record;
+    use crate::althea::database::storage::MemoryStorage;
+    let db = MemoryStorage::new();
+    let k = "burn-ambient_test1";
+    let v = serialize_record(&"test").unwrap();
+    db.put(BURN_AMBIENT_PREFIX, k.as_bytes(), &v).unwrap();
+    let valid = "burn-ambient_test2";
+    let v = serialize_record(&BurnAmbientEvent::default()).unwrap();
+    db.put(BURN_AMBIENT_PREFIX, valid.as_bytes(), &v).unwrap();
+
+    assert!(clear_invalid::<BurnAmbientEvent>(&db, BURN_AMBIENT_PREFIX));
+    assert_eq!(db.get(BURN_AMBIENT_PREFIX, k.as_bytes()).unwrap(), None);
+    assert!(db
+        .get(BURN_AMBIENT_PREFIX, valid.as_bytes())
+        .unwrap()
+        .is_some());
+    assert!(!clear_invalid::<BurnAmbientEvent>(&db, BURN_AMBIENT_PREFIX));
+}
+
+// Options opening the database at `path` without any of the one-off modes
+#[cfg(test)]
+fn test_opts(path: &Path) -> Opts {
     use clarity::Address;
-    let db = open_database(Opts {
-        database_path: "test_db".to_string(),
+    Opts {
+        database_path: path.to_str().unwrap().to_string(),
+        db_memory_mb: 64,
+        delegation_refresh_secs: 4,
+        delegation_refresh_concurrency: 8,
         compact: false,
         compact_and_halt: false,
+        migrate_and_halt: false,
+        migrate_dry_run: false,
+        bootstrap_snapshot: None,
+        export_snapshot: None,
+        snapshot_on_signal: None,
+        check_positions: false,
         reindex: false,
         halt_after_indexing: false,
         dex_contract: Address::default(),
@@ -124,6 +251,7 @@ fn test_clear_invalid() {
         multicall_contract: Address::default(),
         pool_tokens: Vec::new(),
         pool_templates: Vec::new(),
+        usd_token: None,
         address: "0.0.0.0".parse().unwrap(),
         port: 0,
         https: false,
@@ -132,21 +260,204 @@ fn test_clear_invalid() {
         evm_rpc_url: String::new(),
         cosmos_rpc_url: String::new(),
         mainnet_rpc_url: String::new(),
-    });
-    let prefix = "test";
-    let k = "test1";
-    let v = bincode::serialize(&"test").unwrap();
-    db.put(k.as_bytes(), v).unwrap();
-    clear_invalid::<BurnAmbientEvent>(&db, prefix.as_bytes());
-    let value = db.get(k);
-    match value {
-        Ok(value) => match value {
-            Some(value) => {
-                println!("Expected None, got {:?}", value);
-                panic!("Key not deleted");
-            }
-            None => println!("Value is None"),
-        },
-        Err(_) => println!("Key deleted!"),
     }
 }
+
+#[test]
+fn test_migrate_to_column_families() {
+    let path = std::env::temp_dir().join(format!("althea-link-migrate-{}", std::process::id()));
+    let opts = test_opts(&path);
+    let mut db = open_database(opts.clone());
+
+    // Records written to the default family by older versions are moved into their own family
+    let k = "swap_test1";
+    DB::put(&db, k.as_bytes(), [1]).unwrap();
+    assert_eq!(migrate_to_column_families(&db, true), 1);
+    assert!(DB::get(&db, k).unwrap().is_some());
+    assert_eq!(migrate_to_column_families(&db, false), 1);
+    assert!(DB::get(&db, k).unwrap().is_none());
+    assert_eq!(db.get_cf(cf(&db, SWAP_PREFIX), k).unwrap(), Some(vec![1]));
+    assert_eq!(migrate_to_column_families(&db, false), 0);
+    // Records of no family, like the Cosmos responses older versions cached, are left alone
+    DB::put(&db, b"validators", [1]).unwrap();
+    assert_eq!(migrate_to_column_families(&db, false), 0);
+    assert!(DB::get(&db, b"validators").unwrap().is_some());
+
+    // Families this version does not use are kept, and do not stop the database from opening
+    db.create_cf("staking_info", &Options::default()).unwrap();
+    db.put_cf(db.cf_handle("staking_info").unwrap(), b"k", [1])
+        .unwrap();
+    drop(db);
+    let db = open_database(opts);
+    let unknown = db.cf_handle("staking_info").unwrap();
+    assert_eq!(db.get_cf(unknown, b"k").unwrap(), Some(vec![1]));
+
+    drop(db);
+    DB::destroy(&Options::default(), &path).unwrap();
+}
+
+#[test]
+fn test_misfiled_harvests_are_moved() {
+    use crate::althea::database::{
+        positions::ranged::harvest_key,
+        save_latest_searched_block,
+        schema::{save_schema_version, split_record, RECORD_VERSION_SCHEMA},
+    };
+    let path = std::env::temp_dir().join(format!("althea-link-harvests-{}", std::process::id()));
+    let opts = test_opts(&path);
+
+    // A database written before harvests had their own keys or records stored their version
+    let db = open_database(opts.clone());
+    save_latest_searched_block(&db, 100u32.into());
+    save_schema_version(&db, 0);
+    let he = HarvestEvent::default();
+    let v = bincode::serialize(&he).unwrap();
+    Storage::put(&db, BURN_RANGED_PREFIX, b"burn-ranged_test", &v).unwrap();
+    drop(db);
+
+    // Opening it moves the harvest, leaving nothing for clear_invalid_entries to delete and so no resync
+    let db = open_database(opts);
+    assert!(!clear_invalid_entries(&db));
+    let k = harvest_key(
+        he.user,
+        he.base,
+        he.quote,
+        he.pool_idx,
+        he.bid_tick,
+        he.ask_tick,
+        he.block_height,
+        he.index,
+    );
+    // Tagged with the version which started storing versions
+    let moved = Storage::get(&db, HARVEST_PREFIX, k.as_bytes())
+        .unwrap()
+        .unwrap();
+    assert_eq!(split_record(&moved), Some((RECORD_VERSION_SCHEMA, &v[..])));
+    assert_eq!(
+        Storage::get(&db, BURN_RANGED_PREFIX, b"burn-ranged_test").unwrap(),
+        None
+    );
+
+    drop(db);
+    DB::destroy(&Options::default(), &path).unwrap();
+}
+
+#[test]
+fn test_migration_dry_run_before_column_families() {
+    use crate::althea::database::{save_latest_searched_block, schema::save_schema_version};
+    let path = std::env::temp_dir().join(format!("althea-link-dry-run-{}", std::process::id()));
+    let db = open_database(test_opts(&path));
+
+    // A database from before column families, with a harvest misfiled under a burn key in the default family
+    save_latest_searched_block(&db, 100u32.into());
+    save_schema_version(&db, 0);
+    let v = bincode::serialize(&HarvestEvent::default()).unwrap();
+    DB::put(&db, b"burn-ranged_test", &v).unwrap();
+
+    assert_eq!(migrate_to_column_families(&db, true), 1);
+    let report = migrate(&db, true);
+    // Migration 1 moves the harvest and migration 2 adds its version
+    assert_eq!((report.moved, report.rewritten), (1, 1));
+    // Nothing was written
+    assert!(DB::get(&db, b"burn-ranged_test").unwrap().is_some());
+    assert_eq!(
+        crate::althea::database::schema::get_schema_version(&db),
+        Some(0)
+    );
+
+    drop(db);
+    DB::destroy(&Options::default(), &path).unwrap();
+}
+
+#[test]
+fn test_interrupted_migration_resumes() {
+    use crate::althea::database::{
+        save_latest_searched_block,
+        schema::{
+            get_schema_version, save_schema_version, split_record, MigrationProgress,
+            MIGRATION_PROGRESS_KEY, RECORD_VERSION_SCHEMA, SCHEMA_VERSION,
+        },
+    };
+    let path = std::env::temp_dir().join(format!("althea-link-resume-{}", std::process::id()));
+    let db = open_database(test_opts(&path));
+
+    // Migration 2 was stopped after tagging the first of two swaps
+    save_latest_searched_block(&db, 100u32.into());
+    save_schema_version(&db, RECORD_VERSION_SCHEMA - 1);
+    let tagged = [0, 0, 0, RECORD_VERSION_SCHEMA as u8, 1];
+    Storage::put(&db, SWAP_PREFIX, b"swap_a", &tagged).unwrap();
+    Storage::put(&db, SWAP_PREFIX, b"swap_b", &[2]).unwrap();
+    let progress = MigrationProgress {
+        version: RECORD_VERSION_SCHEMA,
+        family: SWAP_PREFIX.to_string(),
+        key: b"swap_a".to_vec(),
+    };
+    DB::put(
+        &db,
+        MIGRATION_PROGRESS_KEY,
+        bincode::serialize(&progress).unwrap(),
+    )
+    .unwrap();
+
+    migrate(&db, false);
+    let get = |k: &[u8]| Storage::get(&db, SWAP_PREFIX, k).unwrap().unwrap();
+    assert_eq!(get(b"swap_a"), tagged);
+    assert_eq!(
+        split_record(&get(b"swap_b")),
+        Some((RECORD_VERSION_SCHEMA, &[2u8][..]))
+    );
+    assert!(DB::get(&db, MIGRATION_PROGRESS_KEY).unwrap().is_none());
+    assert_eq!(get_schema_version(&db), Some(SCHEMA_VERSION));
+
+    drop(db);
+    DB::destroy(&Options::default(), &path).unwrap();
+}
+
+#[test]
+fn test_tracked_pool_fee_rates_are_converted() {
+    use crate::althea::database::{
+        save_latest_searched_block,
+        schema::{deserialize_record, save_schema_version},
+    };
+    let path = std::env::temp_dir().join(format!("althea-link-fee-rate-{}", std::process::id()));
+    let db = open_database(test_opts(&path));
+
+    // A pool tracked with the template's raw fee rate, and one whose rate a revision already made a fraction
+    save_latest_searched_block(&db, 100u32.into());
+    save_schema_version(&db, 2);
+    let old =
+        |pool: TrackedPool| [&2u32.to_be_bytes()[..], &bincode::serialize(&pool).unwrap()].concat();
+    let raw = TrackedPool {
+        fee_rate: 3000.0,
+        base_fees: 3e9,
+        quote_fees: 6e9,
+        ..Default::default()
+    };
+    Storage::put(&db, TRACKED_POOL_PREFIX, b"tracked-pool_raw", &old(raw)).unwrap();
+    let revised = TrackedPool {
+        fee_rate: 0.001,
+        base_fees: 1000.0,
+        ..Default::default()
+    };
+    Storage::put(
+        &db,
+        TRACKED_POOL_PREFIX,
+        b"tracked-pool_revised",
+        &old(revised),
+    )
+    .unwrap();
+
+    let report = migrate(&db, false);
+    assert_eq!((report.checked, report.rewritten), (2, 1));
+    let get = |k: &[u8]| -> TrackedPool {
+        deserialize_record(&Storage::get(&db, TRACKED_POOL_PREFIX, k).unwrap().unwrap()).unwrap()
+    };
+    let raw = get(b"tracked-pool_raw");
+    assert_eq!(raw.fee_rate, 0.003);
+    assert_eq!((raw.base_fees, raw.quote_fees), (3000.0, 6000.0));
+    let revised = get(b"tracked-pool_revised");
+    assert_eq!((revised.fee_rate, revised.base_fees), (0.001, 1000.0));
+
+    drop(db);
+    DB::destroy(&Options::default(), &path).unwrap();
+}
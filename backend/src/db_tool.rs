@@ -0,0 +1,328 @@
+//! The `db` command, which inspects and repairs the database of a stopped node without going through the web server.
+//! Run it as `althea-link-backend db --database-path <path> <command>`, see `db --help` for the commands
+//!
+//! Reading commands open the database read only and also work while the node is running, though they may miss the latest
+//! writes. Repairing commands need the node to be stopped, RocksDB refuses to open a database another process holds
+
+use std::path::Path;
+
+use clap::{Args, Subcommand};
+use clarity::{Address, Uint256};
+use rocksdb::{Options, DB};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::althea::ambient::croc_query::CurveState;
+use crate::althea::ambient::knockout::{
+    BurnKnockoutEvent, MintKnockoutEvent, WithdrawKnockoutEvent,
+};
+use crate::althea::ambient::pools::{InitPoolEvent, PoolRevisionEvent};
+use crate::althea::ambient::positions::{
+    BurnAmbientEvent, BurnRangedEvent, HarvestEvent, MintAmbientEvent, MintRangedEvent,
+};
+use crate::althea::ambient::swap::SwapEvent;
+use crate::althea::database::blocks::BLOCK_TIME_PREFIX;
+use crate::althea::database::column_families::{
+    cf, existing_family_descriptors, family_for_key, DbMemory, FAMILIES,
+};
+use crate::althea::database::curve::{LATEST_CURVE_KEY, LATEST_LIQUIDITY_KEY, LATEST_PRICE_KEY};
+use crate::althea::database::pools::{
+    delete_init_pool, get_init_pool, Pool, INIT_POOL_PREFIX, POOL_TEMPLATE_PREFIX, REVISION_PREFIX,
+    SWAP_PREFIX,
+};
+use crate::althea::database::positions::ambient::{BURN_AMBIENT_PREFIX, MINT_AMBIENT_PREFIX};
+use crate::althea::database::positions::index::ACTIVE_POSITIONS_PREFIX;
+use crate::althea::database::positions::knockout::{
+    BURN_KNOCKOUT_PREFIX, MINT_KNOCKOUT_PREFIX, WITHDRAW_KNOCKOUT_PREFIX,
+};
+use crate::althea::database::positions::ranged::{
+    BURN_RANGED_PREFIX, HARVEST_PREFIX, MINT_RANGED_PREFIX,
+};
+use crate::althea::database::positions::PoolPositions;
+use crate::althea::database::schema::{
+    deserialize_record, MigrationProgress, MIGRATION_PROGRESS_KEY, SCHEMA_VERSION_KEY,
+};
+use crate::althea::database::storage::{Storage, DEFAULT_FAMILY};
+use crate::althea::database::tokens::{TokenMetadata, TOKEN_METADATA_PREFIX};
+use crate::althea::database::tracking::candles::{Candle, CANDLE_PREFIX};
+use crate::althea::database::tracking::history::{PoolSnapshot, POOL_SNAPSHOT_PREFIX};
+use crate::althea::database::tracking::knockouts::KNOCKOUT_CROSS_PREFIX;
+use crate::althea::database::tracking::{
+    delete_pool_index, get_dirty_pool, reset_pool_index, DirtyPoolTracker, TrackedPool,
+    DIRTY_POOL_PREFIX, TRACKED_POOL_PREFIX,
+};
+use crate::althea::database::transactions::{Tx, POOL_TX_PREFIX};
+use crate::althea::database::{LATEST_SEARCHED_BLOCK_KEY, SYNCING_KEY};
+
+/// The arguments of the `db` subcommand
+#[derive(Args)]
+pub struct DbOpts {
+    #[clap(long, default_value = "backend_db_path")]
+    database_path: String,
+
+    #[clap(subcommand)]
+    command: DbCommand,
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// Lists every key prefix with RocksDB's estimate of the records stored under it and their size on disk
+    Prefixes {
+        /// Count the records and their key and value bytes exactly. This reads every record in the database, which can
+        /// take a long time on a large database
+        #[clap(long)]
+        exact: bool,
+    },
+    /// Prints the record stored under a key, decoded as JSON
+    Get { key: String },
+    /// Prints the records whose keys start with a prefix, decoded as JSON with one record per line
+    Scan {
+        prefix: String,
+        /// The most records to print
+        #[clap(long, default_value = "100")]
+        limit: usize,
+    },
+    /// Deletes a pool's tracked state, snapshots and candles and marks it dirty, so the indexer rebuilds it from the
+    /// stored events when the node next starts
+    DirtyPool(PoolArgs),
+    /// Deletes a pool's tracked state, snapshots, candles and InitPool event, so the pool is no longer listed or tracked.
+    /// Its other events are kept
+    DeletePool(PoolArgs),
+}
+
+#[derive(Args)]
+struct PoolArgs {
+    #[clap(long)]
+    base: Address,
+    #[clap(long)]
+    quote: Address,
+    #[clap(long)]
+    pool_idx: u64,
+}
+
+/// Runs the command given by `opts`, exiting with an error message if it fails
+pub fn run(opts: DbOpts) {
+    if let Err(e) = run_command(opts) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run_command(opts: DbOpts) -> Result<(), String> {
+    match opts.command {
+        DbCommand::Prefixes { exact } => {
+            let db = open(&opts.database_path, true)?;
+            if exact {
+                eprintln!("Counting every record in the database, this may take a while");
+            }
+            println!(
+                "{:<20} {:>12} {:>14} {:>14} {:>14}",
+                "prefix", "records", "key bytes", "value bytes", "sst bytes"
+            );
+            let property = |name: &str, property: &str| {
+                db.property_int_value_cf(cf(&db, name), property)
+                    .ok()
+                    .flatten()
+                    .unwrap_or(0)
+            };
+            for family in FAMILIES.iter() {
+                let sst_bytes = property(family.name, "rocksdb.total-sst-files-size");
+                let (records, key_bytes, value_bytes) = if exact {
+                    let (mut records, mut key_bytes, mut value_bytes) = (0u64, 0u64, 0u64);
+                    for (k, v) in db.prefix_scan(family.name, &[]).flatten() {
+                        records += 1;
+                        key_bytes += k.len() as u64;
+                        value_bytes += v.len() as u64;
+                    }
+                    (
+                        records.to_string(),
+                        key_bytes.to_string(),
+                        value_bytes.to_string(),
+                    )
+                } else {
+                    let estimate = property(family.name, "rocksdb.estimate-num-keys");
+                    (format!("~{}", estimate), "-".to_string(), "-".to_string())
+                };
+                println!(
+                    "{:<20} {:>12} {:>14} {:>14} {:>14}",
+                    family.name, records, key_bytes, value_bytes, sst_bytes
+                );
+            }
+        }
+        DbCommand::Get { key } => {
+            let db = open(&opts.database_path, true)?;
+            let family = family_for_key(key.as_bytes()).unwrap_or(DEFAULT_FAMILY);
+            let value = Storage::get(&db, family, key.as_bytes())
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("No record at key {}", key))?;
+            println!("{}", decode_record(family, key.as_bytes(), &value)?);
+        }
+        DbCommand::Scan { prefix, limit } => {
+            let db = open(&opts.database_path, true)?;
+            let family = family_for_key(prefix.as_bytes()).unwrap_or(DEFAULT_FAMILY);
+            for record in db.prefix_scan(family, prefix.as_bytes()).take(limit) {
+                let (k, v) = record.map_err(|e| e.to_string())?;
+                let key = serde_json::to_string(&String::from_utf8_lossy(&k)).unwrap();
+                match decode_record(family, &k, &v) {
+                    Ok(value) => println!("{{\"key\":{},\"value\":{}}}", key, value),
+                    Err(e) => println!(
+                        "{{\"key\":{},\"error\":{}}}",
+                        key,
+                        serde_json::to_string(&e).unwrap()
+                    ),
+                }
+            }
+        }
+        DbCommand::DirtyPool(pool) => {
+            let db = open(&opts.database_path, false)?;
+            let pool_idx = pool.pool_idx.into();
+            if !reset_pool_index(&db, pool.base, pool.quote, pool_idx) {
+                return Err(format!(
+                    "No InitPool stored for {} {} {}, the pool can not be rebuilt",
+                    pool.base, pool.quote, pool_idx
+                ));
+            }
+            println!(
+                "Pool {} {} {} will be rebuilt from its stored events when the node starts",
+                pool.base, pool.quote, pool_idx
+            );
+        }
+        DbCommand::DeletePool(pool) => {
+            let db = open(&opts.database_path, false)?;
+            let pool_idx = pool.pool_idx.into();
+            if get_init_pool(&db, pool.base, pool.quote, pool_idx).is_none()
+                && get_dirty_pool(&db, pool.base, pool.quote, pool_idx).is_none()
+            {
+                return Err(format!(
+                    "No pool {} {} {} is stored",
+                    pool.base, pool.quote, pool_idx
+                ));
+            }
+            delete_pool_index(&db, pool.base, pool.quote, pool_idx);
+            delete_init_pool(&db, pool.base, pool.quote, pool_idx);
+            println!("Deleted pool {} {} {}", pool.base, pool.quote, pool_idx);
+        }
+    }
+    Ok(())
+}
+
+/// The memory budget of the database when opened by the db command, which reads little and may run next to the node
+const DB_TOOL_MEMORY_MB: usize = 64;
+
+/// Opens an existing database, never creating one. A read only database can be opened alongside a running node
+fn open(path: &str, read_only: bool) -> Result<DB, String> {
+    if !Path::new(path).join("CURRENT").exists() {
+        return Err(format!("No database at {}", path));
+    }
+    let options = Options::default();
+    let memory = DbMemory::new(DB_TOOL_MEMORY_MB);
+    let families = existing_family_descriptors(Path::new(path), &memory);
+    let db = if read_only {
+        DB::open_cf_descriptors_read_only(&options, path, families, false)
+    } else {
+        DB::open_cf_descriptors(&options, path, families)
+    };
+    db.map_err(|e| {
+        format!(
+            "Unable to open the database at {}, is the node stopped? {}",
+            path, e
+        )
+    })
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+fn decode<T: DeserializeOwned + Serialize>(value: &[u8]) -> Result<String, String> {
+    let decoded: T = deserialize_record(value).map_err(|e| format!("Undecodable record: {}", e))?;
+    to_json(&decoded)
+}
+
+fn be_bytes<const N: usize>(value: &[u8]) -> Result<[u8; N], String> {
+    value
+        .try_into()
+        .map_err(|_| format!("Expected {} bytes, found {}", N, value.len()))
+}
+
+/// Decodes a record stored in `family` to JSON, by the type the family's records are stored as
+pub fn decode_record(family: &str, key: &[u8], value: &[u8]) -> Result<String, String> {
+    match family {
+        INIT_POOL_PREFIX => decode::<InitPoolEvent>(value),
+        POOL_TEMPLATE_PREFIX => decode::<Pool>(value),
+        SWAP_PREFIX => decode::<SwapEvent>(value),
+        REVISION_PREFIX => decode::<PoolRevisionEvent>(value),
+        MINT_RANGED_PREFIX => decode::<MintRangedEvent>(value),
+        BURN_RANGED_PREFIX => decode::<BurnRangedEvent>(value),
+        HARVEST_PREFIX => decode::<HarvestEvent>(value),
+        MINT_AMBIENT_PREFIX => decode::<MintAmbientEvent>(value),
+        BURN_AMBIENT_PREFIX => decode::<BurnAmbientEvent>(value),
+        MINT_KNOCKOUT_PREFIX => decode::<MintKnockoutEvent>(value),
+        BURN_KNOCKOUT_PREFIX => decode::<BurnKnockoutEvent>(value),
+        WITHDRAW_KNOCKOUT_PREFIX => decode::<WithdrawKnockoutEvent>(value),
+        POOL_TX_PREFIX => decode::<Tx>(value),
+        BLOCK_TIME_PREFIX => to_json(&u64::from_be_bytes(be_bytes(value)?)),
+        CANDLE_PREFIX => decode::<Candle>(value),
+        POOL_SNAPSHOT_PREFIX => decode::<PoolSnapshot>(value),
+        ACTIVE_POSITIONS_PREFIX => decode::<PoolPositions>(value),
+        // The key holds the whole record
+        KNOCKOUT_CROSS_PREFIX => to_json(&()),
+        TRACKED_POOL_PREFIX => decode::<TrackedPool>(value),
+        DIRTY_POOL_PREFIX => decode::<DirtyPoolTracker>(value),
+        LATEST_CURVE_KEY => decode::<CurveState>(value),
+        LATEST_PRICE_KEY | LATEST_LIQUIDITY_KEY => to_json(&u128::from_be_bytes(be_bytes(value)?)),
+        TOKEN_METADATA_PREFIX => decode::<TokenMetadata>(value),
+        DEFAULT_FAMILY => match std::str::from_utf8(key) {
+            Ok(LATEST_SEARCHED_BLOCK_KEY) => to_json(&Uint256::from_be_bytes(value).to_string()),
+            Ok(SYNCING_KEY) => to_json(&(value.first() == Some(&1))),
+            Ok(SCHEMA_VERSION_KEY) => to_json(&u32::from_be_bytes(be_bytes(value)?)),
+            Ok(MIGRATION_PROGRESS_KEY) => bincode::deserialize::<MigrationProgress>(value)
+                .map_err(|e| format!("Undecodable record: {}", e))
+                .and_then(|p| to_json(&p)),
+            _ => Err(format!("Unknown key {}", String::from_utf8_lossy(key))),
+        },
+        _ => Err(format!("Unknown family {}", family)),
+    }
+}
+
+#[test]
+fn test_decode_record() {
+    use crate::althea::database::schema::serialize_record;
+
+    let tracker = DirtyPoolTracker {
+        dirty: true,
+        last_block: 10u8.into(),
+        base: Address::default(),
+        quote: Address::default(),
+        pool_idx: 36000u32.into(),
+    };
+    let json = decode_record(
+        DIRTY_POOL_PREFIX,
+        b"dirty-pool_",
+        &serialize_record(&tracker).unwrap(),
+    )
+    .unwrap();
+    let decoded: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded["dirty"], true);
+
+    let price = 1u128 << 64;
+    assert_eq!(
+        decode_record(LATEST_PRICE_KEY, b"price", &price.to_be_bytes()).unwrap(),
+        price.to_string()
+    );
+    assert_eq!(
+        decode_record(
+            DEFAULT_FAMILY,
+            SCHEMA_VERSION_KEY.as_bytes(),
+            &3u32.to_be_bytes()
+        )
+        .unwrap(),
+        "3"
+    );
+    // Every family's records can be decoded
+    for family in FAMILIES.iter() {
+        assert!(
+            !decode_record(family.name, b"", &[]).is_err_and(|e| e.starts_with("Unknown family"))
+        );
+    }
+    assert!(decode_record(SWAP_PREFIX, b"swap_", &[1, 2]).is_err());
+}
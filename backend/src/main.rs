@@ -1,20 +1,51 @@
 use crate::server::start_server;
 use althea::{
-    database::save_latest_searched_block, start_ambient_indexer, DEFAULT_START_SEARCH_BLOCK,
+    cosmos::{cache::CosmosCaches, delegations::DEFAULT_DELEGATION_REFRESH_CONCURRENCY},
+    database::{
+        column_families::DEFAULT_DB_MEMORY_MB,
+        positions::index::{check_position_index, position_index_missing},
+        save_latest_searched_block,
+        transactions::{pool_tx_index_missing, rebuild_pool_tx_index},
+    },
+    start_ambient_indexer, DEFAULT_START_SEARCH_BLOCK, DELEGATIONS_CACHE_DURATION,
 };
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use clarity::Address;
 use env_logger::Env;
 use log::info;
 use rustls::crypto::CryptoProvider;
-use std::{net::IpAddr, sync::Arc};
+use snapshot::{export_snapshot, get_chain_id, restore_snapshot, start_snapshot_signal_handler};
+use std::{net::IpAddr, path::Path, sync::Arc};
 
 pub mod althea;
 pub mod database;
+pub mod db_tool;
 pub mod server;
+pub mod snapshot;
 
-#[derive(Parser, Clone)]
-#[clap(version = "1.0", author = "Christian Borst")]
+/// Runs the server with the given options, or one of the subcommands instead
+#[derive(Parser)]
+#[clap(
+    version = "1.0",
+    author = "Christian Borst",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    opts: Option<Opts>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inspect and repair the database of a stopped node
+    Db(db_tool::DbOpts),
+}
+
+#[derive(Args, Clone)]
 pub struct Opts {
     /// The address of the CrocSwapDEX contract
     #[clap(short, long)]
@@ -36,6 +67,10 @@ pub struct Opts {
     #[clap(short = 't', long, value_delimiter = ',')]
     pool_templates: Vec<u64>,
 
+    /// The address of a USD stablecoin with indexed pools, used to value positions in USD
+    #[clap(long)]
+    usd_token: Option<Address>,
+
     /// The url of the EVM JSONRPC
     #[clap(short, long, default_value = "http://localhost:8545")]
     evm_rpc_url: String,
@@ -67,6 +102,19 @@ pub struct Opts {
     #[clap(long, default_value = "backend_db_path")]
     database_path: String,
 
+    /// The memory the database may use for its block cache and write buffers together, in megabytes
+    #[clap(long, default_value_t = DEFAULT_DB_MEMORY_MB)]
+    db_memory_mb: usize,
+
+    /// How often the delegations of recently active delegators are refreshed in the background, in seconds. The wait
+    /// doubles while the Cosmos RPC keeps failing
+    #[clap(long, default_value_t = DELEGATIONS_CACHE_DURATION)]
+    delegation_refresh_secs: u64,
+
+    /// How many delegators' delegations are refreshed from the Cosmos RPC at once
+    #[clap(long, default_value_t = DEFAULT_DELEGATION_REFRESH_CONCURRENCY)]
+    delegation_refresh_concurrency: usize,
+
     /// If true the database will be reindexed checking all avaialble data before returning to
     /// normal operation
     #[clap(short, long, default_value = "false")]
@@ -76,6 +124,10 @@ pub struct Opts {
     #[clap(long, default_value = "false", requires("reindex"))]
     halt_after_indexing: bool,
 
+    /// If true the active position index is checked against the stored events on startup, and repaired if they differ
+    #[clap(long, default_value = "false")]
+    check_positions: bool,
+
     /// If true the database will be compacted on startup
     #[clap(long, default_value = "false")]
     compact: bool,
@@ -83,28 +135,122 @@ pub struct Opts {
     /// If true the database will be compacted on startup then the server will halt
     #[clap(long, default_value = "false")]
     compact_and_halt: bool,
+
+    /// If true records written by older versions are moved into their own column families and upgraded to the current
+    /// schema, which otherwise happens automatically on startup, then the database is compacted and the server will halt
+    #[clap(long, default_value = "false")]
+    migrate_and_halt: bool,
+
+    /// If true the records which the startup migrations would move or upgrade are counted without writing anything,
+    /// then the server will halt
+    #[clap(long, default_value = "false")]
+    migrate_dry_run: bool,
+
+    /// If set and no database exists yet, the database is created from the snapshot archive at this path and indexing
+    /// continues from the snapshot's block. Ignored once the database exists
+    #[clap(long)]
+    bootstrap_snapshot: Option<String>,
+
+    /// If set a snapshot of the database is written to this path as a compressed archive, then the server will halt.
+    /// This opens the database, so a node using the same database must be stopped first, see --snapshot-on-signal
+    #[clap(long, conflicts_with("snapshot_on_signal"))]
+    export_snapshot: Option<String>,
+
+    /// If set the running server writes a snapshot of its database to this path as a compressed archive each time it
+    /// receives SIGUSR1, without stopping
+    #[clap(long)]
+    snapshot_on_signal: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
-    let opts: Opts = Opts::parse();
+    let opts = match Cli::parse() {
+        Cli {
+            command: Some(Command::Db(db_opts)),
+            ..
+        } => {
+            db_tool::run(db_opts);
+            return;
+        }
+        // Clap requires the server's arguments whenever no subcommand is given
+        Cli { opts, .. } => opts.expect("Missing server arguments"),
+    };
     CryptoProvider::install_default(rustls::crypto::aws_lc_rs::default_provider()).unwrap();
     openssl_probe::init_ssl_cert_env_vars();
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    if let Some(archive) = &opts.bootstrap_snapshot {
+        bootstrap_from_snapshot(&opts, Path::new(archive)).await;
+    }
     let db = database::open_database(opts.clone());
 
     if database::clear_invalid_entries(&db) {
         info!("Cleared invalid entries from the database, triggering resync");
         save_latest_searched_block(&db, DEFAULT_START_SEARCH_BLOCK.into());
     }
+    if pool_tx_index_missing(&db) {
+        info!("Building the pool transaction index from stored events");
+        rebuild_pool_tx_index(&db);
+    }
+    if opts.check_positions || position_index_missing(&db) {
+        info!("Checking the active position index against stored events");
+        let check = check_position_index(&db, true);
+        info!(
+            "Checked positions of {} users and pools, repaired {} and removed {} stale",
+            check.checked, check.mismatched, check.stale
+        );
+    }
+
+    if let Some(archive) = &opts.export_snapshot {
+        let chain_id = get_chain_id(&opts)
+            .await
+            .expect("Unable to get the chain id for the snapshot");
+        let metadata = export_snapshot(&db, chain_id, opts.dex_contract, Path::new(archive))
+            .expect("Failed to export snapshot");
+        info!(
+            "Exported snapshot at block {} to {}, halting",
+            metadata.latest_searched_block, archive
+        );
+        std::process::exit(0);
+    }
 
     let db = Arc::new(db);
+    let caches = Arc::new(CosmosCaches::new());
+
+    if let Some(archive) = &opts.snapshot_on_signal {
+        start_snapshot_signal_handler(opts.clone(), db.clone(), archive.into());
+    }
 
     // Start the background indexer service
     info!("Starting ambient indexer");
-    start_ambient_indexer(opts.clone(), db.clone());
+    start_ambient_indexer(opts.clone(), db.clone(), caches.clone());
 
     // Start the Actix web server
     info!("Starting web server");
-    start_server(opts, db.clone()).await;
+    start_server(opts, db.clone(), caches).await;
+}
+
+/// Creates the database from a snapshot archive when it does not exist yet, so a new node does not index from the first block
+async fn bootstrap_from_snapshot(opts: &Opts, archive: &Path) {
+    let database_path = Path::new(&opts.database_path);
+    if database_path.exists()
+        && database_path
+            .read_dir()
+            .is_ok_and(|mut d| d.next().is_some())
+    {
+        info!(
+            "Database already exists, not bootstrapping from {}",
+            archive.display()
+        );
+        return;
+    }
+    info!("Bootstrapping the database from {}", archive.display());
+    let chain_id = get_chain_id(opts)
+        .await
+        .expect("Unable to get the chain id to check the snapshot against");
+    let metadata = restore_snapshot(archive, database_path, chain_id, opts.dex_contract)
+        .expect("Failed to bootstrap from snapshot");
+    info!(
+        "Restored snapshot taken at {} with schema version {}, indexing continues from block {}",
+        metadata.created, metadata.schema_version, metadata.latest_searched_block
+    );
 }
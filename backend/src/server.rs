@@ -1,13 +1,16 @@
 use std::sync::Arc;
 
+use crate::althea::cosmos::cache::CosmosCaches;
 use crate::althea::endpoints::ambient::{
-    moralis_eth_in_usdc, pool_liq_curve, pool_stats, query_all_burn_ambient,
+    all_pool_stats, dex_pairs, moralis_eth_in_usdc, pool_candles, pool_history, pool_liq_curve,
+    pool_stats, pool_txs, position_pnl_stats, position_stats, query_all_burn_ambient,
     query_all_burn_knockout, query_all_burn_ranged, query_all_init_pools, query_all_mint_ambient,
     query_all_mint_knockout, query_all_mint_ranged, query_pool, query_price, slingshot_trade,
-    slingshot_trade_get, user_pool_positions, user_positions,
+    slingshot_trade_get, user_pool_positions, user_positions, user_txs,
 };
 use crate::althea::endpoints::cosmos::{
-    get_delegations, get_proposals, get_staking_info, get_validators,
+    cosmos_cache_stats, get_delegations, get_proposals, get_redelegations, get_staking_info,
+    get_staking_overview, get_validators,
 };
 use crate::althea::endpoints::get_constants;
 use crate::Opts;
@@ -25,8 +28,9 @@ async fn index() -> impl Responder {
     "althea.link"
 }
 
-pub async fn start_server(opts: Opts, db: Arc<rocksdb::DB>) {
+pub async fn start_server(opts: Opts, db: Arc<rocksdb::DB>, caches: Arc<CosmosCaches>) {
     let db = web::Data::new(db.clone());
+    let caches = web::Data::new(caches);
 
     // Create shared Contact instance
     let contact = Contact::new(
@@ -41,6 +45,7 @@ pub async fn start_server(opts: Opts, db: Arc<rocksdb::DB>) {
     let server = HttpServer::new(move || {
         App::new()
             .app_data(db.clone())
+            .app_data(caches.clone())
             .app_data(contact.clone())
             .app_data(Data::new(op.clone()))
             .wrap(
@@ -55,6 +60,8 @@ pub async fn start_server(opts: Opts, db: Arc<rocksdb::DB>) {
             .service(get_validators)
             .service(get_proposals)
             .service(get_delegations)
+            .service(get_redelegations)
+            .service(get_staking_overview)
             .service(get_staking_info)
             // Debug endpoints
             .service(
@@ -67,16 +74,26 @@ pub async fn start_server(opts: Opts, db: Arc<rocksdb::DB>) {
                     .service(query_all_burn_ambient)
                     .service(query_all_mint_knockout)
                     .service(query_all_burn_knockout)
-                    .service(query_price),
+                    .service(query_price)
+                    .service(cosmos_cache_stats),
             )
             // Graphcache-go endpoints
             .service(
                 web::scope("/gcgo")
                     .service(user_positions)
                     .service(user_pool_positions)
+                    .service(position_stats)
+                    .service(position_pnl_stats)
+                    .service(user_txs)
+                    .service(pool_txs)
                     .service(pool_liq_curve)
-                    .service(pool_stats),
+                    .service(pool_stats)
+                    .service(pool_history)
+                    .service(pool_candles)
+                    .service(all_pool_stats),
             )
+            // Canto-style dex endpoints
+            .service(web::scope("/v1/dex").service(dex_pairs))
             .service(
                 web::scope("/api")
                     // Slingshot Trade endpoint
@@ -0,0 +1,333 @@
+//! Exports consistent copies of the database as compressed archives, and creates new databases from those archives so that a
+//! new node continues indexing from the archive's block rather than from DEFAULT_START_SEARCH_BLOCK
+//!
+//! An archive is a gzipped tar holding metadata.json, describing where the copy came from, and the RocksDB checkpoint under db/
+//!
+//! A running node exports a snapshot when it receives SIGUSR1, see start_snapshot_signal_handler. --export-snapshot opens
+//! the database itself, so it only works while no node is using that database
+
+use std::fmt;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_web::rt::System;
+use clarity::{Address, Uint256};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info};
+use rocksdb::checkpoint::Checkpoint;
+use rocksdb::{Options, DB};
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{signal, SignalKind};
+use web30::jsonrpc::error::Web3Error;
+
+use crate::althea::database::get_latest_searched_block;
+use crate::althea::database::schema::{get_schema_version, SCHEMA_VERSION};
+use crate::althea::database::storage::DEFAULT_FAMILY;
+use crate::althea::{get_althea_web3, TIMEOUT};
+use crate::Opts;
+
+pub const SNAPSHOT_METADATA_FILE: &str = "metadata.json";
+pub const SNAPSHOT_DB_DIR: &str = "db";
+
+/// Describes the database an archive was taken from, read from the checkpoint itself so it matches the archived records
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotMetadata {
+    /// The EVM chain id of the indexed chain
+    pub chain_id: Uint256,
+    /// The indexed CrocSwapDEX contract
+    pub dex_contract: Address,
+    /// Indexing resumes from the block after this one
+    pub latest_searched_block: Uint256,
+    pub schema_version: u32,
+    /// Unix time in seconds the checkpoint was taken
+    pub created: u64,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    IoError(std::io::Error),
+    DatabaseError(rocksdb::Error),
+    EthereumRestError(Web3Error),
+    InvalidSnapshotError(String),
+}
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::IoError(val) => write!(f, "IO error: {}", val),
+            SnapshotError::DatabaseError(val) => write!(f, "Database error: {}", val),
+            SnapshotError::EthereumRestError(val) => write!(f, "Web3 error: {}", val),
+            SnapshotError::InvalidSnapshotError(val) => write!(f, "Invalid snapshot: {}", val),
+        }
+    }
+}
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> Self {
+        SnapshotError::IoError(error)
+    }
+}
+impl From<rocksdb::Error> for SnapshotError {
+    fn from(error: rocksdb::Error) -> Self {
+        SnapshotError::DatabaseError(error)
+    }
+}
+impl From<Web3Error> for SnapshotError {
+    fn from(error: Web3Error) -> Self {
+        SnapshotError::EthereumRestError(error)
+    }
+}
+
+/// Fetches the chain id of the EVM RPC, which snapshots are stamped with and checked against
+pub async fn get_chain_id(opts: &Opts) -> Result<Uint256, SnapshotError> {
+    get_althea_web3(opts, TIMEOUT)
+        .eth_chainid()
+        .await?
+        .ok_or_else(|| SnapshotError::InvalidSnapshotError("RPC returned no chain id".to_string()))
+}
+
+/// A working directory next to `path`, removing anything left there by an earlier failed attempt
+fn staging_dir(path: &Path, suffix: &str) -> Result<PathBuf, SnapshotError> {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    let dir = PathBuf::from(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(dir)
+}
+
+/// Takes a checkpoint of `db`, which is consistent even while the indexer is writing, and writes it to `archive`
+pub fn export_snapshot(
+    db: &DB,
+    chain_id: Uint256,
+    dex_contract: Address,
+    archive: &Path,
+) -> Result<SnapshotMetadata, SnapshotError> {
+    let checkpoint_dir = staging_dir(archive, ".checkpoint")?;
+    Checkpoint::new(db)?.create_checkpoint(&checkpoint_dir)?;
+    let result = archive_checkpoint(&checkpoint_dir, chain_id, dex_contract, archive);
+    fs::remove_dir_all(&checkpoint_dir)?;
+    result
+}
+
+/// Exports a snapshot of the running node's database to `archive` each time the process receives SIGUSR1, e.g. with
+/// `kill -USR1 <pid>`. Exports happen one at a time while the node keeps indexing and serving
+pub fn start_snapshot_signal_handler(opts: Opts, db: Arc<DB>, archive: PathBuf) {
+    // The web3 client is not Send, so like the indexer this runs on its own thread, which the exports may block
+    thread::spawn(move || {
+        let runner = System::new();
+        runner.block_on(async move {
+            let mut signals = match signal(SignalKind::user_defined1()) {
+                Ok(signals) => signals,
+                Err(e) => {
+                    error!(
+                        "Unable to listen for SIGUSR1, snapshots can not be triggered: {}",
+                        e
+                    );
+                    return;
+                }
+            };
+            while signals.recv().await.is_some() {
+                info!(
+                    "Received SIGUSR1, exporting a snapshot to {}",
+                    archive.display()
+                );
+                let chain_id = match get_chain_id(&opts).await {
+                    Ok(chain_id) => chain_id,
+                    Err(e) => {
+                        error!("Unable to get the chain id for the snapshot: {}", e);
+                        continue;
+                    }
+                };
+                match replace_with_snapshot(&db, chain_id, opts.dex_contract, &archive) {
+                    Ok(metadata) => info!(
+                        "Exported snapshot at block {} to {}",
+                        metadata.latest_searched_block,
+                        archive.display()
+                    ),
+                    Err(e) => error!("Failed to export snapshot: {}", e),
+                }
+            }
+        });
+    });
+}
+
+/// Exports a snapshot of `db` next to `archive` and then renames it into place, so an earlier archive at that path stays
+/// whole until the new one is complete
+fn replace_with_snapshot(
+    db: &DB,
+    chain_id: Uint256,
+    dex_contract: Address,
+    archive: &Path,
+) -> Result<SnapshotMetadata, SnapshotError> {
+    let mut partial = archive.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let metadata = export_snapshot(db, chain_id, dex_contract, &partial)?;
+    fs::rename(&partial, archive)?;
+    Ok(metadata)
+}
+
+fn archive_checkpoint(
+    checkpoint_dir: &Path,
+    chain_id: Uint256,
+    dex_contract: Address,
+    archive: &Path,
+) -> Result<SnapshotMetadata, SnapshotError> {
+    let metadata = {
+        // Only the default family is needed, which holds the latest searched block and schema version
+        let checkpoint = DB::open_cf_for_read_only(
+            &Options::default(),
+            checkpoint_dir,
+            [DEFAULT_FAMILY],
+            false,
+        )?;
+        let latest_searched_block = get_latest_searched_block(&checkpoint).ok_or_else(|| {
+            SnapshotError::InvalidSnapshotError("Nothing has been indexed yet".to_string())
+        })?;
+        SnapshotMetadata {
+            chain_id,
+            dex_contract,
+            latest_searched_block,
+            schema_version: get_schema_version(&checkpoint).unwrap_or(0),
+            created: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }
+    };
+
+    let encoder = GzEncoder::new(File::create(archive)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let json = serde_json::to_vec_pretty(&metadata).unwrap();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(json.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(metadata.created);
+    header.set_cksum();
+    builder.append_data(&mut header, SNAPSHOT_METADATA_FILE, json.as_slice())?;
+    builder.append_dir_all(SNAPSHOT_DB_DIR, checkpoint_dir)?;
+    builder.into_inner()?.finish()?;
+    Ok(metadata)
+}
+
+/// Creates the database at `database_path` from `archive`, refusing archives of another chain or DEX contract, or of a
+/// schema newer than this version can read. The database path must not hold a database already
+pub fn restore_snapshot(
+    archive: &Path,
+    database_path: &Path,
+    chain_id: Uint256,
+    dex_contract: Address,
+) -> Result<SnapshotMetadata, SnapshotError> {
+    if database_path.exists() && fs::read_dir(database_path)?.next().is_some() {
+        return Err(SnapshotError::InvalidSnapshotError(format!(
+            "{} is not empty, refusing to overwrite it",
+            database_path.display()
+        )));
+    }
+    let staging = staging_dir(database_path, ".restore")?;
+    let result = unpack_snapshot(archive, &staging, database_path, chain_id, dex_contract);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    result
+}
+
+fn unpack_snapshot(
+    archive: &Path,
+    staging: &Path,
+    database_path: &Path,
+    chain_id: Uint256,
+    dex_contract: Address,
+) -> Result<SnapshotMetadata, SnapshotError> {
+    tar::Archive::new(GzDecoder::new(File::open(archive)?)).unpack(staging)?;
+    let metadata: SnapshotMetadata =
+        serde_json::from_slice(&fs::read(staging.join(SNAPSHOT_METADATA_FILE))?).map_err(|e| {
+            SnapshotError::InvalidSnapshotError(format!(
+                "Unreadable {}: {}",
+                SNAPSHOT_METADATA_FILE, e
+            ))
+        })?;
+    if metadata.chain_id != chain_id {
+        return Err(SnapshotError::InvalidSnapshotError(format!(
+            "Snapshot is of chain {}, expected {}",
+            metadata.chain_id, chain_id
+        )));
+    }
+    if metadata.dex_contract != dex_contract {
+        return Err(SnapshotError::InvalidSnapshotError(format!(
+            "Snapshot is of DEX contract {}, expected {}",
+            metadata.dex_contract, dex_contract
+        )));
+    }
+    if metadata.schema_version > SCHEMA_VERSION {
+        return Err(SnapshotError::InvalidSnapshotError(format!(
+            "Snapshot schema version {} is newer than this version's {}",
+            metadata.schema_version, SCHEMA_VERSION
+        )));
+    }
+    if database_path.exists() {
+        fs::remove_dir(database_path)?;
+    }
+    fs::rename(staging.join(SNAPSHOT_DB_DIR), database_path)?;
+    Ok(metadata)
+}
+
+#[test]
+fn test_snapshot_roundtrip() {
+    use crate::althea::database::column_families::{cf, family_descriptors, DbMemory};
+    use crate::althea::database::pools::SWAP_PREFIX;
+    use crate::althea::database::save_latest_searched_block;
+    use crate::althea::database::schema::save_schema_version;
+
+    let dir = std::env::temp_dir().join(format!("althea-link-snapshot-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("source");
+    let restored = dir.join("restored");
+    let archive = dir.join("snapshot.tar.gz");
+    let chain_id: Uint256 = 258432u64.into();
+    let dex = Address::default();
+
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    options.create_missing_column_families(true);
+    let memory = DbMemory::new(64);
+    {
+        let db = DB::open_cf_descriptors(&options, &source, family_descriptors(&memory)).unwrap();
+        save_latest_searched_block(&db, 1234u64.into());
+        save_schema_version(&db, SCHEMA_VERSION);
+        db.put_cf(cf(&db, SWAP_PREFIX), b"swap_test", [7]).unwrap();
+
+        let metadata = export_snapshot(&db, chain_id, dex, &archive).unwrap();
+        assert_eq!(metadata.latest_searched_block, 1234u64.into());
+        assert_eq!(metadata.schema_version, SCHEMA_VERSION);
+
+        // Exporting from a running node replaces the archive, leaving no partial one behind
+        replace_with_snapshot(&db, chain_id, dex, &archive).unwrap();
+        assert!(!dir.join("snapshot.tar.gz.partial").exists());
+    }
+
+    // Another chain's snapshot is refused and leaves nothing behind
+    assert!(restore_snapshot(&archive, &restored, 1u64.into(), dex).is_err());
+    assert!(!restored.exists());
+
+    let metadata = restore_snapshot(&archive, &restored, chain_id, dex).unwrap();
+    assert_eq!(metadata.latest_searched_block, 1234u64.into());
+    {
+        let db = DB::open_cf_descriptors(&options, &restored, family_descriptors(&memory)).unwrap();
+        assert_eq!(get_latest_searched_block(&db), Some(1234u64.into()));
+        assert_eq!(
+            db.get_cf(cf(&db, SWAP_PREFIX), b"swap_test").unwrap(),
+            Some(vec![7])
+        );
+    }
+    // An existing database is never overwritten
+    assert!(restore_snapshot(&archive, &restored, chain_id, dex).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
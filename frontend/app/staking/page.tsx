@@ -278,6 +278,11 @@ export default function StakingPage() {
       }, 0)
     : 0;
 
+  // rewards can be paid in several denoms, only the staking denom is shown
+  const stakingRewards = userStaking?.rewards?.total?.find(
+    (coin) => coin.denom === "aalthea"
+  );
+
   const handlePageClick = (index: number) => {
     setCurrentPage(index);
   };
@@ -705,11 +710,8 @@ export default function StakingPage() {
                   <div style={{ margin: "0 4px 0 4px" }}>
                     <Text font="macan" size={isMobile ? "title" : "x-lg"}>
                       {displayAmount(
-                        userStaking.rewards?.total[0]?.amount &&
-                          !isNaN(Number(userStaking.rewards?.total[0]?.amount))
-                          ? userStaking.rewards?.total[0]?.amount
-                          : "0.00",
-                        18,
+                        stakingRewards?.base_amount ?? "0",
+                        stakingRewards?.decimals ?? 18,
                         { precision: 2 }
                       )}
                     </Text>
@@ -1,5 +1,6 @@
 import { Validation } from "@/config/interfaces";
 import {
+  RewardCoin,
   UnbondingDelegation,
   Validator,
   ValidatorWithDelegations,
@@ -15,15 +16,9 @@ export interface StakingHookInputParams {
 export interface DelegationRewards {
   rewards: {
     validator_address: string;
-    reward: {
-      denom: string;
-      amount: string;
-    }[];
-  }[];
-  total: {
-    denom: string;
-    amount: string;
+    reward: RewardCoin[];
   }[];
+  total: RewardCoin[];
 }
 
 export interface StakingHookReturn {
@@ -70,19 +70,26 @@ interface UnbondingDelegationResponse {
 }
 [];
 
+/**
+ * @notice A reward coin, amounts can be fractions of the denom's base unit
+ * @dev amount is only kept for older clients, use base_amount or display_amount
+ */
+export interface RewardCoin {
+  denom: string;
+  amount: string;
+  base_amount: string;
+  display_denom: string | null;
+  decimals: number | null;
+  display_amount: string | null;
+}
+
 /**
  * @notice Response type for querying user rewards
  */
 interface DelegationRewardResponse {
   rewards: {
     validator_address: string;
-    reward: {
-      denom: string;
-      amount: string;
-    }[];
-  }[];
-  total: {
-    denom: string;
-    amount: string;
+    reward: RewardCoin[];
   }[];
+  total: RewardCoin[];
 }